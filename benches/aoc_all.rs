@@ -3,12 +3,27 @@ fn main() {
     divan::main();
 }
 
+#[path = "../src/cancel/mod.rs"]
+mod cancel;
+
+#[path = "../src/parallel/mod.rs"]
+mod parallel;
+
+#[path = "../src/parse/mod.rs"]
+mod parse;
+
+#[path = "../src/progress/mod.rs"]
+mod progress;
+
+#[path = "../src/render/mod.rs"]
+mod render;
+
 #[path = "../src/day1/mod.rs"]
 mod day1;
 
 #[divan::bench(max_time = 1)]
 fn day1() {
-    day1::part2(divan::black_box(include_str!("../src/day1/input.txt")));
+    day1::part2(divan::black_box(include_str!("../src/day1/input.txt"))).unwrap();
 }
 
 #[path = "../src/day2/mod.rs"]
@@ -32,7 +47,7 @@ mod day4;
 
 #[divan::bench(max_time = 1)]
 fn day4() {
-    day4::part2(divan::black_box(include_str!("../src/day4/input.txt")));
+    day4::part2(divan::black_box(include_str!("../src/day4/input.txt"))).unwrap();
 }
 
 #[path = "../src/day5/mod.rs"]
@@ -40,7 +55,7 @@ mod day5;
 
 #[divan::bench(max_time = 1)]
 fn day5() {
-    day5::part2(divan::black_box(include_str!("../src/day5/input.txt")));
+    day5::part2(divan::black_box(include_str!("../src/day5/input.txt"))).unwrap();
 }
 
 #[path = "../src/day6/mod.rs"]
@@ -64,7 +79,7 @@ mod day8;
 
 #[divan::bench(max_time = 1)]
 fn day8() {
-    day8::part2(divan::black_box(include_str!("../src/day8/input.txt")));
+    day8::part2(divan::black_box(include_str!("../src/day8/input.txt"))).unwrap();
 }
 
 #[path = "../src/day9/mod.rs"]
@@ -208,3 +223,29 @@ mod day25;
 fn day25() {
     day25::part1(divan::black_box(include_str!("../src/day25/input.txt")));
 }
+
+#[path = "../src/generators/mod.rs"]
+mod generators;
+
+fn generated(generator: generators::Generator, size: usize) -> String {
+    use rand::SeedableRng;
+    generator(&mut rand::rngs::StdRng::seed_from_u64(0), size)
+}
+
+#[divan::bench(max_time = 1, consts = [10, 100, 1000])]
+fn day19_generated<const SIZE: usize>(bencher: divan::Bencher) {
+    let input = generated(generators::day19, SIZE);
+    bencher.bench(|| day19::part2(divan::black_box(&input)));
+}
+
+#[divan::bench(max_time = 1, consts = [10, 30, 100])]
+fn day10_generated<const SIZE: usize>(bencher: divan::Bencher) {
+    let input = generated(generators::day10, SIZE);
+    bencher.bench(|| day10::part2(divan::black_box(&input)));
+}
+
+#[divan::bench(max_time = 1, consts = [100, 300, 1000])]
+fn day22_generated<const SIZE: usize>(bencher: divan::Bencher) {
+    let input = generated(generators::day22, SIZE);
+    bencher.bench(|| day22::part2(divan::black_box(&input)));
+}
@@ -0,0 +1,132 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/cancel/mod.rs"]
+mod cancel;
+
+#[path = "../../src/parallel/mod.rs"]
+mod parallel;
+
+#[path = "../../src/parse/mod.rs"]
+mod parse;
+
+#[path = "../../src/progress/mod.rs"]
+mod progress;
+
+#[path = "../../src/render/mod.rs"]
+mod render;
+
+#[path = "../../src/day1/mod.rs"]
+mod day1;
+
+#[path = "../../src/day2/mod.rs"]
+mod day2;
+
+#[path = "../../src/day3/mod.rs"]
+mod day3;
+
+#[path = "../../src/day4/mod.rs"]
+mod day4;
+
+#[path = "../../src/day5/mod.rs"]
+mod day5;
+
+#[path = "../../src/day6/mod.rs"]
+mod day6;
+
+#[path = "../../src/day7/mod.rs"]
+mod day7;
+
+#[path = "../../src/day8/mod.rs"]
+mod day8;
+
+#[path = "../../src/day9/mod.rs"]
+mod day9;
+
+#[path = "../../src/day10/mod.rs"]
+mod day10;
+
+#[path = "../../src/day11/mod.rs"]
+mod day11;
+
+#[path = "../../src/day12/mod.rs"]
+mod day12;
+
+#[path = "../../src/day13/mod.rs"]
+mod day13;
+
+#[path = "../../src/day14/mod.rs"]
+mod day14;
+
+#[path = "../../src/day15/mod.rs"]
+mod day15;
+
+#[path = "../../src/day16/mod.rs"]
+mod day16;
+
+#[path = "../../src/day17/mod.rs"]
+mod day17;
+
+#[path = "../../src/day18/mod.rs"]
+mod day18;
+
+#[path = "../../src/day19/mod.rs"]
+mod day19;
+
+#[path = "../../src/day20/mod.rs"]
+mod day20;
+
+#[path = "../../src/day21/mod.rs"]
+mod day21;
+
+#[path = "../../src/day22/mod.rs"]
+mod day22;
+
+#[path = "../../src/day23/mod.rs"]
+mod day23;
+
+#[path = "../../src/day24/mod.rs"]
+mod day24;
+
+#[path = "../../src/day25/mod.rs"]
+mod day25;
+
+/// Every day's parser, indexed by the first byte of the fuzz input
+const PARSERS: [fn(&str); 25] = [
+    day1::fuzz,
+    day2::fuzz,
+    day3::fuzz,
+    day4::fuzz,
+    day5::fuzz,
+    day6::fuzz,
+    day7::fuzz,
+    day8::fuzz,
+    day9::fuzz,
+    day10::fuzz,
+    day11::fuzz,
+    day12::fuzz,
+    day13::fuzz,
+    day14::fuzz,
+    day15::fuzz,
+    day16::fuzz,
+    day17::fuzz,
+    day18::fuzz,
+    day19::fuzz,
+    day20::fuzz,
+    day21::fuzz,
+    day22::fuzz,
+    day23::fuzz,
+    day24::fuzz,
+    day25::fuzz,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((day, input)) = data.split_first() else {
+        return;
+    };
+
+    if let Ok(input) = std::str::from_utf8(input) {
+        PARSERS[*day as usize % PARSERS.len()](input);
+    }
+});
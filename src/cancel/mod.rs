@@ -0,0 +1,106 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Stops a long running solver once it is cancelled or its time budget runs out.
+/// Solvers call [`Token::check`] in their loops, clones share the cancellation
+#[derive(Debug, Clone)]
+pub struct Token {
+    cancelled: Arc<AtomicBool>,
+    started: Instant,
+    deadline: Option<Instant>,
+}
+
+impl Default for Token {
+    fn default() -> Token {
+        Token::new()
+    }
+}
+
+impl Token {
+    /// A token that only stops when cancelled
+    pub fn new() -> Token {
+        Token {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            started: Instant::now(),
+            deadline: None,
+        }
+    }
+
+    /// Also stops once `budget` has passed since the token was made
+    pub fn with_budget(self, budget: Duration) -> Token {
+        Token {
+            deadline: Some(self.started + budget),
+            ..self
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// Fails once cancelled, `partial` describes how far the solver got
+    pub fn check(&self, partial: impl FnOnce() -> String) -> Result<(), TimedOut> {
+        if self.is_cancelled() {
+            return Err(TimedOut {
+                after: self.started.elapsed(),
+                partial: partial(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A solver that stopped before it found the answer
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedOut {
+    pub after: Duration,
+    pub partial: String,
+}
+
+impl Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timed out after {:.2?}, {}", self.after, self.partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_without_a_budget_only_stop_when_cancelled() {
+        let token = Token::new();
+        assert_eq!(token.check(|| "Nothing".to_string()), Ok(()));
+
+        token.clone().cancel();
+        let timed_out = token.check(|| "3 of 4 steps".to_string()).unwrap_err();
+        assert_eq!(timed_out.partial, "3 of 4 steps");
+        assert!(timed_out.to_string().ends_with(", 3 of 4 steps"));
+    }
+
+    #[test]
+    fn budgets_run_out() {
+        let token = Token::new().with_budget(Duration::from_millis(20));
+        assert!(!token.is_cancelled());
+
+        std::thread::sleep(Duration::from_millis(30));
+        let timed_out = token.check(String::new).unwrap_err();
+        assert!(timed_out.after >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn partial_progress_is_only_described_on_expiry() {
+        let token = Token::new();
+        assert!(token.check(|| panic!("Shouldn't be described")).is_ok());
+    }
+}
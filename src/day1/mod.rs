@@ -1,54 +1,327 @@
-pub fn part1(input: &str) -> u32 {
-    let result: u32 = input
-        .lines()
-        .map(|line| {
-            let numbers = line
-                .chars()
-                .filter(|char| char.is_ascii_digit())
-                .collect::<Vec<_>>();
-            let number = format!("{}{}", numbers.first().unwrap(), numbers.last().unwrap());
-            // println!("number: {number}");
-            return number.parse::<u32>().unwrap();
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use aho_corasick::{AhoCorasick, Anchored, Input, StartKind};
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+/// The spelled out digits of part 2
+pub const ENGLISH: [(&str, u32); 9] = [
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+];
+
+/// A digit found in a line, `start..end` are its byte offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Digit {
+    pub value: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Finds the numerals `0` to `9` and the words of a vocabulary in a single pass over a line
+pub struct Scanner {
+    automaton: AhoCorasick,
+    values: Vec<u32>,
+}
+
+impl Scanner {
+    /// Matches every word of `vocabulary` as the value it stands for, next to the numerals
+    pub fn new(vocabulary: &[(&str, u32)]) -> Result<Scanner, String> {
+        if let Some((_, value)) = vocabulary.iter().find(|(word, _)| word.is_empty()) {
+            return Err(format!("Empty word for {value} in the vocabulary"));
+        }
+
+        let numerals = (0..=9).map(|n| (n.to_string(), n));
+        let words = vocabulary.iter().map(|(w, v)| (w.to_string(), *v));
+        let (patterns, values): (Vec<_>, Vec<_>) = numerals.chain(words).unzip();
+
+        // Both so the first and last digit can be looked for at a given offset
+        let automaton = AhoCorasick::builder()
+            .start_kind(StartKind::Both)
+            .build(patterns)
+            .map_err(|e| format!("Invalid vocabulary: {e}"))?;
+
+        Ok(Scanner { automaton, values })
+    }
+
+    pub fn numerals() -> Scanner {
+        Scanner::new(&[]).unwrap()
+    }
+
+    pub fn english() -> Scanner {
+        Scanner::new(&ENGLISH).unwrap()
+    }
+
+    /// Every digit in `line` in the order they end, overlapping ones too like `two` and `one`
+    /// in `twone`
+    pub fn digits<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Digit> + 'a {
+        self.automaton.find_overlapping_iter(line).map(|m| Digit {
+            value: self.values[m.pattern()],
+            start: m.start(),
+            end: m.end(),
         })
-        .sum();
-    return result;
-}
-
-pub fn part2(input: &str) -> u32 {
-    let result: u32 = input
-        .lines()
-        .map(|line| {
-            let numbers = line
-                .replace("one", "o1ne")
-                .replace("two", "t2wo")
-                .replace("three", "th3ree")
-                .replace("four", "fo4ur")
-                .replace("five", "fi5ve")
-                .replace("six", "s6ix")
-                .replace("seven", "se7ven")
-                .replace("eight", "ei8ght")
-                .replace("nine", "ni9ne")
-                .chars()
-                .filter(|char| char.is_ascii_digit())
-                .collect::<Vec<_>>();
-            let number = format!("{}{}", numbers.first().unwrap(), numbers.last().unwrap());
-            // println!("number: {number}");
-            return number.parse::<u32>().unwrap();
+    }
+
+    /// The shortest digit starting at byte `start`
+    fn digit_at(&self, line: &str, start: usize) -> Option<Digit> {
+        let input = Input::new(line)
+            .span(start..line.len())
+            .anchored(Anchored::Yes);
+        let m = self.automaton.find(input)?;
+
+        Some(Digit {
+            value: self.values[m.pattern()],
+            start: m.start(),
+            end: m.end(),
         })
-        .sum();
+    }
+
+    /// The digit starting first, stopping as soon as it's found
+    pub fn first(&self, line: &str) -> Option<Digit> {
+        (0..line.len()).find_map(|i| self.digit_at(line, i))
+    }
+
+    /// The digit starting last, looking from the end of the line backwards
+    pub fn last(&self, line: &str) -> Option<Digit> {
+        (0..line.len()).rev().find_map(|i| self.digit_at(line, i))
+    }
 
-    return result;
+    /// The first and last digit of the line as a two digit number
+    pub fn calibration(&self, line: &str) -> Option<u64> {
+        let first = self.first(line)?;
+        let last = self.last(line)?;
+
+        Some(u64::from(first.value) * 10 + u64::from(last.value))
+    }
+}
+
+/// A line that was skipped, `line` counts from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The sum of the calibration values of a document, and the lines that had none
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Calibration {
+    pub sum: u64,
+    pub lines: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Why a document has no calibration sum, lines count from 1
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Lines without a calibration value, when every line needs one
+    Uncalibrated(Vec<Diagnostic>),
+    /// The sum no longer fits a `u64` once `line` is added
+    Overflow {
+        line: usize,
+    },
+    Unreadable {
+        line: usize,
+        message: String,
+    },
+    TimedOut(TimedOut),
+}
+
+impl From<TimedOut> for Error {
+    fn from(timed_out: TimedOut) -> Error {
+        Error::TimedOut(timed_out)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Uncalibrated(diagnostics) => {
+                let first = &diagnostics[0];
+                write!(f, "Line {}: {}", first.line, first.message)?;
+                if diagnostics.len() > 1 {
+                    write!(f, ", and {} more lines", diagnostics.len() - 1)?;
+                }
+                Ok(())
+            }
+            Error::Overflow { line } => write!(f, "Calibration sum overflows u64 on line {line}"),
+            Error::Unreadable { line, message } => {
+                write!(f, "Couldn't read line {line}: {message}")
+            }
+            Error::TimedOut(timed_out) => write!(f, "{timed_out}"),
+        }
+    }
+}
+
+/// Sums the calibration values of `reader` one line at a time, so the document never has to fit
+/// in memory. Lines without a digit or that aren't UTF-8 become diagnostics
+pub fn calibrate(reader: impl BufRead, scanner: &Scanner) -> Result<Calibration, Error> {
+    return calibrate_with_progress(reader, scanner, &mut (), &Token::new());
+}
+
+/// Reports every line calibrated, or stops between lines when `cancel` does
+fn calibrate_with_progress(
+    mut reader: impl BufRead,
+    scanner: &Scanner,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<Calibration, Error> {
+    let mut calibration = Calibration::default();
+    let mut buffer = Vec::new();
+
+    progress.start("Calibration lines", None);
+    loop {
+        cancel.check(|| format!("{} lines calibrated", calibration.lines))?;
+        buffer.clear();
+        let read = reader
+            .read_until(b'\n', &mut buffer)
+            .map_err(|e| Error::Unreadable {
+                line: calibration.lines + 1,
+                message: e.to_string(),
+            })?;
+        if read == 0 {
+            break;
+        }
+        calibration.lines += 1;
+        progress.advance(1);
+
+        let line = match std::str::from_utf8(&buffer) {
+            Ok(line) => line.trim_end_matches(['\n', '\r']),
+            Err(e) => {
+                calibration.diagnostics.push(Diagnostic {
+                    line: calibration.lines,
+                    message: format!("Line isn't UTF-8: {e}"),
+                });
+                continue;
+            }
+        };
+
+        match scanner.calibration(line) {
+            Some(value) => {
+                calibration.sum = calibration.sum.checked_add(value).ok_or(Error::Overflow {
+                    line: calibration.lines,
+                })?
+            }
+            None => calibration.diagnostics.push(Diagnostic {
+                line: calibration.lines,
+                message: format!("No digit in calibration line `{line}`"),
+            }),
+        }
+    }
+    progress.finish();
+
+    return Ok(calibration);
+}
+
+/// Calibrates the document at `path`, or stdin for `-`
+pub fn calibrate_file(path: &str, scanner: &Scanner) -> Result<Calibration, String> {
+    if path == "-" {
+        return calibrate(io::stdin().lock(), scanner).map_err(|e| e.to_string());
+    }
+
+    let file = File::open(path).map_err(|e| format!("Couldn't open `{path}`: {e}"))?;
+    return calibrate(BufReader::new(file), scanner).map_err(|e| e.to_string());
+}
+
+/// The calibration value of every line, optionally counting spelled out digits
+pub fn parse(input: &str, spelled: bool) -> Result<Vec<u64>, ParseError> {
+    let scanner = match spelled {
+        true => Scanner::english(),
+        false => Scanner::numerals(),
+    };
+
+    parse::lines(input, |line| {
+        scanner
+            .calibration(line)
+            .ok_or(ParseErrorKind::Missing("a digit"))
+    })
+}
+
+/// Sums the calibration values of every line, failing on the lines that have none
+fn calibrate_lines(
+    input: &str,
+    scanner: &Scanner,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, Error> {
+    let calibration = calibrate_with_progress(input.as_bytes(), scanner, progress, cancel)?;
+    if !calibration.diagnostics.is_empty() {
+        return Err(Error::Uncalibrated(calibration.diagnostics));
+    }
+
+    return Ok(calibration.sum);
+}
+
+pub fn part1(input: &str) -> Result<u64, Error> {
+    part1_with_progress(input, &mut (), &Token::new())
+}
+
+/// Reports every line calibrated, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, Error> {
+    return calibrate_lines(input, &Scanner::numerals(), progress, cancel);
+}
+
+pub fn part2(input: &str) -> Result<u64, Error> {
+    part2_with_progress(input, &mut (), &Token::new())
+}
+
+/// Reports every line calibrated, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, Error> {
+    return calibrate_lines(input, &Scanner::english(), progress, cancel);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    for spelled in [false, true] {
+        let strict = parse(input, spelled);
+        let scanner = match spelled {
+            true => Scanner::english(),
+            false => Scanner::numerals(),
+        };
+        let streamed = calibrate(input.as_bytes(), &scanner).unwrap();
+        match strict {
+            Ok(values) => {
+                assert_eq!(streamed.sum, values.iter().sum());
+                assert!(streamed.diagnostics.is_empty());
+            }
+            Err(error) => assert_eq!(streamed.diagnostics[0].line, error.line),
+        }
+    }
 }
 
 pub fn process(input: String) {
-    let result = part2(&input);
+    let result = calibrate(input.as_bytes(), &Scanner::english()).unwrap();
+    for diagnostic in &result.diagnostics {
+        eprintln!("Line {}: {}", diagnostic.line, diagnostic.message);
+    }
 
-    println!("Result: {result}");
+    println!("Result: {}", result.sum);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -57,14 +330,14 @@ pqr3stu8vwx
 a1b2c3d4e5f
 treb7uchet";
         let result = part1(input);
-        assert_eq!(result, 142);
+        assert_eq!(result, Ok(142));
     }
 
     #[test]
     fn part1_input() {
         let input = include_str!("input.txt");
         let result = part1(input);
-        assert_eq!(result, 56042);
+        assert_eq!(result, Ok(56042));
     }
 
     #[test]
@@ -77,13 +350,146 @@ xtwone3four
 zoneight234
 7pqrstsixteen";
         let result = part2(input);
-        assert_eq!(result, 281);
+        assert_eq!(result, Ok(281));
     }
 
     #[test]
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
-        assert_eq!(result, 55358);
+        assert_eq!(result, Ok(55358));
+    }
+
+    #[test]
+    fn parse_rejects_lines_without_digits() {
+        assert_eq!(
+            parse("abc", false),
+            Err(ParseErrorKind::Missing("a digit").at(1))
+        );
+        assert_eq!(
+            parse("1abc\nthree", false),
+            Err(ParseErrorKind::Missing("a digit").at(2))
+        );
+        assert_eq!(parse("1abc\nthree", true), Ok(vec![11, 33]));
+    }
+
+    #[test]
+    fn parts_fail_on_lines_without_digits() {
+        let error = part1("1abc2\ntwo\nthree").unwrap_err();
+        assert_eq!(
+            error,
+            Error::Uncalibrated(vec![
+                Diagnostic {
+                    line: 2,
+                    message: "No digit in calibration line `two`".to_string()
+                },
+                Diagnostic {
+                    line: 3,
+                    message: "No digit in calibration line `three`".to_string()
+                },
+            ])
+        );
+        assert_eq!(
+            error.to_string(),
+            "Line 2: No digit in calibration line `two`, and 1 more lines"
+        );
+        assert_eq!(part2("1abc2\ntwo\nthree"), Ok(12 + 22 + 33));
+    }
+
+    #[test]
+    fn calibrate_reports_lines_without_digits() {
+        let input: &[u8] = b"1abc2\nnothing\r\ntwo\n\xff3\n";
+        let result = calibrate(input, &Scanner::numerals()).unwrap();
+
+        assert_eq!(result.sum, 12);
+        assert_eq!(result.lines, 4);
+        assert_eq!(result.diagnostics[2].line, 4);
+        assert!(result.diagnostics[2]
+            .message
+            .starts_with("Line isn't UTF-8"));
+        assert_eq!(
+            result.diagnostics[..2],
+            [
+                Diagnostic {
+                    line: 2,
+                    message: "No digit in calibration line `nothing`".to_string()
+                },
+                Diagnostic {
+                    line: 3,
+                    message: "No digit in calibration line `two`".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn calibrate_reads_in_small_chunks() {
+        let input = include_str!("input.txt");
+        let reader = BufReader::with_capacity(3, input.as_bytes());
+        let result = calibrate(reader, &Scanner::english()).unwrap();
+
+        assert_eq!(result.sum, 55358);
+        assert_eq!(result.lines, input.lines().count());
+        assert!(result.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn digits_overlap() {
+        let scanner = Scanner::english();
+        let digits = scanner.digits("xtwone3").collect::<Vec<_>>();
+
+        assert_eq!(
+            digits,
+            [
+                Digit {
+                    value: 2,
+                    start: 1,
+                    end: 4
+                },
+                Digit {
+                    value: 1,
+                    start: 3,
+                    end: 6
+                },
+                Digit {
+                    value: 3,
+                    start: 6,
+                    end: 7
+                },
+            ]
+        );
+        assert_eq!(scanner.first("xtwone").map(|d| d.value), Some(2));
+        assert_eq!(scanner.last("xtwone").map(|d| d.value), Some(1));
+        assert_eq!(Scanner::numerals().calibration("xtwone"), None);
+    }
+
+    #[test]
+    fn vocabularies_are_pluggable() {
+        let german = Scanner::new(&[("eins", 1), ("zwei", 2), ("fünf", 5), ("vier", 4)]).unwrap();
+        assert_eq!(german.calibration("fünfundvierzig"), Some(54));
+        assert_eq!(german.first("zweieins").unwrap().end, 4);
+
+        let decimal = Scanner::new(&[("zero", 0), ("ten", 10)]).unwrap();
+        assert_eq!(decimal.calibration("zero7"), Some(7));
+        assert_eq!(decimal.calibration("3often"), Some(40));
+
+        assert!(Scanner::new(&[("", 0)]).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn first_and_last_are_the_outer_digits(line in "[a-z1-9]{0,30}") {
+            let scanner = Scanner::english();
+            let digits = scanner.digits(&line).collect::<Vec<_>>();
+
+            prop_assert_eq!(scanner.first(&line), digits.iter().min_by_key(|d| d.start).copied());
+            prop_assert_eq!(scanner.last(&line), digits.iter().max_by_key(|d| d.start).copied());
+        }
+
+        #[test]
+        fn parse_finds_outer_digits(line in "[a-z]*[1-9][a-z1-9]*", spelled: bool) {
+            let values = parse(&line, spelled).unwrap();
+            prop_assert!((11..=99).contains(&values[0]));
+        }
     }
 }
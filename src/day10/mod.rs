@@ -1,5 +1,10 @@
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+use crate::render::{Image, Palette};
 use itertools::Itertools;
 use std::collections::HashSet;
+use tracing::{debug, instrument};
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 enum Direction {
@@ -45,10 +50,10 @@ enum Pipe {
 }
 
 impl Pipe {
-    pub fn parse(input: char) -> Pipe {
+    pub fn parse(input: char) -> Result<Pipe, ParseErrorKind> {
         use Pipe::*;
 
-        match input {
+        Ok(match input {
             '|' => NorthSouth,
             '-' => EastWest,
             'L' => NorthEast,
@@ -57,8 +62,8 @@ impl Pipe {
             'F' => SouthEast,
             '.' => Ground,
             'S' => Start,
-            p => panic!("Found an unexpected pipe shape {p}"),
-        }
+            p => return Err(ParseErrorKind::UnexpectedChar(p)),
+        })
     }
 
     fn inlets(&self) -> Vec<Direction> {
@@ -117,13 +122,22 @@ struct PipeMaze {
 }
 
 impl PipeMaze {
-    fn parse(input: &str) -> PipeMaze {
-        let pipes = input
-            .lines()
-            .map(|line| line.chars().map(Pipe::parse).collect_vec())
-            .collect_vec();
+    fn parse(input: &str) -> Result<PipeMaze, ParseError> {
+        let pipes: Vec<Vec<Pipe>> =
+            parse::lines(input, |line| line.chars().map(Pipe::parse).collect())?;
+
+        let mut starts = pipes
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().filter(|p| **p == Pipe::Start).map(move |_| y));
+        if starts.next().is_none() {
+            return Err(ParseErrorKind::Missing("a start `S`").at(pipes.len() + 1));
+        }
+        if let Some(y) = starts.next() {
+            return Err(ParseErrorKind::Duplicate("S".to_string()).at(y + 1));
+        }
 
-        PipeMaze { pipes }
+        Ok(PipeMaze { pipes })
     }
 
     fn get_at(&self, coords: (usize, usize)) -> &Pipe {
@@ -146,7 +160,41 @@ impl PipeMaze {
             .unwrap()
     }
 
-    fn walk_circuit(&self) -> HashSet<(usize, usize)> {
+    /// The pipe hidden under the start, joining the two neighbours that connect back to it
+    fn start_shape(&self) -> Pipe {
+        use Direction::*;
+        use Pipe::*;
+
+        let start = self.start();
+        let connected = |direction: Direction| {
+            let off_the_edge = match direction {
+                North => start.1 == 0,
+                West => start.0 == 0,
+                _ => false,
+            };
+            !off_the_edge
+                && self
+                    .get_at(direction.translate(&start))
+                    .inlets()
+                    .contains(&direction.reverse())
+        };
+
+        match [North, South, East, West].map(connected) {
+            [true, true, _, _] => NorthSouth,
+            [true, _, true, _] => NorthEast,
+            [true, _, _, true] => NorthWest,
+            [_, true, _, true] => SouthWest,
+            [_, true, true, _] => SouthEast,
+            _ => EastWest,
+        }
+    }
+
+    /// Follows the loop from the start, reporting every pipe, or stopping when `cancel` does
+    fn walk_circuit(
+        &self,
+        progress: &mut dyn Progress,
+        cancel: &Token,
+    ) -> Result<HashSet<(usize, usize)>, TimedOut> {
         use Direction::*;
 
         let start = self.start();
@@ -168,43 +216,60 @@ impl PipeMaze {
 
         path.insert(node);
 
+        progress.start("Loop pipes", None);
         while node != start {
+            cancel.check(|| format!("{} pipes into the loop", path.len()))?;
             let pipe = self.get_at(node);
 
             node_direction = pipe.traverse(node_direction);
             node = node_direction.translate(&node);
 
             path.insert(node);
+            progress.advance(1);
         }
+        progress.finish();
 
-        return path;
+        return Ok(path);
     }
 
-    fn points_inside(&self, path: &HashSet<(usize, usize)>) -> HashSet<(usize, usize)> {
+    /// The tiles enclosed by `path`, reporting every row, or stopping when `cancel` does
+    fn points_inside(
+        &self,
+        path: &HashSet<(usize, usize)>,
+        progress: &mut dyn Progress,
+        cancel: &Token,
+    ) -> Result<HashSet<(usize, usize)>, TimedOut> {
         use Pipe::*;
-        
-        self.pipes
-            .iter()
-            .enumerate()
-            .flat_map(|(y, line)| {
-                line.iter().enumerate().filter_map(move |(x, _)| {
-                    if !path.contains(&(x, y)) {
-                        let crossed_pipes = (0..x)
-                            .filter(|x| path.contains(&(*x, y)))
-                            .filter(|x| match self.get_at((*x, y)) {
-                                NorthSouth | NorthWest | NorthEast | Start => true,
-                                _ => false,
-                            })
-                            .count();
-
-                        if crossed_pipes % 2 == 1 {
-                            return Some((x, y));
-                        }
+
+        let start_shape = self.start_shape();
+        let mut inside = HashSet::new();
+        progress.start("Rows", Some(self.pipes.len() as u64));
+        for (y, line) in self.pipes.iter().enumerate() {
+            cancel.check(|| format!("{y} of {} rows searched", self.pipes.len()))?;
+            inside.extend(line.iter().enumerate().filter_map(|(x, _)| {
+                if !path.contains(&(x, y)) {
+                    let crossed_pipes = (0..x)
+                        .filter(|x| path.contains(&(*x, y)))
+                        .filter(|x| {
+                            let pipe = match self.get_at((*x, y)) {
+                                Start => &start_shape,
+                                pipe => pipe,
+                            };
+                            matches!(pipe, NorthSouth | NorthWest | NorthEast)
+                        })
+                        .count();
+
+                    if crossed_pipes % 2 == 1 {
+                        return Some((x, y));
                     }
-                    return None;
-                })
-            })
-            .collect::<HashSet<_>>()
+                }
+                return None;
+            }));
+            progress.advance(1);
+        }
+        progress.finish();
+
+        return Ok(inside);
     }
 
     fn to_string(
@@ -235,23 +300,95 @@ impl PipeMaze {
     }
 }
 
+#[instrument(skip_all)]
 pub fn part1(input: &str) -> usize {
-    let maze = PipeMaze::parse(&input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every pipe of the loop, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let maze = PipeMaze::parse(input).unwrap();
 
-    let path = maze.walk_circuit();
+    let path = maze.walk_circuit(progress, cancel)?;
 
-    return path.len() / 2;
+    return Ok(path.len() / 2);
 }
 
+#[instrument(skip_all)]
 pub fn part2(input: &str) -> usize {
-    let maze = PipeMaze::parse(input);
-    let path = maze.walk_circuit();
-    let highlights = maze.points_inside(&path);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let display = maze.to_string(&path, &highlights);
-    // println!("{}", display);
+/// Reports every pipe of the loop and then every row searched for enclosed tiles, or stops
+/// when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let maze = PipeMaze::parse(input).unwrap();
+    let path = maze.walk_circuit(progress, cancel)?;
+    let highlights = maze.points_inside(&path, progress, cancel)?;
 
-    return highlights.len();
+    debug!("Loop:\n{}", maze.to_string(&path, &highlights));
+
+    return Ok(highlights.len());
+}
+
+/// The loop drawn with box characters, with dots on the tiles it encloses
+pub fn ascii(input: &str) -> String {
+    let maze = PipeMaze::parse(input).unwrap();
+    let path = maze.walk_circuit(&mut (), &Token::new()).unwrap();
+    let inside = maze.points_inside(&path, &mut (), &Token::new()).unwrap();
+
+    return maze.to_string(&path, &inside);
+}
+
+/// Draws the maze three pixels per tile so the shape of every pipe shows
+pub fn render(input: &str, palette: &Palette) -> Image {
+    let maze = PipeMaze::parse(input).unwrap();
+    let path = maze.walk_circuit(&mut (), &Token::new()).unwrap();
+    let inside = maze.points_inside(&path, &mut (), &Token::new()).unwrap();
+
+    let (width, height) = (maze.pipes[0].len(), maze.pipes.len());
+    let mut image = Image::new(width * 3, height * 3, palette.background);
+
+    for (y, row) in maze.pipes.iter().enumerate() {
+        for (x, pipe) in row.iter().enumerate() {
+            let tile = (0..3).cartesian_product(0..3);
+
+            if inside.contains(&(x, y)) {
+                let cells = tile.map(|(dx, dy)| (x * 3 + dx, y * 3 + dy));
+                image.overlay(cells, palette.highlight);
+            } else if *pipe != Pipe::Ground {
+                let color = match path.contains(&(x, y)) {
+                    true => palette.path,
+                    false => palette.foreground,
+                };
+
+                // The center of the tile and the sides the pipe connects to
+                let arms = pipe
+                    .inlets()
+                    .iter()
+                    .map(|d| d.translate(&(1, 1)))
+                    .collect_vec();
+                for (dx, dy) in arms.into_iter().chain([(1, 1)]) {
+                    image.set(x * 3 + dx, y * 3 + dy, color);
+                }
+            }
+        }
+    }
+
+    return image;
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = PipeMaze::parse(input);
 }
 
 pub fn process(input: String) {
@@ -262,6 +399,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -318,4 +456,55 @@ L--J.L7...LJS7F-7L7.
         let result = part2(input);
         assert_eq!(result, 8);
     }
+
+    #[test]
+    fn render_draws_the_loop() {
+        let palette = Palette::default();
+        let image = render(
+            "..F7.
+.FJ|.
+SJ.L7
+|F--J
+LJ...",
+            &palette,
+        );
+
+        assert_eq!((image.width(), image.height()), (15, 15));
+        // The start connects east and south, the tile east of it connects north and west
+        assert_eq!(image.get(1, 7), Some(palette.path));
+        assert_eq!(image.get(2, 7), Some(palette.path));
+        assert_eq!(image.get(4, 6), Some(palette.path));
+        assert_eq!(image.get(4, 8), Some(palette.background));
+        assert_eq!(image.get(1, 1), Some(palette.background));
+    }
+
+    #[test]
+    fn ascii_draws_the_loop() {
+        let text = ascii(
+            ".....
+.F-7.
+.|.|.
+.S-J.
+.....",
+        );
+
+        assert_eq!(text, "     \n ╔═╗ \n ║.║ \n ╬═╝ \n     ");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_mazes() {
+        use ParseErrorKind::*;
+
+        let error = |input| PipeMaze::parse(input).err();
+        assert_eq!(error(".F7\n.LJ"), Some(Missing("a start `S`").at(3)));
+        assert_eq!(error("SF7\n.LS"), Some(Duplicate("S".to_string()).at(2)));
+        assert_eq!(error("SF7\n.LX"), Some(UnexpectedChar('X').at(2)));
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_mazes(input in "([|\\-LJ7F.S]{0,8}\n){0,8}") {
+            let _ = PipeMaze::parse(&input);
+        }
+    }
 }
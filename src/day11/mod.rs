@@ -1,27 +1,54 @@
 use std::collections::HashSet;
 
 use itertools::Itertools;
+use tracing::{debug, instrument, trace};
 
-pub fn parse(input: &str) -> HashSet<(usize, usize)> {
+use crate::cancel::{TimedOut, Token};
+use crate::parallel;
+use crate::parse::{ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+pub fn parse(input: &str) -> Result<HashSet<(usize, usize)>, ParseError> {
     input
         .lines()
         .enumerate()
         .flat_map(|(y, line)| {
             line.chars().enumerate().flat_map(move |(x, c)| match c {
-                '#' => Some((x, y)),
+                '#' => Some(Ok((x, y))),
                 '.' => None,
-                c => panic!("Unexpected char in input {c}"),
+                c => Some(Err(ParseErrorKind::UnexpectedChar(c).at(y + 1))),
             })
         })
         .collect()
 }
 
 pub fn part1(input: &str) -> usize {
-    part2(input, 2)
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every galaxy measured with empty space doubled, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    return part2_with_progress(input, 2, progress, cancel);
 }
 
 pub fn part2(input: &str, factor: usize) -> usize {
-    let galaxies = parse(input);
+    part2_with_progress(input, factor, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every galaxy measured against the ones after it, or stops between galaxies when
+/// `cancel` does
+#[instrument(skip(input, progress, cancel))]
+pub fn part2_with_progress(
+    input: &str,
+    factor: usize,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let galaxies = parse(input).unwrap();
     let dim_y = input.lines().count();
     let dim_x = input.lines().nth(0).unwrap().chars().count();
 
@@ -32,7 +59,7 @@ pub fn part2(input: &str, factor: usize) -> usize {
         .filter(|x| !galaxies.iter().any(|(gx, _)| gx == x))
         .collect_vec();
 
-    // println!("Expansions: {:?} + {:?}", expansions_x, expansions_y);
+    debug!(?expansions_x, ?expansions_y, "Empty columns and rows");
 
     let expanded_galaxies = galaxies
         .iter()
@@ -41,19 +68,38 @@ pub fn part2(input: &str, factor: usize) -> usize {
             let ex = expansions_x.iter().filter(|x| gx > x).count() * (factor - 1);
             (gx + ex, gy + ey)
         })
-        .collect::<HashSet<_>>();
+        .collect_vec();
 
-    let distances = expanded_galaxies
-        .iter()
-        .tuple_combinations::<(_, _)>()
-        .map(|((ax, ay), (bx, by))| {
-            let distance = ax.abs_diff(*bx) + ay.abs_diff(*by);
-            // println!("Distance between {ax},{ay} and {bx},{by} : {distance}");
-            return distance;
+    // Every pair is counted once, from the first of its two galaxies
+    let count = expanded_galaxies.len();
+    progress.start("Galaxies", Some(count as u64));
+    let mut distances = 0;
+    for (i, chunk) in expanded_galaxies.chunks(64).enumerate() {
+        cancel.check(|| format!("{} of {count} galaxies measured", i * 64))?;
+        distances += parallel::map(chunk, |a| {
+            expanded_galaxies
+                .iter()
+                .filter(|b| *b > a)
+                .map(|(bx, by)| {
+                    let (ax, ay) = a;
+                    let distance = ax.abs_diff(*bx) + ay.abs_diff(*by);
+                    trace!("Distance between {ax},{ay} and {bx},{by}: {distance}");
+                    return distance;
+                })
+                .sum::<usize>()
         })
-        .sum();
+        .iter()
+        .sum::<usize>();
+        progress.advance(chunk.len() as u64);
+    }
+    progress.finish();
+
+    return Ok(distances);
+}
 
-    return distances;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = parse(input);
 }
 
 pub fn process(input: String) {
@@ -126,4 +172,13 @@ mod tests {
         let result = part2(input, 1_000_000);
         assert_eq!(result, 791134099634);
     }
+
+    #[test]
+    fn parse_rejects_unknown_cells() {
+        assert_eq!(
+            parse("#.\n.x"),
+            Err(ParseErrorKind::UnexpectedChar('x').at(2))
+        );
+        assert_eq!(parse("#.\n.#"), Ok(HashSet::from([(0, 0), (1, 1)])));
+    }
 }
@@ -1,21 +1,31 @@
 use std::collections::HashMap;
 use itertools::Itertools;
 
-fn parse(input: &str) -> Vec<(&str, Vec<usize>)> {
-    input
-        .lines()
-        .map(|line| {
-            let (chromosome, guards_str) = line.split_once(' ').unwrap();
-
-            let guards = guards_str
-                .split(',')
-                .map(str::parse)
-                .map(Result::unwrap)
-                .collect_vec();
-
-            (chromosome, guards)
-        })
-        .collect_vec()
+use crate::cancel::{TimedOut, Token};
+use crate::parallel;
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+fn parse(input: &str) -> Result<Vec<(&str, Vec<usize>)>, ParseError> {
+    parse::lines(input, |line| {
+        let (chromosome, guards_str) = line
+            .split_once(' ')
+            .ok_or(ParseErrorKind::Missing("springs and groups"))?;
+
+        if let Some(c) = chromosome.chars().find(|c| !".#?".contains(*c)) {
+            return Err(ParseErrorKind::UnexpectedChar(c));
+        }
+
+        let guards = guards_str
+            .split(',')
+            .map(|g| match parse::number(g)? {
+                0 => Err(ParseErrorKind::InvalidNumber(g.to_string())),
+                g => Ok(g),
+            })
+            .collect::<Result<Vec<usize>, ParseErrorKind>>()?;
+
+        Ok((chromosome, guards))
+    })
 }
 
 fn arrangements(
@@ -82,30 +92,69 @@ fn arrangements(
 }
 
 pub fn part1(input: &str) -> usize {
-    let lines = parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let result = lines
+/// Reports every record counted, or stops between records when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let lines = parse(input).unwrap();
+    let records = lines
         .iter()
-        .map(|(row, guards)| 
-            arrangements(&mut HashMap::new(), row.as_bytes(), None, &guards)
-        )
-        .sum();
+        .map(|(row, guards)| (row.to_string(), guards.clone()))
+        .collect_vec();
 
-    return result;
+    return count_arrangements(&records, progress, cancel);
 }
 
 pub fn part2(input: &str) -> usize {
-    let lines = parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every unfolded record counted, or stops between records when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let lines = parse(input).unwrap();
 
-    let result = lines
+    let unfolded = lines
         .iter()
         .map(|(row, guards)| (std::iter::repeat(row).take(5).join("?"), guards.repeat(5)))
-        .map(|(row, guards)| 
-            arrangements(&mut HashMap::new(), row.as_str().as_bytes(), None, &guards)
-        )
-        .sum();
+        .collect_vec();
+
+    return count_arrangements(&unfolded, progress, cancel);
+}
+
+/// Sums the arrangements of every record, reporting them as they're counted
+fn count_arrangements(
+    records: &[(String, Vec<usize>)],
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    progress.start("Records", Some(records.len() as u64));
+    let mut result = 0;
+    for (i, chunk) in records.chunks(64).enumerate() {
+        cancel.check(|| format!("{} of {} records counted", i * 64, records.len()))?;
+        result += parallel::map(chunk, |(row, guards)| {
+            arrangements(&mut HashMap::new(), row.as_bytes(), None, guards)
+        })
+        .iter()
+        .sum::<usize>();
+        progress.advance(chunk.len() as u64);
+    }
+    progress.finish();
 
-    return result;
+    return Ok(result);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = parse(input);
 }
 
 pub fn process(input: String) {    
@@ -119,6 +168,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -157,4 +207,35 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 6792010726878);
     }
+
+    #[test]
+    fn part2_stops_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part2_with_progress("?#? 1", &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 1 records counted");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_records() {
+        use ParseErrorKind::*;
+        assert_eq!(parse("???.###"), Err(Missing("springs and groups").at(1)));
+        assert_eq!(
+            parse("# 1\n???.### 1,x"),
+            Err(InvalidNumber("x".to_string()).at(2))
+        );
+        assert_eq!(
+            parse("???.### 1,0,3"),
+            Err(InvalidNumber("0".to_string()).at(1))
+        );
+        assert_eq!(parse("??a.### 1,1,3"), Err(UnexpectedChar('a').at(1)));
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_records(input in "([.#?x]{0,10} [0-9]{1,2}(,[0-9]{1,2}){0,3}\n){0,4}") {
+            let _ = parse(&input);
+        }
+    }
 }
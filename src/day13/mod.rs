@@ -1,11 +1,40 @@
 use itertools::Itertools;
 use regex::Regex;
+use tracing::{instrument, trace};
 
-fn parse(input: &str) -> Vec<Vec<&str>> {
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+fn parse(input: &str) -> Result<Vec<Vec<&str>>, ParseError> {
     let double_line_ending = Regex::new("\r?\n\r?\n").unwrap();
     let mirrors = double_line_ending.split(input);
 
-    mirrors.map(|m| m.lines().collect_vec()).collect_vec()
+    mirrors
+        .map(|m| {
+            let first_line = parse::line_of(input, m);
+            let pattern = m.lines().collect_vec();
+
+            if pattern.is_empty() || pattern[0].is_empty() {
+                return Err(ParseErrorKind::Missing("a pattern").at(first_line));
+            }
+            for (y, row) in pattern.iter().enumerate() {
+                if row.len() != pattern[0].len() {
+                    return Err(ParseErrorKind::Invalid(format!(
+                        "Row is {} long, the pattern is {} wide",
+                        row.len(),
+                        pattern[0].len()
+                    ))
+                    .at(first_line + y));
+                }
+                if let Some(c) = row.chars().find(|c| !"#.\r".contains(*c)) {
+                    return Err(ParseErrorKind::UnexpectedChar(c).at(first_line + y));
+                }
+            }
+
+            Ok(pattern)
+        })
+        .collect()
 }
 
 fn find_vertical_mirror(plane: &Vec<&str>, expected_differences: usize) -> usize {
@@ -58,7 +87,7 @@ fn find_horizontal_mirror(plane: &Vec<&str>, expected_differences: usize) -> usi
             .sum();
 
         if diff == expected_differences {
-            // println!("Equal {i}: {}");
+            trace!(row = i, "Found a horizontal mirror");
             return i + 1; // 1 based index
         }
     }
@@ -67,36 +96,66 @@ fn find_horizontal_mirror(plane: &Vec<&str>, expected_differences: usize) -> usi
 }
 
 pub fn part1(input: &str) -> usize {
-    let mirrors = parse(input);
-
-    let result = mirrors
-        .iter()
-        .map(|m| {
-            let v = find_vertical_mirror(m, 0);
-            let h = find_horizontal_mirror(m, 0);
-
-            // println!("hscore: {h}, vscore: {v}");
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-            return v + 100 * h;
-        })
-        .sum();
+/// Reports every pattern whose mirror was found, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let mirrors = parse(input).unwrap();
+
+    progress.start("Patterns", Some(mirrors.len() as u64));
+    let mut result = 0;
+    for (i, m) in mirrors.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} patterns searched", mirrors.len()))?;
+        let v = find_vertical_mirror(m, 0);
+        let h = find_horizontal_mirror(m, 0);
+        trace!(h, v, "Mirror scores");
+
+        result += v + 100 * h;
+        progress.advance(1);
+    }
+    progress.finish();
 
-    return result;
+    return Ok(result);
 }
 
 pub fn part2(input: &str) -> usize {
-    let mirrors = parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let result = mirrors
-        .iter()
-        .map(|m| {
-            let v = find_vertical_mirror(m, 1);
-            let h = find_horizontal_mirror(m, 1);
-            return v + 100 * h;
-        })
-        .sum();
+/// Reports every pattern whose mirror was found, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let mirrors = parse(input).unwrap();
+
+    progress.start("Patterns", Some(mirrors.len() as u64));
+    let mut result = 0;
+    for (i, m) in mirrors.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} patterns searched", mirrors.len()))?;
+        let v = find_vertical_mirror(m, 1);
+        let h = find_horizontal_mirror(m, 1);
+        trace!(h, v, "Smudged mirror scores");
+
+        result += v + 100 * h;
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(result);
+}
 
-    return result;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = parse(input);
 }
 
 pub fn process(input: String) {
@@ -110,6 +169,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "#.##..##.
 ..#.##.#.
@@ -152,4 +212,25 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 34795);
     }
+
+    #[test]
+    fn parse_rejects_malformed_patterns() {
+        use ParseErrorKind::*;
+        assert!(matches!(
+            parse("#.#\n##"),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(parse("#.#\n\n#.#\n#x#"), Err(UnexpectedChar('x').at(4)));
+        assert_eq!(parse("#.#\n\n\n\n#.#"), Err(Missing("a pattern").at(3)));
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_patterns(input in "(([#.]{1,6}\n){1,6}\n){1,3}") {
+            let _ = parse(&input);
+        }
+    }
 }
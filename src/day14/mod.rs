@@ -1,20 +1,21 @@
 use std::{collections::HashMap, iter};
 
-use indicatif::ProgressIterator;
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError};
+use crate::progress::{Bar, Progress};
+use crate::render::{Image, Palette, Recorder};
 use itertools::Itertools;
+use tracing::{debug, instrument};
 
 struct Grid {
     grid: Vec<Vec<char>>,
 }
 
 impl Grid {
-    fn parse(input: &str) -> Grid {
-        let grid = input
-            .lines()
-            .map(|line| line.chars().collect_vec())
-            .collect_vec();
+    fn parse(input: &str) -> Result<Grid, ParseError> {
+        let grid = parse::grid(input, "a platform", "O#.")?;
 
-        Grid { grid }
+        Ok(Grid { grid })
     }
 
     fn tilt_north(&mut self) {
@@ -87,6 +88,19 @@ impl Grid {
         }
     }
 
+    /// Tilts north, west, south and east, capturing a frame after every tilt
+    #[instrument(level = "trace", skip_all)]
+    fn spin(&mut self, recorder: &mut impl Recorder) {
+        self.tilt_north();
+        recorder.capture(|palette| self.draw(palette));
+        self.tilt_west();
+        recorder.capture(|palette| self.draw(palette));
+        self.tilt_south();
+        recorder.capture(|palette| self.draw(palette));
+        self.tilt_east();
+        recorder.capture(|palette| self.draw(palette));
+    }
+
     fn north_load(&self) -> usize {
         self.grid
             .iter()
@@ -101,59 +115,110 @@ impl Grid {
             .map(|line| line.iter().collect::<String>())
             .join("\n")
     }
+
+    fn draw(&self, palette: &Palette) -> Image {
+        Image::from_grid(&self.grid, |c| match c {
+            'O' => palette.path,
+            '#' => palette.foreground,
+            _ => palette.background,
+        })
+    }
 }
 
+#[instrument(skip_all)]
 pub fn part1(input: &str) -> usize {
-    let mut grid = Grid::parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Tilts the platform north once, unless `cancel` stopped it before it began
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let mut grid = Grid::parse(input).unwrap();
+
+    progress.start("Tilts", Some(1));
+    cancel.check(|| "No tilts done".to_string())?;
     grid.tilt_north();
+    progress.advance(1);
+    progress.finish();
 
     let result = grid.north_load();
+    debug!("Tilted north:\n{}", grid.to_string());
 
-    // println!("{}", grid.to_string());
-
-    return result;
+    return Ok(result);
 }
 
 pub fn part2(input: &str) -> usize {
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every spin cycle until the platform repeats itself, or until `cancel` stops it
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
     let iterations = 1000000000;
-    let mut grid = Grid::parse(input);
+    let mut grid = Grid::parse(input).unwrap();
 
     let mut cycle_detector: HashMap<String, usize> = HashMap::new();
     let mut cycle_predictor: HashMap<usize, usize> = HashMap::new();
 
-    let first_cycle_index = (1..=1_000_000_000).find_map(|i| {
-        grid.tilt_north();
-        grid.tilt_west();
-        grid.tilt_south();
-        grid.tilt_east();
+    progress.start("Spin cycles", None);
+    let mut first_cycle_index = None;
+    for i in 1..=1_000_000_000 {
+        cancel.check(|| format!("{} spin cycles without a repeat", i - 1))?;
+        grid.spin(&mut ());
+        progress.advance(1);
 
         let state = grid.to_string();
         cycle_predictor.insert(i, grid.north_load());
         if let Some(before) = cycle_detector.get(&state) {
-            // println!("Detected a cycle: {before}, {i}");
-            Some((*before, i))
-        } else {
-            cycle_detector.insert(state, i);
-            None
+            debug!("Detected a cycle: {before}, {i}");
+            first_cycle_index = Some((*before, i));
+            break;
         }
-    });
+        cycle_detector.insert(state, i);
+    }
+    progress.finish();
 
     if let Some((first_encounter, second_encounter)) = first_cycle_index {
         let cycle_length = second_encounter - first_encounter;
         let cycle_i = first_encounter + (iterations - first_encounter) % cycle_length;
         
         let result = cycle_predictor.get(&cycle_i).unwrap();
-        // println!("Predicting a result {result} based on {cycle_i}");
+        debug!("Predicting a result {result} based on {cycle_i}");
+
+        return Ok(*result);
+    }
+    return Ok(0);
+}
+
+/// Records the platform after every tilt of the first `cycles` spin cycles
+pub fn animate(input: &str, cycles: usize, recorder: &mut impl Recorder) {
+    let mut grid = Grid::parse(input).unwrap();
+    recorder.capture(|palette| grid.draw(palette));
 
-        return *result;
+    for _ in 0..cycles {
+        grid.spin(recorder);
+    }
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(grid) = Grid::parse(input) {
+        let printed = grid.to_string();
+        assert_eq!(Grid::parse(&printed).map(|g| g.to_string()), Ok(printed));
     }
-    return 0;
 }
 
 pub fn process(input: String) {
     use std::time::Instant;
     let now = Instant::now();
-    let result = part2(&input);
+    let result = part2_with_progress(&input, &mut Bar::default(), &Token::new()).unwrap();
     println!("Result: {result}");
     println!("Finished in: {:.2?}", now.elapsed());
 }
@@ -161,6 +226,8 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::Animation;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "O....#....
 O.OO#....#
@@ -193,10 +260,66 @@ O.#..O.#.#
         assert_eq!(result, 64);
     }
 
+    #[test]
+    fn part2_stops_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part2_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 spin cycles without a repeat");
+    }
+
     #[test]
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
-        assert_eq!(result, 0);
+        assert_eq!(result, 90795);
+    }
+
+    #[test]
+    fn animate_captures_every_tilt() {
+        let mut animation = Animation::new(Palette::default());
+        animate(EXAMPLE, 3, &mut animation);
+
+        assert_eq!(animation.frames().len(), 1 + 3 * 4);
+        // After the first tilt north the rounded rocks of the first column are stacked
+        let palette = Palette::default();
+        let tilted = &animation.frames()[1];
+        assert_eq!(tilted.get(0, 3), Some(palette.path));
+        assert_eq!(tilted.get(0, 4), Some(palette.background));
+        assert_eq!(tilted.get(0, 8), Some(palette.foreground));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_platforms() {
+        use crate::parse::ParseErrorKind::*;
+        assert_eq!(Grid::parse("").err(), Some(Missing("a platform").at(1)));
+        assert_eq!(Grid::parse("\n").err(), Some(Missing("a platform").at(1)));
+        assert!(matches!(
+            Grid::parse("O.#\nO."),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            Grid::parse("O.#\nO.x").err(),
+            Some(UnexpectedChar('x').at(2))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn platform_round_trips(width in 1..10usize, cells in "[O#.]{1,100}") {
+            let input = cells
+                .as_bytes()
+                .chunks(width)
+                .map(|c| std::str::from_utf8(c).unwrap())
+                .join("\n");
+            match Grid::parse(&input) {
+                Ok(grid) => prop_assert_eq!(grid.to_string(), input),
+                Err(_) => prop_assert!(cells.len() % width != 0 && cells.len() > width),
+            }
+        }
     }
 }
@@ -2,6 +2,11 @@ use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{collections::BTreeMap, str::FromStr};
+use tracing::{debug, instrument};
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::ParseErrorKind;
+use crate::progress::Progress;
 
 #[derive(Debug)]
 enum Operation {
@@ -18,31 +23,64 @@ struct Step {
 }
 
 lazy_static! {
-    static ref STEP_REGEX: Regex = Regex::new(r"([\w]+)(\-|\=)([\d])*").unwrap();
+    static ref STEP_REGEX: Regex = Regex::new(r"^([\w]+)(\-|\=([1-9]))$").unwrap();
+}
+
+impl Step {
+    /// Why `s` didn't match [`STEP_REGEX`]
+    fn rejection(s: &str) -> ParseErrorKind {
+        let label_end = s
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(s.len());
+        let (label, operation) = s.split_at(label_end);
+        let mut chars = operation.chars();
+
+        return match (label, chars.next()) {
+            ("", None | Some('-' | '=')) => ParseErrorKind::Missing("a label"),
+            (_, None) => ParseErrorKind::Missing("`-` or `=`"),
+            (_, Some('-')) => match chars.next() {
+                Some(c) => ParseErrorKind::UnexpectedChar(c),
+                None => ParseErrorKind::Invalid(format!("Unexpected step `{s}`")),
+            },
+            (_, Some('=')) if chars.as_str().is_empty() => {
+                ParseErrorKind::Missing("a focal length")
+            }
+            (_, Some('=')) => ParseErrorKind::InvalidNumber(chars.as_str().to_string()),
+            (_, Some(c)) => ParseErrorKind::UnexpectedChar(c),
+        };
+    }
 }
 
 impl FromStr for Step {
-    type Err = String;
+    type Err = ParseErrorKind;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let captures = STEP_REGEX.captures(s).unwrap();
+        let captures = STEP_REGEX.captures(s).ok_or_else(|| Step::rejection(s))?;
 
         Ok(Self {
             label: captures.get(1).unwrap().as_str().to_string(),
             box_number: hash(captures.get(1).unwrap().as_str()),
             operation: match captures.get(2).unwrap().as_str() {
-                "=" => Operation::Set,
                 "-" => Operation::Remove,
-                _ => panic!("Unexpected operation"),
+                _ => Operation::Set,
             },
             focal_length: captures
                 .get(3)
-                .map(|c| c.as_str().parse::<usize>().unwrap_or(0))
+                .map(|c| c.as_str().parse::<usize>().unwrap())
                 .unwrap_or(0),
         })
     }
 }
 
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.operation {
+            Operation::Remove => write!(f, "{}-", self.label),
+            Operation::Set => write!(f, "{}={}", self.label, self.focal_length),
+        }
+    }
+}
+
 fn hash(input: &str) -> usize {
     let mut current_value: usize = 0;
 
@@ -56,11 +94,41 @@ fn hash(input: &str) -> usize {
 }
 
 pub fn part1(input: &str) -> usize {
-    let result = input.split(',').map(|p| hash(p)).sum();
-    return result;
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every step hashed, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let steps = input.split(',').collect_vec();
+
+    progress.start("Steps", Some(steps.len() as u64));
+    let mut result = 0;
+    for (i, step) in steps.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} steps hashed", steps.len()))?;
+        result += hash(step);
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(result);
 }
 
 pub fn part2(input: &str) -> usize {
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every step placing or removing a lens, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
     let steps: Vec<Step> = input
         .split(',')
         .map(str::parse)
@@ -69,7 +137,11 @@ pub fn part2(input: &str) -> usize {
 
     let mut boxes: BTreeMap<usize, Vec<Step>> = (0..=256).map(|i| (i, Vec::new())).collect();
 
-    for step in steps {
+    let count = steps.len();
+    progress.start("Steps", Some(count as u64));
+    for (i, step) in steps.into_iter().enumerate() {
+        cancel.check(|| format!("{i} of {count} steps done"))?;
+        progress.advance(1);
         match step.operation {
             Operation::Remove => {
                 let b = boxes.get_mut(&step.box_number).unwrap();
@@ -90,6 +162,7 @@ pub fn part2(input: &str) -> usize {
             }
         }
     }
+    progress.finish();
 
     let result: usize = boxes
         .iter()
@@ -100,10 +173,17 @@ pub fn part2(input: &str) -> usize {
                 .sum::<usize>()
         })
         .sum();
+    debug!(?boxes);
 
-    // println!("{:?}", &boxes);
+    return Ok(result);
+}
 
-    return result;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    for step in input.split(',').filter_map(|s| s.parse::<Step>().ok()) {
+        let printed = step.to_string();
+        assert_eq!(printed.parse::<Step>().map(|s| s.to_string()), Ok(printed));
+    }
 }
 
 pub fn process(input: String) {
@@ -117,6 +197,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7";
 
@@ -151,4 +232,29 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 259333);
     }
+
+    #[test]
+    fn parse_rejects_malformed_steps() {
+        use ParseErrorKind::*;
+        assert_eq!("rn".parse::<Step>().err(), Some(Missing("`-` or `=`")));
+        assert_eq!("rn=".parse::<Step>().err(), Some(Missing("a focal length")));
+        assert_eq!(
+            "rn=12".parse::<Step>().err(),
+            Some(InvalidNumber("12".to_string()))
+        );
+        assert_eq!("rn-1".parse::<Step>().err(), Some(UnexpectedChar('1')));
+        assert_eq!(
+            "rn=1\n".parse::<Step>().err(),
+            Some(InvalidNumber("1\n".to_string()))
+        );
+        assert_eq!("=1".parse::<Step>().err(), Some(Missing("a label")));
+        assert_eq!("r+1".parse::<Step>().err(), Some(UnexpectedChar('+')));
+    }
+
+    proptest! {
+        #[test]
+        fn step_round_trips(step in "[a-z]{1,6}(-|=[1-9])") {
+            prop_assert_eq!(step.parse::<Step>().unwrap().to_string(), step);
+        }
+    }
 }
@@ -1,6 +1,12 @@
 use std::collections::BTreeSet;
 
+use crate::cancel::{TimedOut, Token};
+use crate::parallel;
+use crate::parse::{self, ParseError};
+use crate::progress::Progress;
+use crate::render::{Image, Palette, Recorder};
 use itertools::Itertools;
+use tracing::{instrument, trace};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
 enum Direction {
@@ -33,93 +39,151 @@ impl Direction {
     }
 }
 
-fn parse(input: &str) -> Vec<Vec<char>> {
-    input
-        .lines()
-        .map(|line| line.chars().collect_vec())
-        .collect_vec()
+fn parse(input: &str) -> Result<Vec<Vec<char>>, ParseError> {
+    parse::grid(input, "a contraption", "./\\|-")
 }
 
-fn energise(grid: &Vec<Vec<char>>, start: (i32, i32, Direction)) -> usize {
+/// Draws the mirrors and splitters with the energised tiles and the heads of the beams
+fn draw(
+    grid: &[Vec<char>],
+    energised: &BTreeSet<(i32, i32)>,
+    beams: &[(i32, i32, Direction)],
+    palette: &Palette,
+) -> Image {
+    let mut image = Image::from_grid(grid, |c| match c {
+        '.' => palette.background,
+        _ => palette.foreground,
+    });
+
+    // Beams start just outside of the contraption
+    let on_grid = |(x, y): (i32, i32)| (x >= 0 && y >= 0).then_some((x as usize, y as usize));
+    image.overlay(energised.iter().filter_map(|p| on_grid(*p)), palette.path);
+    image.overlay(
+        beams.iter().filter_map(|(x, y, _)| on_grid((*x, *y))),
+        palette.highlight,
+    );
+
+    return image;
+}
+
+/// Follows the beam from `start` a tile per frame, or stops between frames when `cancel` does
+#[instrument(level = "debug", skip(grid, recorder, cancel))]
+fn energise(
+    grid: &[Vec<char>],
+    start: (i32, i32, Direction),
+    recorder: &mut impl Recorder,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
     let mut visited: BTreeSet<(i32, i32, Direction)> = BTreeSet::new();
     let mut energised: BTreeSet<(i32, i32)> = BTreeSet::new();
     let mut beams: Vec<(i32, i32, Direction)> = Vec::new();
 
     beams.push(start);
 
-    while let Some((beam_x, beam_y, beam_dir)) = beams.pop() {
-        if !visited.contains(&(beam_x, beam_y, beam_dir)) {
-            visited.insert((beam_x, beam_y, beam_dir.clone()));
-            energised.insert((beam_x, beam_y));
-        } else {
-            continue;
-        }
+    // Every beam moves a tile per frame
+    while !beams.is_empty() {
+        cancel.check(|| format!("{} tiles energised", energised.len().saturating_sub(1)))?;
+        for (beam_x, beam_y, beam_dir) in std::mem::take(&mut beams) {
+            if !visited.contains(&(beam_x, beam_y, beam_dir)) {
+                visited.insert((beam_x, beam_y, beam_dir));
+                energised.insert((beam_x, beam_y));
+            } else {
+                continue;
+            }
 
-        if let Some((next_x, next_y)) = beam_dir.apply(beam_x, beam_y) {
-            if let Some(c) = grid
-                .get(next_y as usize)
-                .and_then(|row| row.get(next_x as usize))
-            {
-                match c {
-                    '.' => {
-                        beams.push((next_x, next_y, beam_dir));
-                    }
-                    '\\' if beam_dir == Direction::Right => {
-                        beams.push((next_x, next_y, Direction::Down));
-                    }
-                    '\\' if beam_dir == Direction::Left => {
-                        beams.push((next_x, next_y, Direction::Up));
-                    }
-                    '\\' if beam_dir == Direction::Up => {
-                        beams.push((next_x, next_y, Direction::Left));
-                    }
-                    '\\' if beam_dir == Direction::Down => {
-                        beams.push((next_x, next_y, Direction::Right));
-                    }
-                    '/' if beam_dir == Direction::Right => {
-                        beams.push((next_x, next_y, Direction::Up));
-                    }
-                    '/' if beam_dir == Direction::Left => {
-                        beams.push((next_x, next_y, Direction::Down));
+            if let Some((next_x, next_y)) = beam_dir.apply(beam_x, beam_y) {
+                if let Some(c) = grid
+                    .get(next_y as usize)
+                    .and_then(|row| row.get(next_x as usize))
+                {
+                    match c {
+                        '.' => {
+                            beams.push((next_x, next_y, beam_dir));
+                        }
+                        '\\' if beam_dir == Direction::Right => {
+                            beams.push((next_x, next_y, Direction::Down));
+                        }
+                        '\\' if beam_dir == Direction::Left => {
+                            beams.push((next_x, next_y, Direction::Up));
+                        }
+                        '\\' if beam_dir == Direction::Up => {
+                            beams.push((next_x, next_y, Direction::Left));
+                        }
+                        '\\' if beam_dir == Direction::Down => {
+                            beams.push((next_x, next_y, Direction::Right));
+                        }
+                        '/' if beam_dir == Direction::Right => {
+                            beams.push((next_x, next_y, Direction::Up));
+                        }
+                        '/' if beam_dir == Direction::Left => {
+                            beams.push((next_x, next_y, Direction::Down));
+                        }
+                        '/' if beam_dir == Direction::Up => {
+                            beams.push((next_x, next_y, Direction::Right));
+                        }
+                        '/' if beam_dir == Direction::Down => {
+                            beams.push((next_x, next_y, Direction::Left));
+                        }
+                        '|' if beam_dir == Direction::Right || beam_dir == Direction::Left => {
+                            beams.push((next_x, next_y, Direction::Up));
+                            beams.push((next_x, next_y, Direction::Down));
+                        }
+                        '|' if beam_dir == Direction::Up || beam_dir == Direction::Down => {
+                            beams.push((next_x, next_y, beam_dir));
+                        }
+                        '-' if beam_dir == Direction::Right || beam_dir == Direction::Left => {
+                            beams.push((next_x, next_y, beam_dir));
+                        }
+                        '-' if beam_dir == Direction::Up || beam_dir == Direction::Down => {
+                            beams.push((next_x, next_y, Direction::Left));
+                            beams.push((next_x, next_y, Direction::Right));
+                        }
+                        _ => (),
                     }
-                    '/' if beam_dir == Direction::Up => {
-                        beams.push((next_x, next_y, Direction::Right));
-                    }
-                    '/' if beam_dir == Direction::Down => {
-                        beams.push((next_x, next_y, Direction::Left));
-                    }
-                    '|' if beam_dir == Direction::Right || beam_dir == Direction::Left => {
-                        beams.push((next_x, next_y, Direction::Up));
-                        beams.push((next_x, next_y, Direction::Down));
-                    }
-                    '|' if beam_dir == Direction::Up || beam_dir == Direction::Down => {
-                        beams.push((next_x, next_y, beam_dir));
-                    }
-                    '-' if beam_dir == Direction::Right || beam_dir == Direction::Left => {
-                        beams.push((next_x, next_y, beam_dir));
-                    }
-                    '-' if beam_dir == Direction::Up || beam_dir == Direction::Down => {
-                        beams.push((next_x, next_y, Direction::Left));
-                        beams.push((next_x, next_y, Direction::Right));
-                    }
-                    _ => (),
                 }
             }
         }
+
+        recorder.capture(|palette| draw(grid, &energised, &beams, palette));
     }
 
-    // println!("{energised:?}");
-    return energised.len() - 1;
+    trace!(?energised);
+    return Ok(energised.len() - 1);
 }
 
 pub fn part1(input: &str) -> usize {
-    let grid = parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Follows the beam from the top left corner, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let grid = parse(input).unwrap();
 
-    return energise(&grid, (-1, 0, Direction::Right));
+    progress.start("Beams", Some(1));
+    let result = energise(&grid, (-1, 0, Direction::Right), &mut (), cancel)?;
+    progress.advance(1);
+    progress.finish();
+
+    return Ok(result);
 }
 
 pub fn part2(input: &str) -> usize {
-    let grid = parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every edge tile the beam is sent in from, or stops between them when `cancel` does
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let grid = parse(input).unwrap();
 
     let from_left = (0..grid.len()).map(|y| (-1_i32, y as i32, Direction::Right));
     let from_right = (0..grid.len()).map(|y| (grid[0].len() as i32, y as i32, Direction::Left));
@@ -127,9 +191,36 @@ pub fn part2(input: &str) -> usize {
     let from_top = (0..grid[0].len()).map(|x| (x as i32, -1_i32, Direction::Down));
     let from_bottom = (0..grid.len()).map(|x| (x as i32, grid.len() as i32, Direction::Up));
 
-    let result = from_left.chain(from_right).chain(from_top).chain(from_bottom).map(|start| energise(&grid, start)).max().unwrap();
+    let starts = from_left
+        .chain(from_right)
+        .chain(from_top)
+        .chain(from_bottom)
+        .collect_vec();
+
+    progress.start("Beam starts", Some(starts.len() as u64));
+    let mut result = 0;
+    for (i, chunk) in starts.chunks(16).enumerate() {
+        cancel.check(|| format!("{} of {} beam starts energised", i * 16, starts.len()))?;
+        for energised in parallel::map(chunk, |start| energise(&grid, *start, &mut (), cancel)) {
+            result = result.max(energised?);
+        }
+        progress.advance(chunk.len() as u64);
+    }
+    progress.finish();
 
-    return result;
+    return Ok(result);
+}
+
+/// Records the beam of part 1 spreading through the contraption
+pub fn animate(input: &str, recorder: &mut impl Recorder) {
+    let grid = parse(input).unwrap();
+
+    energise(&grid, (-1, 0, Direction::Right), recorder, &Token::new()).unwrap();
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = parse(input);
 }
 
 pub fn process(input: String) {
@@ -143,6 +234,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::Animation;
 
     const EXAMPLE: &str = r".|...\....
 |.-.\.....
@@ -170,8 +262,7 @@ mod tests {
 
     #[test]
     fn part2_example() {
-        let input = "";
-        let result = part2(input);
+        let result = part2(EXAMPLE);
         assert_eq!(result, 51);
     }
 
@@ -179,6 +270,50 @@ mod tests {
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
-        assert_eq!(result, 0);
+        assert_eq!(result, 7521);
+    }
+
+    #[test]
+    fn part2_stops_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part2_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 40 beam starts energised");
+    }
+
+    #[test]
+    fn animate_follows_the_beam() {
+        let palette = Palette::default();
+        let mut animation = Animation::new(palette.clone());
+        animate(EXAMPLE, &mut animation);
+
+        // The beam enters from the left and is split up by the mirror on the second tile
+        let frames = animation.frames();
+        assert_eq!(frames[0].get(0, 0), Some(palette.highlight));
+        assert_eq!(frames[1].get(1, 0), Some(palette.highlight));
+        assert_eq!(frames[2].get(1, 1), Some(palette.highlight));
+
+        let last = frames.last().unwrap();
+        let energised = (0..10)
+            .cartesian_product(0..10)
+            .filter(|(x, y)| last.get(*x, *y) == Some(palette.path))
+            .count();
+        assert_eq!(energised, 46);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_contraptions() {
+        use crate::parse::ParseErrorKind::*;
+        assert_eq!(parse(""), Err(Missing("a contraption").at(1)));
+        assert_eq!(parse("\n"), Err(Missing("a contraption").at(1)));
+        assert!(matches!(
+            parse(".|.\n.-"),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(parse(".|.\n.x."), Err(UnexpectedChar('x').at(2)));
     }
 }
@@ -1,18 +1,25 @@
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError};
+use crate::progress::Progress;
+use crate::render::{Image, Palette};
 use glam::IVec2;
 use itertools::Itertools;
 use pathfinding::prelude::dijkstra;
 use std::collections::HashMap;
+use tracing::{debug, instrument};
 
-fn parse(input: &str) -> HashMap<IVec2, u32> {
-    input
-        .lines()
+fn parse(input: &str) -> Result<HashMap<IVec2, u32>, ParseError> {
+    let grid = parse::grid(input, "a city map", "0123456789")?;
+
+    Ok(grid
+        .iter()
         .enumerate()
-        .flat_map(|(y, line)| {
-            line.chars()
+        .flat_map(|(y, row)| {
+            row.iter()
                 .enumerate()
                 .map(move |(x, c)| (IVec2::new(x as i32, y as i32), c.to_digit(10).unwrap()))
         })
-        .collect()
+        .collect())
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
@@ -195,18 +202,23 @@ impl CrucibleMechanics for UltraCrucibleMechanics {
     }
 }
 
+/// The path with the least heat loss from `start`, none once `cancel` stops the search
 fn find_shortest_path(
     grid: &HashMap<IVec2, u32>,
     start: Crucible,
     mechanics: &impl CrucibleMechanics,
-) -> (Vec<Crucible>, u32) {
+    cancel: &Token,
+) -> Option<(Vec<Crucible>, u32)> {
     let max_x = grid.keys().map(|i| i.x).max().unwrap();
     let max_y = grid.keys().map(|i| i.y).max().unwrap();
 
     let goal_pos = IVec2::new(max_x, max_y);
-    let (path, distance) = dijkstra(
+    return dijkstra(
         &start,
         |node| {
+            if cancel.is_cancelled() {
+                return vec![];
+            }
             mechanics
                 .successors(node)
                 .iter()
@@ -214,10 +226,31 @@ fn find_shortest_path(
                 .collect::<Vec<(Crucible, u32)>>()
         },
         |node| node.pos == goal_pos && mechanics.can_stop(node),
-    )
-    .expect("should have a valid path");
+    );
+}
 
-    return (path, distance);
+/// The path with the least heat loss over all of the crucible's starts, reporting every
+/// start searched, or stopping when `cancel` does
+#[instrument(skip_all)]
+fn best_path(
+    grid: &HashMap<IVec2, u32>,
+    mechanics: &impl CrucibleMechanics,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<(Vec<Crucible>, u32), TimedOut> {
+    let starts = mechanics.starts();
+
+    progress.start("Crucible starts", Some(starts.len() as u64));
+    let mut paths = vec![];
+    for (i, start) in starts.iter().enumerate() {
+        let path = find_shortest_path(grid, *start, mechanics, cancel);
+        cancel.check(|| format!("{i} of {} crucible starts searched", starts.len()))?;
+        paths.push(path.expect("should have a valid path"));
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(paths.into_iter().min_by_key(|(_, d)| *d).unwrap());
 }
 
 fn print_path(path: &Vec<Crucible>) -> String {
@@ -240,38 +273,76 @@ fn print_path(path: &Vec<Crucible>) -> String {
         .join("\n")
 }
 
-pub fn part1(input: &str) -> u32 {
-    let grid = parse(input);
+/// The path of the large crucible, drawn with `#`
+pub fn ascii(input: &str) -> String {
+    let grid = parse(input).unwrap();
+    let (path, _) = best_path(&grid, &LargeCrucibleMechanics, &mut (), &Token::new()).unwrap();
 
-    let mechanics = LargeCrucibleMechanics {};
+    return print_path(&path);
+}
 
-    let (p, d) = &mechanics
-        .starts()
-        .iter()
-        .map(|start| find_shortest_path(&grid, *start, &mechanics))
-        .min_by_key(|(_, d)| *d)
-        .unwrap();
+pub fn part1(input: &str) -> u32 {
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    // println!("{}", print_path(p));
+/// Reports every start of the large crucible searched, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u32, TimedOut> {
+    let grid = parse(input).unwrap();
 
-    return *d;
+    let (p, d) = best_path(&grid, &LargeCrucibleMechanics, progress, cancel)?;
+    debug!("Best path:\n{}", print_path(&p));
+
+    return Ok(d);
 }
 
 pub fn part2(input: &str) -> u32 {
-    let grid = parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let mechanics = UltraCrucibleMechanics {};
+/// Reports every start of the ultra crucible searched, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u32, TimedOut> {
+    let grid = parse(input).unwrap();
 
-    let (p, d) = &mechanics
-        .starts()
-        .iter()
-        .map(|start| find_shortest_path(&grid, *start, &mechanics))
-        .min_by_key(|(_, d)| *d)
-        .unwrap();
+    let (p, d) = best_path(&grid, &UltraCrucibleMechanics, progress, cancel)?;
+    debug!("Best path:\n{}", print_path(&p));
+
+    return Ok(d);
+}
+
+/// Draws the heat loss of every block with the best path of the regular crucible,
+/// and the best path of the ultra crucible on top of it
+pub fn render(input: &str, palette: &Palette) -> Image {
+    let grid = parse(input).unwrap();
+    let size = grid.keys().fold(IVec2::ZERO, |a, b| a.max(*b)) + 1;
+    let cell = |pos: IVec2| (pos.x as usize, pos.y as usize);
+
+    let heat_losses = grid.iter().map(|(pos, loss)| (cell(*pos), *loss as u64));
+
+    let mut image = Image::new(size.x as usize, size.y as usize, palette.background);
+    image.heatmap(heat_losses, palette);
+
+    let (large, _) = best_path(&grid, &LargeCrucibleMechanics, &mut (), &Token::new()).unwrap();
+    image.overlay(large.iter().map(|c| cell(c.pos)), palette.path);
 
-    // println!("{}", print_path(p));
+    let (ultra, _) = best_path(&grid, &UltraCrucibleMechanics, &mut (), &Token::new()).unwrap();
+    image.overlay(ultra.iter().map(|c| cell(c.pos)), palette.highlight);
 
-    return *d;
+    return image;
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = parse(input);
 }
 
 pub fn process(input: String) {
@@ -336,4 +407,43 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 1017);
     }
+
+    #[test]
+    fn paths_stop_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part1_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 1 crucible starts searched");
+        let timed_out = part2_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 2 crucible starts searched");
+    }
+
+    #[test]
+    fn render_draws_both_paths() {
+        let palette = Palette::default();
+        let image = render(EXAMPLE, &palette);
+
+        assert_eq!((image.width(), image.height()), (13, 13));
+        // Both crucibles leave the top left corner and end in the bottom right one
+        assert_eq!(image.get(0, 0), Some(palette.highlight));
+        assert_eq!(image.get(12, 12), Some(palette.highlight));
+        // Neither path passes the top right block, which loses 3 on a map from 1 to 9
+        assert_eq!(image.get(12, 0), Some(palette.heat(2. / 8.)));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_maps() {
+        use crate::parse::ParseErrorKind::*;
+        assert_eq!(parse(""), Err(Missing("a city map").at(1)));
+        assert_eq!(parse("\n"), Err(Missing("a city map").at(1)));
+        assert!(matches!(
+            parse("123\n12"),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(parse("123\n1a3"), Err(UnexpectedChar('a').at(2)));
+    }
 }
@@ -1,3 +1,7 @@
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+use crate::render::{Image, Palette};
 use glam::I64Vec2;
 use itertools::Itertools;
 use std::{collections::HashSet, str::FromStr};
@@ -11,7 +15,7 @@ enum Direction {
 }
 
 impl FromStr for Direction {
-    type Err = String;
+    type Err = ParseErrorKind;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
@@ -19,7 +23,7 @@ impl FromStr for Direction {
             "R" => Ok(Direction::Right),
             "U" => Ok(Direction::Up),
             "D" => Ok(Direction::Down),
-            i => Err(format!("Unexpected direction input `{i}`")),
+            i => Err(ParseErrorKind::Unknown(i.to_string())),
         }
     }
 }
@@ -32,19 +36,47 @@ struct Instruction {
 }
 
 impl FromStr for Instruction {
-    type Err = String;
+    type Err = ParseErrorKind;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (d, l, c) = s.split(" ").collect_tuple().unwrap();
+        let (d, l, c) = s
+            .split(" ")
+            .collect_tuple()
+            .ok_or(ParseErrorKind::Missing("a direction, length and color"))?;
+
+        // The color encodes the corrected instruction: five hex digits and a direction
+        let valid_color = c.is_ascii()
+            && c.len() == 9
+            && c.starts_with("(#")
+            && c.ends_with(")")
+            && c[2..7].chars().all(|c| c.is_ascii_hexdigit())
+            && ("0"..="3").contains(&&c[7..8]);
+        if !valid_color {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Color `{c}` should be `(#<five hex digits><0 to 3>)`"
+            )));
+        }
 
         Ok(Instruction {
             direction: d.parse()?,
-            length: l.parse().unwrap(),
+            length: parse::number(l)?,
             color: c.to_string(),
         })
     }
 }
 
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            Direction::Right => "R",
+            Direction::Left => "L",
+            Direction::Up => "U",
+            Direction::Down => "D",
+        };
+        write!(f, "{direction} {} {}", self.length, self.color)
+    }
+}
+
 impl Instruction {
     fn to_correct(&self) -> Instruction {
         let length = &self.color[2..7];
@@ -64,12 +96,8 @@ impl Instruction {
     }
 }
 
-fn parse(input: &str) -> Vec<Instruction> {
-    input
-        .lines()
-        .map(str::parse)
-        .map(Result::unwrap)
-        .collect_vec()
+fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    parse::lines(input, str::parse)
 }
 
 fn print_trenches(trenches: &HashSet<I64Vec2>, detections: &HashSet<I64Vec2>) -> String {
@@ -162,15 +190,25 @@ fn vertices(instructions: &Vec<Instruction>) -> Vec<I64Vec2> {
     return vertices;
 }
 
-fn flood_fill(start: I64Vec2, trenches: &HashSet<I64Vec2>) -> HashSet<I64Vec2> {
+/// The tiles reachable from `start` without crossing a trench, reporting every tile filled,
+/// or stopping when `cancel` does
+fn flood_fill(
+    start: I64Vec2,
+    trenches: &HashSet<I64Vec2>,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<HashSet<I64Vec2>, TimedOut> {
     let mut queue: Vec<I64Vec2> = Vec::new();
     queue.push(start);
 
     let mut visited: HashSet<I64Vec2> = HashSet::new();
 
+    progress.start("Lagoon tiles", None);
     while let Some(n) = queue.pop() {
         if !trenches.contains(&n) && !visited.contains(&n) {
+            cancel.check(|| format!("{} lagoon tiles filled", visited.len()))?;
             visited.insert(n);
+            progress.advance(1);
 
             queue.push(I64Vec2::new(n.x, n.y + 1));
             queue.push(I64Vec2::new(n.x, n.y - 1));
@@ -178,8 +216,9 @@ fn flood_fill(start: I64Vec2, trenches: &HashSet<I64Vec2>) -> HashSet<I64Vec2> {
             queue.push(I64Vec2::new(n.x - 1, n.y));
         }
     }
+    progress.finish();
 
-    return visited;
+    return Ok(visited);
 }
 
 fn polygon_area(vertices: &Vec<I64Vec2>) -> f64 {
@@ -199,17 +238,42 @@ fn polygon_area(vertices: &Vec<I64Vec2>) -> f64 {
 }
 
 pub fn part1(input: &str) -> usize {
-    let instructions = parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every tile of the lagoon filled, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let instructions = parse(input).unwrap();
 
     let trenches = trenches(&instructions);
 
-    let fill = flood_fill(I64Vec2::new(1, 1), &trenches);
+    let fill = flood_fill(I64Vec2::new(1, 1), &trenches, progress, cancel)?;
 
-    return trenches.len() + fill.len();
+    return Ok(trenches.len() + fill.len());
 }
 
 pub fn part2(input: &str) -> usize {
-    let instructions = parse(input).iter().map(|i| i.to_correct()).collect_vec();
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Measures the lagoon from its corners, unless `cancel` stopped it before it began
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let instructions = parse(input)
+        .unwrap()
+        .iter()
+        .map(|i| i.to_correct())
+        .collect_vec();
+
+    progress.start("Lagoons", Some(1));
+    cancel.check(|| "No lagoon measured".to_string())?;
     let vertices = vertices(&instructions);
     
     let area = polygon_area(&vertices);
@@ -217,8 +281,46 @@ pub fn part2(input: &str) -> usize {
     let perimeter_length: usize = instructions.iter().map(|i| i.length).sum();
 
     let total_area = area as f64 + (perimeter_length as f64 / 2.) + 1.;
+    progress.advance(1);
+    progress.finish();
 
-    return total_area as usize;
+    return Ok(total_area as usize);
+}
+
+/// The trenches of part 1 drawn with `#`, with dots on the lagoon they enclose
+pub fn ascii(input: &str) -> String {
+    let instructions = parse(input).unwrap();
+    let trenches = trenches(&instructions);
+    let lagoon = flood_fill(I64Vec2::new(1, 1), &trenches, &mut (), &Token::new()).unwrap();
+
+    return print_trenches(&trenches, &lagoon);
+}
+
+/// Draws the trench dug by the plan of part 1 and the lagoon it encloses
+pub fn render(input: &str, palette: &Palette) -> Image {
+    let instructions = parse(input).unwrap();
+    let trenches = trenches(&instructions);
+    let lagoon = flood_fill(I64Vec2::new(1, 1), &trenches, &mut (), &Token::new()).unwrap();
+
+    let min = trenches.iter().fold(I64Vec2::MAX, |a, b| a.min(*b));
+    let max = trenches.iter().fold(I64Vec2::MIN, |a, b| a.max(*b));
+    let size = max - min + 1;
+    let cell = |pos: &I64Vec2| ((pos.x - min.x) as usize, (pos.y - min.y) as usize);
+
+    let mut image = Image::new(size.x as usize, size.y as usize, palette.background);
+    image.overlay(lagoon.iter().map(cell), palette.highlight);
+    image.overlay(trenches.iter().map(cell), palette.path);
+
+    return image;
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(instructions) = parse(input) {
+        let printed = instructions.iter().join("\n");
+        let reparsed = parse(&printed).map(|i| i.iter().join("\n"));
+        assert_eq!(reparsed, Ok(printed));
+    }
 }
 
 pub fn process(input: String) {
@@ -232,6 +334,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "R 6 (#70c710)
 D 5 (#0dc571)
@@ -273,4 +376,45 @@ U 2 (#7a21e3)";
         let result = part2(input);
         assert_eq!(result, 52885384955882);
     }
+
+    #[test]
+    fn render_draws_the_lagoon() {
+        let palette = Palette::default();
+        let image = render(EXAMPLE, &palette);
+
+        assert_eq!((image.width(), image.height()), (7, 10));
+        let dug = (0..7)
+            .cartesian_product(0..10)
+            .filter(|(x, y)| image.get(*x, *y) != Some(palette.background))
+            .count();
+        assert_eq!(dug, 62);
+        assert_eq!(image.get(0, 0), Some(palette.path));
+        assert_eq!(image.get(1, 1), Some(palette.highlight));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_instructions() {
+        use ParseErrorKind::*;
+        let rejection = |input: &str| parse(input).err().map(|e| e.kind);
+        assert_eq!(
+            rejection("R 6"),
+            Some(Missing("a direction, length and color"))
+        );
+        assert_eq!(rejection("X 6 (#70c710)"), Some(Unknown("X".to_string())));
+        assert_eq!(
+            parse("R 6 (#70c710)\nR -6 (#70c710)").err(),
+            Some(InvalidNumber("-6".to_string()).at(2))
+        );
+        for color in ["(#70c71)", "(#70c714)", "(#70g710)", "(#70c7é)"] {
+            let input = format!("R 6 {color}");
+            assert!(matches!(rejection(&input), Some(Invalid(_))), "{input}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn instruction_round_trips(instruction in "[RLUD] [1-9][0-9]{0,2} \\(#[0-9a-f]{5}[0-3]\\)") {
+            prop_assert_eq!(instruction.parse::<Instruction>().unwrap().to_string(), instruction);
+        }
+    }
 }
@@ -1,122 +1,233 @@
 use itertools::Itertools;
 use lazy_static::*;
 use regex::Regex;
-use std::{collections::HashMap, ops::Range};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    ops::{Range, RangeInclusive},
+};
+use tracing::{debug, instrument};
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
 
 lazy_static! {
-    static ref PART_REGEX: Regex = Regex::new(r"\{x=(\d+),m=(\d+),a=(\d+),s=(\d+)\}").unwrap();
+    static ref CONDITION_REGEX: Regex = Regex::new(r"^(\w+?)(<=|>=|==|<|>)(\d+):(\w+)$").unwrap();
     static ref DOUBLE_LINE_REGEX: Regex = Regex::new(r"\r?\n\r?\n").unwrap();
+    static ref NAME_REGEX: Regex = Regex::new(r"^\w+$").unwrap();
 }
 
 #[derive(Debug)]
-struct Part {
-    x: usize,
-    m: usize,
-    a: usize,
-    s: usize,
+pub struct Part {
+    /// Attribute ratings in the order they appear in the input
+    ratings: Vec<(String, u64)>,
 }
 
 impl Part {
-    fn parse(s: &str) -> Part {
-        let captures = PART_REGEX.captures(s).unwrap();
-        Part {
-            x: captures.get(1).unwrap().as_str().parse().unwrap(),
-            m: captures.get(2).unwrap().as_str().parse().unwrap(),
-            a: captures.get(3).unwrap().as_str().parse().unwrap(),
-            s: captures.get(4).unwrap().as_str().parse().unwrap(),
+    fn parse(s: &str) -> Result<Part, ParseErrorKind> {
+        let ratings: Vec<(String, u64)> = s
+            .trim()
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .ok_or(ParseErrorKind::Missing("`{` and `}` around the ratings"))?
+            .split(',')
+            .map(|rating| {
+                let (attribute, value) = rating
+                    .split_once('=')
+                    .filter(|(attribute, _)| NAME_REGEX.is_match(attribute))
+                    .ok_or(ParseErrorKind::Missing("`<attribute>=<value>`"))?;
+                Ok((attribute.to_string(), parse::number(value)?))
+            })
+            .collect::<Result<_, ParseErrorKind>>()?;
+
+        let attributes = ratings.iter().map(|(attribute, _)| attribute);
+        if let Some(attribute) = attributes.duplicates().next() {
+            return Err(ParseErrorKind::Duplicate(attribute.to_string()));
         }
+
+        Ok(Part { ratings })
     }
 
-    fn value(&self, c: char) -> usize {
-        match c {
-            'x' => self.x,
-            'm' => self.m,
-            'a' => self.a,
-            's' => self.s,
-            other => unreachable!("Should never ask for a part value `{other}`"),
-        }
+    fn value(&self, attribute: &str) -> u64 {
+        self.ratings
+            .iter()
+            .find(|(a, _)| a == attribute)
+            .map(|(_, v)| *v)
+            .unwrap_or_else(|| panic!("Part has no rating for `{attribute}`"))
+    }
+
+    /// The sum of every rating, which can pass `u64::MAX` when ratings reach it
+    fn total(&self) -> u128 {
+        self.ratings.iter().map(|(_, v)| u128::from(*v)).sum()
     }
 }
 
+impl Display for Part {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ratings = self
+            .ratings
+            .iter()
+            .map(|(attribute, value)| format!("{attribute}={value}"))
+            .join(",");
+        write!(f, "{{{ratings}}}")
+    }
+}
+
+/// An N-dimensional box of ratings, one half-open range per attribute. The ends are
+/// `u128` so that a range can still hold a rating of `u64::MAX`
 #[derive(Debug, Clone)]
 struct PartRange {
-    x: Range<usize>,
-    m: Range<usize>,
-    a: Range<usize>,
-    s: Range<usize>,
+    bounds: BTreeMap<String, Range<u128>>,
 }
 
 impl PartRange {
-    fn value(&self, c: char) -> Range<usize> {
-        match c {
-            'x' => self.x.clone(),
-            'm' => self.m.clone(),
-            'a' => self.a.clone(),
-            's' => self.s.clone(),
-            other => unreachable!("Should never ask for a part value `{other}`"),
+    fn value(&self, attribute: &str) -> Range<u128> {
+        self.bounds
+            .get(attribute)
+            .cloned()
+            .unwrap_or_else(|| unreachable!("Should never ask for a part value `{attribute}`"))
+    }
+
+    fn with(&self, attribute: &str, value: Range<u128>) -> PartRange {
+        let mut bounds = self.bounds.clone();
+        bounds.insert(attribute.to_string(), value);
+        PartRange { bounds }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bounds.values().any(|r| r.is_empty())
+    }
+
+    /// How many parts the range holds, or `None` when that doesn't fit in a `u128`
+    fn combinations(&self) -> Option<u128> {
+        self.bounds
+            .values()
+            .try_fold(1u128, |total, r| total.checked_mul(r.end - r.start))
+    }
+
+    /// Splits the range into the box matching the condition and up to two boxes that don't
+    fn split(&self, condition: &Condition) -> (PartRange, Vec<PartRange>) {
+        let current = self.value(&condition.attribute);
+        let matching = condition.matching();
+
+        let start = current.start.max(matching.start);
+        let end = current.end.min(matching.end).max(start);
+
+        let passing = self.with(&condition.attribute, start..end);
+        let failing = [current.start..start.min(current.end), end..current.end]
+            .into_iter()
+            .map(|r| self.with(&condition.attribute, r))
+            .filter(|r| !r.is_empty())
+            .collect();
+
+        (passing, failing)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    MoreOrEqual,
+    More,
+}
+
+impl Comparison {
+    fn parse(input: &str) -> Result<Comparison, ParseErrorKind> {
+        match input {
+            "<" => Ok(Comparison::Less),
+            "<=" => Ok(Comparison::LessOrEqual),
+            "==" => Ok(Comparison::Equal),
+            ">=" => Ok(Comparison::MoreOrEqual),
+            ">" => Ok(Comparison::More),
+            other => Err(ParseErrorKind::Unknown(other.to_string())),
         }
     }
+}
 
-    fn with(&self, c: char, value: Range<usize>) -> PartRange {
-        match c {
-            'x' => PartRange {
-                x: value,
-                m: self.m.clone(),
-                a: self.a.clone(),
-                s: self.s.clone(),
-            },
-            'm' => PartRange {
-                x: self.x.clone(),
-                m: value,
-                a: self.a.clone(),
-                s: self.s.clone(),
-            },
-            'a' => PartRange {
-                x: self.x.clone(),
-                m: self.m.clone(),
-                a: value,
-                s: self.s.clone(),
-            },
-            's' => PartRange {
-                x: self.x.clone(),
-                m: self.m.clone(),
-                a: self.a.clone(),
-                s: value,
-            },
-            other => unreachable!("Should never ask for a part value `{other}`"),
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "==",
+            Comparison::MoreOrEqual => ">=",
+            Comparison::More => ">",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+#[derive(Debug)]
+struct Condition {
+    attribute: String,
+    comparison: Comparison,
+    threshold: u64,
+}
+
+impl Condition {
+    fn test(&self, part: &Part) -> bool {
+        let value = part.value(&self.attribute);
+        match self.comparison {
+            Comparison::Less => value < self.threshold,
+            Comparison::LessOrEqual => value <= self.threshold,
+            Comparison::Equal => value == self.threshold,
+            Comparison::MoreOrEqual => value >= self.threshold,
+            Comparison::More => value > self.threshold,
         }
     }
 
-    /// Splits the range into start..treshold (exclusive) and threshold..end (exclusive)
-    fn split(&self, c: char, treshold: usize) -> (PartRange, PartRange) {
-        if self.value(c).start >= treshold {
-            (self.with(c, 0..0), self.clone())
-        } else if self.value(c).end <= treshold {
-            (self.clone(), self.with(c, 0..0))
-        } else {
-            (
-                self.with(c, self.value(c).start..treshold),
-                self.with(c, treshold..self.value(c).end),
-            )
+    /// The half-open range of values that satisfy the condition
+    fn matching(&self) -> Range<u128> {
+        let t = u128::from(self.threshold);
+        let end = u128::from(u64::MAX) + 1;
+        match self.comparison {
+            Comparison::Less => 0..t,
+            Comparison::LessOrEqual => 0..t + 1,
+            Comparison::Equal => t..t + 1,
+            Comparison::MoreOrEqual => t..end,
+            Comparison::More => t + 1..end,
         }
     }
 }
 
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}{}", self.attribute, self.comparison, self.threshold)
+    }
+}
+
 #[derive(Debug)]
-struct Workflow {
+pub struct Workflow {
     name: String,
     rules: Vec<Rule>,
 }
 
 impl Workflow {
-    fn parse(input: &str) -> Workflow {
-        let (name, rules_str) = input.trim_end_matches('}').split_once('{').unwrap();
-        let rules: Vec<Rule> = rules_str.split(',').map(Rule::parse).collect();
+    fn parse(input: &str) -> Result<Workflow, ParseErrorKind> {
+        let (name, rules_str) = input
+            .strip_suffix('}')
+            .and_then(|s| s.split_once('{'))
+            .filter(|(name, _)| NAME_REGEX.is_match(name))
+            .ok_or(ParseErrorKind::Missing("`<name>{<rules>}`"))?;
+        let rules: Vec<Rule> = rules_str
+            .split(',')
+            .map(Rule::parse)
+            .collect::<Result<_, _>>()?;
+
+        // Parts that fail every condition need a rule that always applies
+        if let Some(Rule::Condition(..)) = rules.last() {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Workflow `{name}` doesn't end with a fallback rule"
+            )));
+        }
 
-        Workflow {
+        Ok(Workflow {
             name: name.to_string(),
             rules,
-        }
+        })
     }
 
     fn test(&self, part: &Part, workflows: &HashMap<String, Workflow>) -> bool {
@@ -126,6 +237,32 @@ impl Workflow {
             .expect("Expected a workflow to yield a definite result")
     }
 
+    /// Follows a part through this workflow and every workflow it is sent to,
+    /// recording each rule that was evaluated along the way.
+    pub fn trace(&self, part: &Part, workflows: &HashMap<String, Workflow>) -> Vec<TraceStep> {
+        let mut steps = Vec::new();
+        let mut current = self;
+
+        'workflows: loop {
+            for (rule_index, rule) in current.rules.iter().enumerate() {
+                let step = rule.trace_step(&current.name, rule_index, part);
+                let outcome = step.outcome.clone();
+                steps.push(step);
+
+                match outcome {
+                    Outcome::Continue => {}
+                    Outcome::Jump(wf) => {
+                        current = &workflows[wf.as_str()];
+                        continue 'workflows;
+                    }
+                    Outcome::Accept | Outcome::Reject => return steps,
+                }
+            }
+
+            unreachable!("Expected a workflow to yield a definite result");
+        }
+    }
+
     fn test_range(
         &self,
         range: PartRange,
@@ -151,59 +288,92 @@ impl Workflow {
     }
 }
 
+impl Display for Workflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{{{}}}", self.name, self.rules.iter().join(","))
+    }
+}
+
 #[derive(Debug)]
 enum Rule {
-    ConditionLess(char, usize, Box<Rule>),
-    ConditionMore(char, usize, Box<Rule>),
+    Condition(Condition, Box<Rule>),
     WorkflowRef(String),
     Result(bool),
 }
 
+/// What happened to a part when a single rule was evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The condition did not match, evaluation moves on to the next rule
+    Continue,
+    /// The part was sent to another workflow
+    Jump(String),
+    Accept,
+    Reject,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Continue => write!(f, "continue"),
+            Outcome::Jump(wf) => write!(f, "{wf}"),
+            Outcome::Accept => write!(f, "A"),
+            Outcome::Reject => write!(f, "R"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub workflow: String,
+    pub rule_index: usize,
+    /// `None` for the unconditional fallback rule at the end of a workflow
+    pub condition: Option<String>,
+    pub outcome: Outcome,
+}
+
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let condition = self.condition.as_deref().unwrap_or("*");
+        write!(
+            f,
+            "{}[{}] {} -> {}",
+            self.workflow, self.rule_index, condition, self.outcome
+        )
+    }
+}
+
 struct RangeTest {
     next: Vec<PartRange>,
     passing: Vec<PartRange>,
 }
 
 impl Rule {
-    fn parse(input: &str) -> Rule {
-        use Rule::*;
+    fn parse(input: &str) -> Result<Rule, ParseErrorKind> {
+        if let Some(captures) = CONDITION_REGEX.captures(input) {
+            let condition = Condition {
+                attribute: captures[1].to_string(),
+                comparison: Comparison::parse(&captures[2])?,
+                threshold: parse::number(&captures[3])?,
+            };
+            return Ok(Rule::Condition(
+                condition,
+                Box::new(Rule::parse(&captures[4])?),
+            ));
+        }
 
         match input {
-            s if s.contains('<') => {
-                let (char, rest) = s.split_once('<').unwrap();
-                let (threshold, result) = rest.split_once(':').unwrap();
-                ConditionLess(
-                    char.chars().next().unwrap(),
-                    threshold.parse().unwrap(),
-                    Box::new(Rule::parse(result)),
-                )
-            }
-            s if s.contains('>') => {
-                let (char, rest) = s.split_once('>').unwrap();
-                let (threshold, result) = rest.split_once(':').unwrap();
-                ConditionMore(
-                    char.chars().next().unwrap(),
-                    threshold.parse().unwrap(),
-                    Box::new(Rule::parse(result)),
-                )
-            }
-            "R" => Result(false),
-            "A" => Result(true),
-            wf => WorkflowRef(wf.to_string()),
+            "R" => Ok(Rule::Result(false)),
+            "A" => Ok(Rule::Result(true)),
+            wf if NAME_REGEX.is_match(wf) => Ok(Rule::WorkflowRef(wf.to_string())),
+            other => Err(ParseErrorKind::Unknown(other.to_string())),
         }
     }
 
     fn test(&self, part: &Part, workflows: &HashMap<String, Workflow>) -> Option<bool> {
         match self {
-            Rule::ConditionLess(char, threshold, if_passing) => {
-                if part.value(*char) < *threshold {
-                    if_passing.test(part, workflows)
-                } else {
-                    None
-                }
-            }
-            Rule::ConditionMore(char, threshold, if_passing) => {
-                if part.value(*char) > *threshold {
+            Rule::Condition(condition, if_passing) => {
+                if condition.test(part) {
                     if_passing.test(part, workflows)
                 } else {
                     None
@@ -214,34 +384,40 @@ impl Rule {
         }
     }
 
-    fn test_range(&self, range: PartRange, workflows: &HashMap<String, Workflow>) -> RangeTest {
+    fn outcome(&self) -> Outcome {
         match self {
-            Rule::ConditionLess(char, threshold, if_passing) => {
-                let (l, m) = range.split(*char, *threshold);
-
-                let if_passing_test = if_passing.test_range(l, workflows);
-
-                let mut next = Vec::new();
-                next.push(m);
-                for i in if_passing_test.next {
-                    next.push(i);
-                }
+            Rule::Condition(_, if_passing) => if_passing.outcome(),
+            Rule::WorkflowRef(wf) => Outcome::Jump(wf.to_string()),
+            Rule::Result(true) => Outcome::Accept,
+            Rule::Result(false) => Outcome::Reject,
+        }
+    }
 
-                RangeTest {
-                    passing: if_passing_test.passing,
-                    next,
-                }
-            }
-            Rule::ConditionMore(char, threshold, if_passing) => {
-                let (l, m) = range.split(*char, *threshold + 1);
+    fn trace_step(&self, workflow: &str, rule_index: usize, part: &Part) -> TraceStep {
+        let (condition, passed) = match self {
+            Rule::Condition(condition, _) => (Some(condition.to_string()), condition.test(part)),
+            _ => (None, true),
+        };
+
+        TraceStep {
+            workflow: workflow.to_string(),
+            rule_index,
+            condition,
+            outcome: if passed {
+                self.outcome()
+            } else {
+                Outcome::Continue
+            },
+        }
+    }
 
-                let if_passing_test = if_passing.test_range(m, workflows);
+    fn test_range(&self, range: PartRange, workflows: &HashMap<String, Workflow>) -> RangeTest {
+        match self {
+            Rule::Condition(condition, if_passing) => {
+                let (matching, mut next) = range.split(condition);
 
-                let mut next = Vec::new();
-                next.push(l);
-                for i in if_passing_test.next {
-                    next.push(i);
-                }
+                let if_passing_test = if_passing.test_range(matching, workflows);
+                next.extend(if_passing_test.next);
 
                 RangeTest {
                     passing: if_passing_test.passing,
@@ -271,40 +447,184 @@ impl Rule {
     }
 }
 
-fn parse(input: &str) -> (Vec<Workflow>, Vec<Part>) {
-    let split = DOUBLE_LINE_REGEX.split(input).collect_vec();
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Condition(condition, if_passing) => write!(f, "{condition}:{if_passing}"),
+            Rule::WorkflowRef(wf) => write!(f, "{wf}"),
+            Rule::Result(true) => write!(f, "A"),
+            Rule::Result(false) => write!(f, "R"),
+        }
+    }
+}
 
-    let workflows: Vec<Workflow> = split.get(0).unwrap().lines().map(Workflow::parse).collect();
+/// The workflows and the parts to sort with them, printed the way `parse` reads them
+#[derive(Debug)]
+struct System {
+    workflows: Vec<Workflow>,
+    parts: Vec<Part>,
+}
 
-    let parts: Vec<Part> = split.get(1).unwrap().lines().map(Part::parse).collect();
+impl Display for System {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\n\n{}",
+            self.workflows.iter().join("\n"),
+            self.parts.iter().join("\n")
+        )
+    }
+}
 
-    return (workflows, parts);
+fn parse(input: &str) -> Result<System, ParseError> {
+    let (workflows, parts) = match DOUBLE_LINE_REGEX.split(input).collect_vec()[..] {
+        [workflows, parts] => (workflows, parts),
+        [_, _, extra, ..] => {
+            return Err(
+                ParseErrorKind::Invalid("Expected only workflows and parts".to_string())
+                    .at(parse::line_of(input, extra)),
+            );
+        }
+        _ => {
+            return Err(ParseErrorKind::Missing("an empty line before the parts")
+                .at(input.lines().count() + 1));
+        }
+    };
+    // Parts are numbered after the workflows and the empty line
+    let parts_offset = parse::line_of(input, parts) - 1;
+
+    let workflows = parse::lines(workflows, Workflow::parse)?;
+    let parts = parse::lines(parts, Part::parse).map_err(|e| e.offset(parts_offset))?;
+
+    let names = workflows.iter().map(|wf| wf.name.as_str()).collect_vec();
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(ParseErrorKind::Duplicate(name.to_string()).at(i + 1));
+        }
+    }
+    if !names.contains(&"in") {
+        return Err(ParseErrorKind::Missing("an `in` workflow").at(workflows.len() + 1));
+    }
+
+    for (i, wf) in workflows.iter().enumerate() {
+        let missing = wf.rules.iter().find_map(|rule| match rule.outcome() {
+            Outcome::Jump(wf) if !names.contains(&wf.as_str()) => Some(wf),
+            _ => None,
+        });
+        if let Some(wf) = missing {
+            return Err(ParseErrorKind::Unknown(wf.to_string()).at(i + 1));
+        }
+    }
+
+    // Rules may only test attributes that every part is rated on
+    for attribute in attributes(&workflows, &[]) {
+        let unrated = parts
+            .iter()
+            .position(|p| !p.ratings.iter().any(|(a, _)| *a == attribute));
+        if let Some(i) = unrated {
+            return Err(
+                ParseErrorKind::Invalid(format!("Part isn't rated on `{attribute}`"))
+                    .at(parts_offset + i + 1),
+            );
+        }
+    }
+
+    return Ok(System { workflows, parts });
 }
 
-pub fn part1(input: &str) -> usize {
-    let (workflows, parts) = parse(input);
+/// Every attribute name mentioned by a rule or rated on a part
+fn attributes(workflows: &[Workflow], parts: &[Part]) -> BTreeSet<String> {
+    let from_rules =
+        workflows
+            .iter()
+            .flat_map(|wf| wf.rules.iter())
+            .filter_map(|rule| match rule {
+                Rule::Condition(condition, _) => Some(condition.attribute.to_string()),
+                _ => None,
+            });
+    let from_parts = parts
+        .iter()
+        .flat_map(|p| p.ratings.iter().map(|(attribute, _)| attribute.to_string()));
+
+    from_rules.chain(from_parts).collect()
+}
+
+pub fn part1(input: &str) -> u128 {
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every part sorted through the workflows, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u128, TimedOut> {
+    let System { workflows, parts } = parse(input).unwrap();
     let workflow_map: HashMap<String, Workflow> = workflows
         .into_iter()
         .map(|wf| (wf.name.to_string(), wf))
         .collect();
     let start_wf = &workflow_map["in"];
 
-    println!("Workflows: {:?}", workflow_map.values());
-    println!("Parts: {parts:?}");
+    debug!(workflows = ?workflow_map.values());
+    debug!(?parts);
 
-    let passing_parts = parts
-        .iter()
-        .filter(|p| start_wf.test(*p, &workflow_map))
-        .collect_vec();
+    progress.start("Parts", Some(parts.len() as u64));
+    let mut passing_parts = vec![];
+    for (i, part) in parts.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} parts sorted", parts.len()))?;
+        if start_wf.test(part, &workflow_map) {
+            passing_parts.push(part);
+        }
+        progress.advance(1);
+    }
+    progress.finish();
 
-    println!("Passing Parts: {passing_parts:?}");
+    debug!(?passing_parts);
 
-    let result = passing_parts.iter().map(|p| p.x + p.m + p.a + p.s).sum();
-    return result;
+    let result: u128 = passing_parts.iter().map(|p| p.total()).sum();
+    return Ok(result);
 }
 
-pub fn part2(input: &str) -> usize {
-    let (workflows, _) = parse(input);
+/// Renders the path a part took through the workflows, one evaluated rule per line
+pub fn render_trace(part: &Part, steps: &[TraceStep]) -> String {
+    let mut output = format!("{part}\n");
+
+    for step in steps {
+        output.push_str(&format!("  {step}\n"));
+    }
+
+    return output;
+}
+
+/// Traces every part of the input through the workflows starting at `in`
+pub fn explain(input: &str) -> Vec<(Part, Vec<TraceStep>)> {
+    let System { workflows, parts } = parse(input).unwrap();
+    let workflow_map: HashMap<String, Workflow> = workflows
+        .into_iter()
+        .map(|wf| (wf.name.to_string(), wf))
+        .collect();
+    let start_wf = &workflow_map["in"];
+
+    parts
+        .into_iter()
+        .map(|p| {
+            let steps = start_wf.trace(&p, &workflow_map);
+            (p, steps)
+        })
+        .collect()
+}
+
+/// Counts the rating combinations accepted by the workflows, where every attribute
+/// discovered in the input ranges over the inclusive bounds returned by `bounds`.
+/// Fails when the input doesn't parse or the count overflows a `u128`
+pub fn accepted_combinations(
+    input: &str,
+    bounds: impl Fn(&str) -> RangeInclusive<u64>,
+) -> Result<u128, String> {
+    let System { workflows, parts } = parse(input)?;
+    let attributes = attributes(&workflows, &parts);
     let workflow_map: HashMap<String, Workflow> = workflows
         .into_iter()
         .map(|wf| (wf.name.to_string(), wf))
@@ -312,25 +632,58 @@ pub fn part2(input: &str) -> usize {
 
     let start_wf = &workflow_map["in"];
     let start_range = PartRange {
-        x: 1..4001,
-        m: 1..4001,
-        a: 1..4001,
-        s: 1..4001,
+        bounds: attributes
+            .into_iter()
+            .map(|attribute| {
+                let range = bounds(&attribute);
+                let range = u128::from(*range.start())..u128::from(*range.end()) + 1;
+                (attribute, range)
+            })
+            .collect(),
     };
 
     let results = start_wf.test_range(start_range, &workflow_map);
 
-    let result: usize = results
+    return results
         .iter()
-        .map(|r| {
-            (r.x.end - r.x.start)
-                * (r.m.end - r.m.start)
-                * (r.a.end - r.a.start)
-                * (r.s.end - r.s.start)
+        .try_fold(0u128, |total, range| {
+            range.combinations().and_then(|c| total.checked_add(c))
         })
-        .sum();
+        .ok_or_else(|| "Accepted combinations overflow u128".to_string());
+}
+
+pub fn part2(input: &str) -> usize {
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Splits the ratings through the workflows in one go, unless `cancel` stopped it before
+/// it began
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    progress.start("Rating ranges", Some(1));
+    cancel.check(|| "No rating ranges split".to_string())?;
+    let result = accepted_combinations(input, |_| 1..=4000).unwrap() as usize;
+    progress.advance(1);
+    progress.finish();
+
+    return Ok(result);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(system) = parse(input) {
+        let printed = system.to_string();
+        assert_eq!(parse(&printed).map(|s| s.to_string()), Ok(printed));
+    }
 
-    return result;
+    if let Ok(part) = Part::parse(input) {
+        let printed = part.to_string();
+        assert_eq!(Part::parse(&printed).map(|p| p.to_string()), Ok(printed));
+    }
 }
 
 pub fn process(input: String) {
@@ -344,6 +697,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "px{a<2006:qkq,m>2090:A,rfg}
 pv{a>1716:R,A}
@@ -376,6 +730,84 @@ hdj{m>838:A,pv}
         assert_eq!(result, 362930);
     }
 
+    #[test]
+    fn trace_example() {
+        let explained = explain(EXAMPLE);
+        let (part, steps) = &explained[0];
+
+        assert_eq!(
+            render_trace(part, steps),
+            "{x=787,m=2655,a=1222,s=2876}
+  in[0] s<1351 -> continue
+  in[1] * -> qqz
+  qqz[0] s>2770 -> qs
+  qs[0] s>3448 -> continue
+  qs[1] * -> lnx
+  lnx[0] m>1548 -> A
+"
+        );
+
+        let outcomes = explained
+            .iter()
+            .map(|(_, steps)| steps.last().unwrap().outcome.clone())
+            .collect_vec();
+        assert_eq!(
+            outcomes,
+            vec![
+                Outcome::Accept,
+                Outcome::Reject,
+                Outcome::Accept,
+                Outcome::Reject,
+                Outcome::Accept
+            ]
+        );
+    }
+
+    #[test]
+    fn accepted_combinations_custom_attributes() {
+        let input = "in{w>=10:lo,w==5:A,R}
+lo{h<=2:A,R}
+
+{w=5,h=9}";
+
+        assert_eq!(accepted_combinations(input, |_| 1..=20), Ok(11 * 2 + 20));
+        assert_eq!(
+            accepted_combinations(input, |a| if a == "w" { 0..=9 } else { 1..=3 }),
+            Ok(3)
+        );
+        assert_eq!(part1(input), 14);
+    }
+
+    #[test]
+    fn accepted_combinations_reach_the_largest_rating() {
+        let top = |_: &str| u64::MAX - 2..=u64::MAX;
+
+        let input = "in{w>=18446744073709551614:A,R}\n\n{w=1}";
+        assert_eq!(accepted_combinations(input, top), Ok(2));
+        let input = "in{w>18446744073709551614:A,R}\n\n{w=1}";
+        assert_eq!(accepted_combinations(input, top), Ok(1));
+        let input = "in{w<=18446744073709551615:A,R}\n\n{w=1}";
+        assert_eq!(accepted_combinations(input, top), Ok(3));
+    }
+
+    #[test]
+    fn accepted_combinations_report_overflows_and_bad_input() {
+        let input = "in{a>0:A,b>0:A,c>0:A,R}\n\n{a=1,b=1,c=1}";
+        let top = |_: &str| 0..=u64::MAX;
+        assert_eq!(
+            accepted_combinations(input, top),
+            Err("Accepted combinations overflow u128".to_string())
+        );
+        assert_eq!(accepted_combinations(input, |_| 0..=9), Ok(1000 - 1));
+        assert!(accepted_combinations("in{a>0:A,R}", top).is_err());
+    }
+
+    #[test]
+    fn totals_pass_the_largest_rating() {
+        let input = "in{x>0:A,R}\n\n{x=18446744073709551615,m=1}\n{x=18446744073709551615,m=0}";
+        assert_eq!(part1(input), 2 * u128::from(u64::MAX) + 1);
+    }
+
     #[test]
     fn part2_example() {
         let result = part2(EXAMPLE);
@@ -388,4 +820,99 @@ hdj{m>838:A,pv}
         let result = part2(input);
         assert_eq!(result, 116365820987729);
     }
+
+    #[test]
+    fn parse_rejects_malformed_systems() {
+        let parse_with = |workflows: &str, parts: &str| parse(&format!("{workflows}\n\n{parts}"));
+
+        use ParseErrorKind::*;
+        let rejection = |workflows, parts| parse_with(workflows, parts).err();
+
+        assert_eq!(
+            parse("in{A}").err(),
+            Some(Missing("an empty line before the parts").at(2))
+        );
+        assert!(matches!(
+            rejection("in{x<5:A}", "{x=1}"),
+            Some(ParseError {
+                line: 1,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            rejection("in{x<5:A,R", "{x=1}"),
+            Some(Missing("`<name>{<rules>}`").at(1))
+        );
+        assert_eq!(
+            rejection("in{x<5:A,foo}", "{x=1}"),
+            Some(Unknown("foo".to_string()).at(1))
+        );
+        assert_eq!(
+            rejection("px{x<5:A,R}", "{x=1}"),
+            Some(Missing("an `in` workflow").at(2))
+        );
+        assert_eq!(
+            rejection("in{x<5:A,R}\nin{R}", "{x=1}"),
+            Some(Duplicate("in".to_string()).at(2))
+        );
+        assert!(matches!(
+            rejection("in{x<5:A,R}", "{x=1}\n{m=1}"),
+            Some(ParseError {
+                line: 4,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            rejection("in{x<5:A,R}", "{x=1,x=2}"),
+            Some(Duplicate("x".to_string()).at(3))
+        );
+        assert_eq!(
+            rejection("in{x<5:A,R}", "{x=-1}"),
+            Some(InvalidNumber("-1".to_string()).at(3))
+        );
+        assert_eq!(
+            rejection("in{x<5:A,R}", "x=1"),
+            Some(Missing("`{` and `}` around the ratings").at(3))
+        );
+        assert_eq!(
+            rejection("in{x<99999999999999999999:A,R}", "{x=1}"),
+            Some(InvalidNumber("99999999999999999999".to_string()).at(1))
+        );
+        assert_eq!(
+            rejection("in{x!5:A,R}", "{x=1}"),
+            Some(Unknown("x!5:A".to_string()).at(1))
+        );
+        assert!(matches!(
+            parse("in{R}\n\n{x=1}\n\n{x=2}"),
+            Err(ParseError {
+                line: 5,
+                kind: Invalid(_)
+            })
+        ));
+        assert!(parse_with("in{x<5:A,R}", "{x=1}").is_ok());
+    }
+
+    #[test]
+    fn system_round_trips() {
+        for input in [EXAMPLE, include_str!("input.txt")] {
+            assert_eq!(parse(input).unwrap().to_string(), input);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_systems(
+            input in "([a-z]{1,2}\\{([xm](<|>|<=|==)[0-9]{1,4}:([a-z]{1,2}|A|R),){0,3}([a-z]{1,2}|A|R)\\}\n){1,4}\n(\\{x=[0-9]{1,4},m=[0-9]{1,4}\\}\n){0,3}"
+        ) {
+            if let Ok(system) = parse(&input) {
+                let printed = system.to_string();
+                prop_assert_eq!(parse(&printed).map(|s| s.to_string()), Ok(printed));
+            }
+        }
+
+        #[test]
+        fn part_round_trips(part in "\\{x=[1-9][0-9]{0,3},m=[1-9][0-9]{0,3},a=[0-9],s=[0-9]\\}") {
+            prop_assert_eq!(Part::parse(&part).map(|p| p.to_string()), Ok(part));
+        }
+    }
 }
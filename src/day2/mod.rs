@@ -1,100 +1,467 @@
-struct Game {
-    red: u32,
-    blue: u32,
-    green: u32,
-}
-
-impl Default for Game {
-    fn default() -> Self {
-        Self {
-            red: 0,
-            blue: 0,
-            green: 0,
-        }
+use std::collections::BTreeMap;
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+/// The cubes of each colour shown in one round, in the order they were listed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Round {
+    pub cubes: Vec<(String, u32)>,
+}
+
+impl std::fmt::Display for Round {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dices = self
+            .cubes
+            .iter()
+            .map(|(color, count)| format!("{count} {color}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}", dices.join(", "))
     }
 }
 
-impl Game {
-    pub fn parse(input: &str) -> Self {
-        let mut red = 0;
-        let mut green = 0;
-        let mut blue = 0;
+impl Round {
+    pub fn parse(input: &str) -> Result<Self, ParseErrorKind> {
+        let mut cubes: Vec<(String, u32)> = Vec::new();
 
         for dices in input.split(", ") {
             let split = dices.split_ascii_whitespace().collect::<Vec<_>>();
-            let count: u32 = split.first().unwrap().parse().unwrap();
-            match split.last().unwrap() {
-                &"red" => red = count,
-                &"green" => green = count,
-                &"blue" => blue = count,
-                _ => (),
+            let [count, color] = split[..] else {
+                return Err(ParseErrorKind::Missing("a count and a color"));
+            };
+            let count: u32 = parse::number(count)?;
+            if count == 0 {
+                return Err(ParseErrorKind::InvalidNumber(count.to_string()));
+            }
+            if !color.chars().all(char::is_alphabetic) {
+                return Err(ParseErrorKind::Unknown(color.to_string()));
             }
+            if cubes.iter().any(|(c, _)| c == color) {
+                return Err(ParseErrorKind::Duplicate(color.to_string()));
+            }
+            cubes.push((color.to_string(), count));
         }
 
-        return Self { red, green, blue };
+        return Ok(Self { cubes });
+    }
+
+    /// How many cubes of `color` were shown, 0 if it wasn't
+    pub fn count(&self, color: &str) -> u32 {
+        self.cubes
+            .iter()
+            .find(|(c, _)| c == color)
+            .map_or(0, |(_, count)| *count)
     }
+}
 
-    pub fn check(&self) -> bool {
-        return self.red <= 12 && self.green <= 13 && self.blue <= 14;
+/// A game record with every round drawn from the bag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub id: u32,
+    pub rounds: Vec<Round>,
+}
+
+impl std::fmt::Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rounds = self.rounds.iter().map(Round::to_string).collect::<Vec<_>>();
+        write!(f, "Game {}: {}", self.id, rounds.join("; "))
     }
 }
 
-pub fn part2(input: &str) -> u32 {
-    let result: u32 = input
-        .lines()
-        .map(|row| {
-            let split = row.split(": ").collect::<Vec<_>>();
+impl Game {
+    pub fn parse(row: &str) -> Result<Self, ParseErrorKind> {
+        let (id, rounds) = row
+            .split_once(": ")
+            .ok_or(ParseErrorKind::Missing("`: `"))?;
+        let id = id
+            .trim()
+            .strip_prefix("Game ")
+            .ok_or(ParseErrorKind::Missing("`Game <id>`"))?;
+        let id: u32 = parse::number(id)?;
+
+        let rounds = rounds
+            .split(";")
+            .map(Round::parse)
+            .collect::<Result<_, _>>()?;
 
-            let games = split
-                .last()
-                .unwrap()
-                .split(";")
-                .map(Game::parse)
-                .collect::<Vec<_>>();
+        return Ok(Game { id, rounds });
+    }
 
-            let max_game = games.iter().fold(Game::default(), |a, b| Game {
-                red: a.red.max(b.red),
-                blue: a.blue.max(b.blue),
-                green: a.green.max(b.green),
-            });
+    /// The fewest cubes of every colour that could have played the game
+    pub fn minimum_bag(&self) -> Bag {
+        let mut cubes = BTreeMap::new();
+        for (color, count) in self.rounds.iter().flat_map(|r| &r.cubes) {
+            let max = cubes.entry(color.clone()).or_insert(0);
+            *max = (*max).max(*count);
+        }
+
+        return Bag { cubes };
+    }
+}
+
+/// How many cubes of each colour a bag holds
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bag {
+    pub cubes: BTreeMap<String, u32>,
+}
+
+impl std::fmt::Display for Bag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dices = self
+            .cubes
+            .iter()
+            .map(|(color, count)| format!("{count} {color}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}", dices.join(", "))
+    }
+}
+
+impl Bag {
+    pub fn count(&self, color: &str) -> u32 {
+        self.cubes.get(color).copied().unwrap_or(0)
+    }
+
+    /// The product of the counts of `colors`, 0 if the bag lacks one of them
+    pub fn power(&self, colors: &[&str]) -> u64 {
+        colors.iter().map(|c| u64::from(self.count(c))).product()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Op {
+    const ALL: [(&'static str, Op); 6] = [
+        ("<", Op::Less),
+        ("<=", Op::LessOrEqual),
+        ("=", Op::Equal),
+        ("!=", Op::NotEqual),
+        (">=", Op::GreaterOrEqual),
+        (">", Op::Greater),
+    ];
+
+    fn apply(&self, a: u32, b: u32) -> bool {
+        match self {
+            Op::Less => a < b,
+            Op::LessOrEqual => a <= b,
+            Op::Equal => a == b,
+            Op::NotEqual => a != b,
+            Op::GreaterOrEqual => a >= b,
+            Op::Greater => a > b,
+        }
+    }
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (symbol, _) = Op::ALL.iter().find(|(_, op)| op == self).unwrap();
+        write!(f, "{symbol}")
+    }
+}
+
+/// A bound on the cubes of one colour, like `red<=12`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub color: String,
+    pub op: Op,
+    pub count: u32,
+}
+
+impl Constraint {
+    fn parse(input: &str) -> Result<Self, ParseErrorKind> {
+        let input = input.trim();
+        let start = input
+            .find(['<', '>', '=', '!'])
+            .ok_or(ParseErrorKind::Missing("a comparison"))?;
+        let end = input[start..]
+            .find(|c| !"<>=!".contains(c))
+            .map_or(input.len(), |i| start + i);
+
+        let color = input[..start].trim();
+        if color.is_empty() || !color.chars().all(char::is_alphabetic) {
+            return Err(ParseErrorKind::Missing("a color"));
+        }
+        let op = Op::ALL
+            .iter()
+            .find(|(symbol, _)| *symbol == &input[start..end])
+            .map(|(_, op)| *op)
+            .ok_or_else(|| ParseErrorKind::Unknown(input[start..end].to_string()))?;
+        let count = parse::number(input[end..].trim())?;
+
+        return Ok(Constraint {
+            color: color.to_string(),
+            op,
+            count,
+        });
+    }
+}
 
-            return max_game.red * max_game.green * max_game.blue;
+impl std::fmt::Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}{}", self.color, self.op, self.count)
+    }
+}
+
+/// Constraints joined with `&`, and alternatives of those joined with `|`, like
+/// `red<=12 & green<=13 | blue>20`. `&` binds tighter than `|`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub any_of: Vec<Vec<Constraint>>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, ParseErrorKind> {
+        let any_of = input
+            .split('|')
+            .map(|all_of| all_of.split('&').map(Constraint::parse).collect())
+            .collect::<Result<_, _>>()?;
+
+        return Ok(Query { any_of });
+    }
+
+    /// Whether a game matches, judged by the fewest cubes that could have played it
+    pub fn matches(&self, game: &Game) -> bool {
+        let bag = game.minimum_bag();
+        self.any_of.iter().any(|all_of| {
+            all_of
+                .iter()
+                .all(|c| c.op.apply(bag.count(&c.color), c.count))
         })
-        .sum();
+    }
+}
 
-    return result;
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let any_of = self
+            .any_of
+            .iter()
+            .map(|all_of| {
+                all_of
+                    .iter()
+                    .map(Constraint::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" & ")
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", any_of.join(" | "))
+    }
+}
+
+/// Every bag over `colors` holding exactly `total` cubes
+pub fn candidates(colors: &[&str], total: u32) -> Vec<Bag> {
+    let Some((color, rest)) = colors.split_first() else {
+        return match total {
+            0 => vec![Bag::default()],
+            _ => vec![],
+        };
+    };
+
+    let mut bags = Vec::new();
+    for count in 0..=total {
+        for mut bag in candidates(rest, total - count) {
+            bag.cubes.insert(color.to_string(), count);
+            bags.push(bag);
+        }
+    }
+
+    return bags;
+}
+
+/// The log of the chance that drawing the cubes of `round` at once from `bag` shows exactly
+/// them, `None` if the bag can't show them
+fn log_likelihood(bag: &Bag, round: &Round) -> Option<f64> {
+    // ln C(n, k), k stays as small as the cubes drawn
+    let ln_choose = |n: u32, k: u32| -> f64 {
+        (0..k)
+            .map(|i| (f64::from(n - i) / f64::from(i + 1)).ln())
+            .sum()
+    };
+
+    let total = bag.cubes.values().sum::<u32>();
+    let drawn = round.cubes.iter().map(|(_, count)| count).sum::<u32>();
+    if drawn > total {
+        return None;
+    }
+
+    let mut result = -ln_choose(total, drawn);
+    for (color, count) in &round.cubes {
+        let held = bag.count(color);
+        if *count > held {
+            return None;
+        }
+        result += ln_choose(held, *count);
+    }
+
+    return Some(result);
+}
+
+/// A bag every game could have been played with, and how likely it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consistent {
+    pub bag: Bag,
+    /// The chance of the bag among the consistent ones given the rounds, starting from even odds
+    pub probability: f64,
+}
+
+/// A bag that can't have played the games with these `ids`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuledOut {
+    pub bag: Bag,
+    pub ids: Vec<u32>,
+}
+
+/// The candidates split into the bags consistent with every round, most likely first, and the
+/// ones some games rule out
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inference {
+    pub consistent: Vec<Consistent>,
+    pub ruled_out: Vec<RuledOut>,
+}
+
+/// Weighs every candidate bag against the games. Every round is a handful of cubes drawn at once
+/// from the full bag and put back afterwards
+pub fn infer(games: &[Game], candidates: Vec<Bag>) -> Inference {
+    let mut inference = Inference::default();
+    let mut log_likelihoods = Vec::new();
+
+    for bag in candidates {
+        let mut ids = Vec::new();
+        let mut log_likelihood_sum = 0.0;
+        for game in games {
+            let rounds = game.rounds.iter().map(|round| log_likelihood(&bag, round));
+            match rounds.sum::<Option<f64>>() {
+                Some(l) => log_likelihood_sum += l,
+                None => ids.push(game.id),
+            }
+        }
+
+        if ids.is_empty() {
+            log_likelihoods.push(log_likelihood_sum);
+            inference.consistent.push(Consistent {
+                bag,
+                probability: 0.0,
+            });
+        } else {
+            inference.ruled_out.push(RuledOut { bag, ids });
+        }
+    }
+
+    // Normalised from the most likely bag so tiny likelihoods don't round to 0 before dividing
+    let max = log_likelihoods
+        .iter()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let weights = log_likelihoods
+        .iter()
+        .map(|l| (l - max).exp())
+        .collect::<Vec<_>>();
+    let sum = weights.iter().sum::<f64>();
+    for (consistent, weight) in inference.consistent.iter_mut().zip(weights) {
+        consistent.probability = weight / sum;
+    }
+    inference
+        .consistent
+        .sort_by(|a, b| b.probability.total_cmp(&a.probability));
+
+    return inference;
+}
+
+/// The bag of part 1, games that drew more than it holds are impossible
+pub const PART1_QUERY: &str = "red<=12 & green<=13 & blue<=14";
+
+/// Every game record
+pub fn parse(input: &str) -> Result<Vec<Game>, ParseError> {
+    parse::lines(input, Game::parse)
+}
+
+/// The ids of the games of `input` matching `query`
+pub fn query(input: &str, query: &str) -> Result<Vec<u32>, String> {
+    let query = Query::parse(query).map_err(|kind| kind.to_string())?;
+    let games = parse(input)?;
+
+    return Ok(games
+        .iter()
+        .filter(|game| query.matches(game))
+        .map(|game| game.id)
+        .collect());
+}
+
+pub fn part2(input: &str) -> u64 {
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every game whose minimum bag was found, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, TimedOut> {
+    let games = parse(input).unwrap();
+
+    progress.start("Games", Some(games.len() as u64));
+    let mut result: u64 = 0;
+    for (i, game) in games.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} games powered", games.len()))?;
+        result += game.minimum_bag().power(&["red", "green", "blue"]);
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(result);
 }
 
 pub fn part1(input: &str) -> u32 {
-    let result: u32 = input
-        .lines()
-        .map(|row| {
-            let split1 = row.split(": ").collect::<Vec<_>>();
-            let game_id: u32 = split1
-                .first()
-                .unwrap()
-                .split_ascii_whitespace()
-                .last()
-                .unwrap()
-                .parse()
-                .unwrap();
-
-            let games = split1
-                .last()
-                .unwrap()
-                .split(";")
-                .map(Game::parse)
-                .collect::<Vec<_>>();
-
-            return if games.iter().all(|g| g.check()) {
-                game_id
-            } else {
-                0
-            };
-        })
-        .sum();
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every game matched against the part 1 query, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u32, TimedOut> {
+    let query = Query::parse(PART1_QUERY).unwrap();
+    let games = parse(input).unwrap();
 
-    return result;
+    progress.start("Games", Some(games.len() as u64));
+    let mut result: u32 = 0;
+    for (i, game) in games.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} games queried", games.len()))?;
+        if query.matches(game) {
+            result += game.id;
+        }
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(result);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(games) = parse(input) {
+        for game in games {
+            let printed = game.to_string();
+            assert_eq!(Game::parse(&printed).map(|g| g.to_string()), Ok(printed));
+        }
+    }
+
+    if let Ok(round) = Round::parse(input) {
+        let printed = round.to_string();
+        assert_eq!(Round::parse(&printed).map(|r| r.to_string()), Ok(printed));
+    }
+
+    if let Ok(query) = Query::parse(input) {
+        assert_eq!(Query::parse(&query.to_string()), Ok(query));
+    }
 }
 
 pub fn process(input: String) {
@@ -105,6 +472,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -141,4 +509,147 @@ Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
         let result = part2(input);
         assert_eq!(result, 56580);
     }
+
+    #[test]
+    fn parse_rejects_malformed_draws() {
+        use ParseErrorKind::*;
+
+        assert_eq!(
+            Round::parse(" 3 pur-ple"),
+            Err(Unknown("pur-ple".to_string()))
+        );
+        assert_eq!(
+            Round::parse(" 3 red, 4 red"),
+            Err(Duplicate("red".to_string()))
+        );
+        assert_eq!(Round::parse(" red"), Err(Missing("a count and a color")));
+        assert_eq!(Round::parse(""), Err(Missing("a count and a color")));
+        assert_eq!(Round::parse(" 0 red"), Err(InvalidNumber("0".to_string())));
+        assert_eq!(
+            parse("Game 1: 2 green\nGame 2: 2 green;"),
+            Err(Missing("a count and a color").at(2))
+        );
+        assert_eq!(
+            parse("Game x: 2 green"),
+            Err(InvalidNumber("x".to_string()).at(1))
+        );
+    }
+
+    #[test]
+    fn games_keep_every_round_and_color() {
+        let game = Game::parse("Game 7: 3 teal, 4 red; 1 red, 2 mauve").unwrap();
+
+        assert_eq!(game.id, 7);
+        assert_eq!(game.rounds.len(), 2);
+        assert_eq!(game.rounds[1].count("mauve"), 2);
+        assert_eq!(game.rounds[1].count("teal"), 0);
+        assert_eq!(game.to_string(), "Game 7: 3 teal, 4 red; 1 red, 2 mauve");
+
+        let bag = game.minimum_bag();
+        assert_eq!(bag.to_string(), "2 mauve, 4 red, 3 teal");
+        assert_eq!(bag.power(&["red", "teal"]), 12);
+        assert_eq!(bag.power(&["red", "blue"]), 0);
+    }
+
+    #[test]
+    fn queries_filter_games() {
+        let input = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+
+        assert_eq!(query(input, PART1_QUERY), Ok(vec![1, 5]));
+        assert_eq!(query(input, "red>10 | blue=2"), Ok(vec![3, 5]));
+        assert_eq!(query(input, " green != 2 & red < 20 "), Ok(vec![5]));
+        assert_eq!(query(input, "purple>=1"), Ok(vec![]));
+        assert_eq!(query(input, "purple<1"), Ok(vec![1, 3, 5]));
+
+        use ParseErrorKind::*;
+
+        assert_eq!(Query::parse("red"), Err(Missing("a comparison")));
+        assert_eq!(Query::parse("<=12"), Err(Missing("a color")));
+        assert_eq!(Query::parse("red=>12"), Err(Unknown("=>".to_string())));
+        assert_eq!(
+            Query::parse("red<=twelve"),
+            Err(InvalidNumber("twelve".to_string()))
+        );
+        assert_eq!(Query::parse("red<=12 &"), Err(Missing("a comparison")));
+    }
+
+    #[test]
+    fn inference_rules_out_bags_and_ranks_the_rest() {
+        let games = parse("Game 1: 1 red\nGame 2: 1 red; 1 blue").unwrap();
+        let inference = infer(&games, candidates(&["red", "blue"], 3));
+
+        let ruled_out = inference
+            .ruled_out
+            .iter()
+            .map(|r| (r.bag.to_string(), r.ids.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ruled_out,
+            [
+                ("3 blue, 0 red".to_string(), vec![1, 2]),
+                ("0 blue, 3 red".to_string(), vec![2]),
+            ]
+        );
+
+        // 1 red and 2 blue show a red 1 in 3 times, 2 red and 1 blue twice as often
+        let consistent = inference
+            .consistent
+            .iter()
+            .map(|c| (c.bag.to_string(), (c.probability * 1000.0).round()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            consistent,
+            [
+                ("1 blue, 2 red".to_string(), 667.0),
+                ("2 blue, 1 red".to_string(), 333.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn inference_keeps_the_bags_above_the_minimum() {
+        let input = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+        let games = parse(input).unwrap();
+        let inference = infer(&games, candidates(&["red", "green", "blue"], 20));
+
+        // At least 6 red, 3 green and 6 blue leaves 5 cubes to spread over 3 colors
+        assert_eq!(inference.consistent.len(), 21);
+        assert_eq!(inference.ruled_out.len(), 231 - 21);
+        let total = inference
+            .consistent
+            .iter()
+            .map(|c| c.probability)
+            .sum::<f64>();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        let empty = infer(&games, vec![Bag::default()]);
+        assert_eq!(empty.ruled_out[0].ids, [1, 2, 5]);
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_records(input in "(Game [0-9]{1,3}: ([0-9]{1,3} (red|green|blue)(, |; )?){0,6}\n?){0,4}") {
+            let _ = parse(&input);
+        }
+
+        #[test]
+        fn game_round_trips(id: u32, rounds in prop::collection::vec(prop::collection::btree_map("[a-z]{1,8}", 1..100u32, 1..4), 1..5)) {
+            let rounds = rounds
+                .into_iter()
+                .map(|cubes| Round { cubes: cubes.into_iter().collect() })
+                .collect();
+            let game = Game { id, rounds };
+            prop_assert_eq!(Game::parse(&game.to_string()), Ok(game));
+        }
+
+        #[test]
+        fn query_round_trips(query in "[a-z]{1,5}(<|<=|=|!=|>=|>)[0-9]{1,3}(( & | \\| )[a-z]{1,5}(<|<=|=|!=|>=|>)[0-9]{1,3}){0,4}") {
+            let parsed = Query::parse(&query).unwrap();
+            prop_assert_eq!(Query::parse(&parsed.to_string()), Ok(parsed));
+        }
+    }
 }
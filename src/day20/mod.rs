@@ -1,3 +1,6 @@
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{ParseError, ParseErrorKind};
+use crate::progress::Progress;
 use itertools::Itertools;
 use std::collections::{BTreeMap, VecDeque};
 
@@ -14,7 +17,7 @@ enum ModuleKind {
     FlipFlop { is_on: bool },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 struct Module {
     name: String,
     kind: ModuleKind,
@@ -88,71 +91,101 @@ impl Module {
     }
 }
 
-fn parse(input: &str) -> (BTreeMap<&str, ModuleKind>, BTreeMap<&str, Vec<&str>>) {
+impl std::fmt::Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = match self.kind {
+            ModuleKind::Broadcaster => "",
+            ModuleKind::Conjunction { .. } => "&",
+            ModuleKind::FlipFlop { .. } => "%",
+        };
+        let destinations = self.destinations.join(", ");
+        write!(f, "{prefix}{} -> {destinations}", self.name)
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Every module by name, printed ordered by name the way `parse_modules` reads them
+#[derive(Debug, PartialEq)]
+struct Configuration {
+    modules: BTreeMap<String, Module>,
+}
+
+impl std::fmt::Display for Configuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.modules.values().join("\n"))
+    }
+}
+
+fn parse_modules(input: &str) -> Result<Configuration, ParseError> {
     use ModuleKind::*;
 
-    let nodes = input.lines().map(|line| {
-        let (name_str, dest_str) = line.split_once(" -> ").unwrap();
-        let destinations = dest_str.split(", ").collect_vec();
+    let mut modules = BTreeMap::new();
+
+    for (i, line) in input.lines().enumerate() {
+        let invalid_name =
+            |name: &str| ParseErrorKind::Invalid(format!("`{name}` isn't a module name")).at(i + 1);
+
+        let (name_str, dest_str) = line
+            .split_once(" -> ")
+            .ok_or(ParseErrorKind::Missing("`<module> -> <destinations>`").at(i + 1))?;
+        let destinations = dest_str.split(", ").map(str::to_string).collect_vec();
+
+        if let Some(d) = destinations.iter().find(|d| !is_name(d)) {
+            return Err(invalid_name(d));
+        }
 
         let (name, kind) = match name_str {
-            s if s.starts_with("broadcaster") => (s, Broadcaster),
-            s if s.starts_with("%") => (s.trim_start_matches("%"), FlipFlop { is_on: false }),
+            "broadcaster" => (name_str.to_string(), Broadcaster),
+            s if s.starts_with("%") => (
+                s.trim_start_matches("%").to_string(),
+                FlipFlop { is_on: false },
+            ),
             s if s.starts_with("&") => (
-                s.trim_start_matches("&"),
+                s.trim_start_matches("&").to_string(),
                 Conjunction {
                     memory: BTreeMap::new(),
                 },
             ),
-            _ => unreachable!("Unexpected module kind when parsing"),
+            s => return Err(ParseErrorKind::Unknown(s.to_string()).at(i + 1)),
         };
 
-        return ((name, kind), (name, destinations));
-    });
-
-    let (kinds, destinations): (BTreeMap<_, _>, BTreeMap<_, _>) = nodes.unzip();
+        if !is_name(&name) {
+            return Err(invalid_name(name_str));
+        }
 
-    return (kinds, destinations);
-}
+        let module = Module {
+            name: name.clone(),
+            kind,
+            destinations,
+        };
+        if modules.insert(name, module).is_some() {
+            return Err(ParseErrorKind::Duplicate(name_str.to_string()).at(i + 1));
+        }
+    }
 
-fn parse_modules(input: &str) -> BTreeMap<String, Module> {
-    use ModuleKind::*;
+    // The button needs a broadcaster to send pulses to
+    if !modules.contains_key("broadcaster") {
+        return Err(ParseErrorKind::Missing("a `broadcaster`").at(input.lines().count() + 1));
+    }
 
-    input
-        .lines()
-        .map(|line| {
-            let (name_str, dest_str) = line.split_once(" -> ").unwrap();
-            let destinations = dest_str.split(", ").map(str::to_string).collect_vec();
-
-            let (name, kind) = match name_str {
-                s if s.starts_with("broadcaster") => (s.to_string(), Broadcaster),
-                s if s.starts_with("%") => (
-                    s.trim_start_matches("%").to_string(),
-                    FlipFlop { is_on: false },
-                ),
-                s if s.starts_with("&") => (
-                    s.trim_start_matches("&").to_string(),
-                    Conjunction {
-                        memory: BTreeMap::new(),
-                    },
-                ),
-                _ => unreachable!("Unexpected module kind when parsing"),
-            };
-
-            (
-                name.clone(),
-                Module {
-                    name,
-                    kind,
-                    destinations,
-                },
-            )
-        })
-        .collect()
+    Ok(Configuration { modules })
 }
 
 pub fn part2(input: &str) -> usize {
-    let mut modules = parse_modules(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every button press until the cycles of the modules feeding `rx` are found,
+/// or until `cancel` stops it
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let mut modules = parse_modules(input).unwrap().modules;
 
     // Init memory
     let module_view = modules.values().cloned().collect_vec();
@@ -176,7 +209,18 @@ pub fn part2(input: &str) -> usize {
 
     let mut factors: BTreeMap<String, u64> = BTreeMap::new();
 
+    progress.start("Button presses", None);
     for i in 1.. {
+        cancel.check(|| {
+            format!(
+                "{} button presses, {} of {} cycles found",
+                i - 1,
+                factors.len(),
+                monitored_nodes.len()
+            )
+        })?;
+        progress.advance(1);
+
         queue.push_back(("button".to_string(), "broadcaster".to_string(), Pulse::Low));
 
         while let Some((sender, receiver, pulse)) = queue.pop_front() {
@@ -198,13 +242,23 @@ pub fn part2(input: &str) -> usize {
             break;
         }
     }
+    progress.finish();
 
     let res = reikna::factor::lcm_all(&factors.into_values().collect_vec());
-    return res as usize;
+    return Ok(res as usize);
 }
 
 pub fn part1(input: &str) -> usize {
-    let mut modules = parse_modules(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every one of the thousand button presses, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let mut modules = parse_modules(input).unwrap().modules;
 
     // Init memory
     let module_view = modules.values().cloned().collect_vec();
@@ -217,7 +271,11 @@ pub fn part1(input: &str) -> usize {
     let mut low_counter = 0;
     let mut high_counter = 0;
 
-    for _ in 0..1000 {
+    progress.start("Button presses", Some(1000));
+    for i in 0..1000 {
+        cancel.check(|| format!("{i} of 1000 button presses"))?;
+        progress.advance(1);
+
         queue.push_back(("button".to_string(), "broadcaster".to_string(), Pulse::Low));
 
         while let Some((sender, receiver, pulse)) = queue.pop_front() {
@@ -232,8 +290,16 @@ pub fn part1(input: &str) -> usize {
             });
         }
     }
+    progress.finish();
 
-    return low_counter * high_counter;
+    return Ok(low_counter * high_counter);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(configuration) = parse_modules(input) {
+        assert_eq!(parse_modules(&configuration.to_string()), Ok(configuration));
+    }
 }
 
 pub fn process(input: String) {
@@ -247,6 +313,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -285,4 +352,63 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 247702167614647);
     }
+
+    #[test]
+    fn part2_times_out_without_rx() {
+        let input = "broadcaster -> a, b, c
+%a -> b
+%b -> c
+%c -> inv
+&inv -> a";
+        let cancel = Token::new().with_budget(std::time::Duration::from_millis(20));
+
+        let timed_out = part2_with_progress(input, &mut (), &cancel).unwrap_err();
+        assert!(timed_out
+            .partial
+            .ends_with(" button presses, 0 of 0 cycles found"));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_modules() {
+        use ParseErrorKind::*;
+        let invalid_at = |input, line| matches!(parse_modules(input), Err(ParseError { line: l, kind: Invalid(_) }) if l == line);
+
+        assert_eq!(
+            parse_modules("broadcaster -> a\n%a"),
+            Err(Missing("`<module> -> <destinations>`").at(2))
+        );
+        assert_eq!(
+            parse_modules("broadcaster -> a\n$a -> b"),
+            Err(Unknown("$a".to_string()).at(2))
+        );
+        assert!(invalid_at("broadcaster -> a\n% -> b", 2));
+        assert!(invalid_at("broadcaster -> a\n%a -> b,c", 2));
+        assert_eq!(
+            parse_modules("broadcaster -> a\n%a -> b\n&a -> b"),
+            Err(Duplicate("&a".to_string()).at(3))
+        );
+        assert_eq!(
+            parse_modules("broadcasters -> a"),
+            Err(Unknown("broadcasters".to_string()).at(1))
+        );
+        assert_eq!(
+            parse_modules("%a -> b"),
+            Err(Missing("a `broadcaster`").at(2))
+        );
+    }
+
+    #[test]
+    fn modules_round_trip() {
+        let configuration = parse_modules(include_str!("input.txt")).unwrap();
+        assert_eq!(parse_modules(&configuration.to_string()), Ok(configuration));
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_networks(input in "((broadcaster|[%&][a-c]{1,2}) -> [a-c]{1,2}(, [a-c]{1,2}){0,2}\n){0,6}") {
+            if let Ok(configuration) = parse_modules(&input) {
+                prop_assert_eq!(parse_modules(&configuration.to_string()), Ok(configuration));
+            }
+        }
+    }
 }
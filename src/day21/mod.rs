@@ -1,5 +1,9 @@
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+use crate::render::{Image, Palette, Recorder};
 use itertools::Itertools;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
 #[derive(Debug, PartialEq, Eq)]
 enum GardenPos {
@@ -8,20 +12,48 @@ enum GardenPos {
     Start,
 }
 
-fn parse(input: &str) -> Vec<Vec<GardenPos>> {
-    input
-        .lines()
-        .map(|line| {
-            line.chars()
+fn parse(input: &str) -> Result<Vec<Vec<GardenPos>>, ParseError> {
+    let grid = parse::grid(input, "a garden", "#S.")?
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
                 .map(|c| match c {
                     '#' => GardenPos::Rock,
                     'S' => GardenPos::Start,
-                    '.' => GardenPos::Plot,
-                    _ => unreachable!("Unknown cell type."),
+                    _ => GardenPos::Plot,
                 })
                 .collect_vec()
         })
-        .collect_vec()
+        .collect_vec();
+
+    let mut starts = grid.iter().enumerate().flat_map(|(y, row)| {
+        row.iter()
+            .filter(|p| **p == GardenPos::Start)
+            .map(move |_| y)
+    });
+    if starts.next().is_none() {
+        return Err(ParseErrorKind::Missing("a start `S`").at(grid.len() + 1));
+    }
+    if let Some(y) = starts.next() {
+        return Err(ParseErrorKind::Duplicate("S".to_string()).at(y + 1));
+    }
+
+    Ok(grid)
+}
+
+fn find_start(grid: &[Vec<GardenPos>]) -> (usize, usize) {
+    grid.iter()
+        .enumerate()
+        .find_map(|(y, row)| {
+            row.iter().enumerate().find_map(|(x, c)| {
+                if c == &GardenPos::Start {
+                    Some((x, y))
+                } else {
+                    None
+                }
+            })
+        })
+        .unwrap()
 }
 
 fn successors_inf(x: i64, y: i64, grid: &Vec<Vec<GardenPos>>) -> Vec<(i64, i64)> {
@@ -40,29 +72,87 @@ fn successors_inf(x: i64, y: i64, grid: &Vec<Vec<GardenPos>>) -> Vec<(i64, i64)>
         .collect_vec()
 }
 
-fn bfs(grid: &Vec<Vec<GardenPos>>, start: (usize, usize), steps: usize) -> usize {
+/// Reports every step taken, or stops between steps when `cancel` does
+fn bfs(
+    grid: &Vec<Vec<GardenPos>>,
+    start: (usize, usize),
+    steps: usize,
+    recorder: &mut impl Recorder,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
     let mut positions = BTreeSet::from([(start.0 as i64, start.1 as i64)]);
     let mut cycles = Vec::new();
 
+    progress.start("Steps", None);
     for i in 1..=steps {
+        cancel.check(|| format!("{} of {steps} steps taken", i - 1))?;
+        progress.advance(1);
         positions = positions
             .into_iter()
             .flat_map(|(x, y)| successors_inf(x, y, grid))
             .collect();
+        recorder.capture(|palette| draw(grid, &positions, palette));
 
         if i % grid.len() == 65 { // Magic number for input (because steps % grid.len() is also 65)
             cycles.push(positions.len());
         }
 
         if cycles.len() > 2 {
-            return quad_regression(steps / grid.len(), cycles);
+            progress.finish();
+            return Ok(quad_regression(steps / grid.len(), cycles));
+        }
+    }
+    progress.finish();
+
+    return Ok(positions.len());
+}
+
+/// Draws the garden and the eight gardens around it with the plots the elf can be on
+fn draw(grid: &[Vec<GardenPos>], positions: &BTreeSet<(i64, i64)>, palette: &Palette) -> Image {
+    let (width, height) = (grid[0].len(), grid.len());
+    let mut image = Image::new(width * 3, height * 3, palette.background);
+
+    for (x, y) in (0..width * 3).cartesian_product(0..height * 3) {
+        if grid[y % height][x % width] == GardenPos::Rock {
+            image.set(x, y, palette.foreground);
+        }
+    }
+
+    let shifted = positions
+        .iter()
+        .map(|(x, y)| (x + width as i64, y + height as i64))
+        .filter(|(x, y)| *x >= 0 && *y >= 0)
+        .map(|(x, y)| (x as usize, y as usize));
+    image.overlay(shifted, palette.path);
+
+    return image;
+}
+
+/// How many steps every plot of the garden is from the start, without leaving the garden
+fn distances(grid: &Vec<Vec<GardenPos>>, start: (usize, usize)) -> BTreeMap<(usize, usize), usize> {
+    let (width, height) = (grid[0].len() as i64, grid.len() as i64);
+    let in_garden = |x: i64, y: i64| (0..width).contains(&x) && (0..height).contains(&y);
+
+    let mut distances = BTreeMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some((x, y)) = queue.pop_front() {
+        let distance = distances[&(x, y)];
+
+        for (x, y) in successors_inf(x as i64, y as i64, grid) {
+            let next = (x as usize, y as usize);
+            if in_garden(x, y) && !distances.contains_key(&next) {
+                distances.insert(next, distance + 1);
+                queue.push_back(next);
+            }
         }
     }
 
-    return positions.len();
+    return distances;
 }
 
-fn _display(grid: &Vec<Vec<GardenPos>>, distances: BTreeMap<(usize, usize), usize>) -> String {
+fn display(grid: &[Vec<GardenPos>], distances: BTreeMap<(usize, usize), usize>) -> String {
     grid.iter()
         .enumerate()
         .map(|(y, row)| {
@@ -78,26 +168,32 @@ fn _display(grid: &Vec<Vec<GardenPos>>, distances: BTreeMap<(usize, usize), usiz
         .join("\n")
 }
 
+/// The distance from the start of the plots up to six steps away, `#` for what can't be reached
+pub fn ascii(input: &str) -> String {
+    let grid = parse(input).unwrap();
+    let distances = distances(&grid, find_start(&grid));
+
+    return display(&grid, distances);
+}
+
 pub fn part1(input: &str, steps: usize) -> usize {
-    let grid = parse(input);
+    part1_with_progress(input, steps, &mut (), &Token::new()).unwrap()
+}
 
-    let start = grid
-        .iter()
-        .enumerate()
-        .find_map(|(y, row)| {
-            row.iter().enumerate().find_map(|(x, c)| {
-                if c == &GardenPos::Start {
-                    Some((x, y))
-                } else {
-                    None
-                }
-            })
-        })
-        .unwrap();
+/// Reports every step the elf takes, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    steps: usize,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let grid = parse(input).unwrap();
 
-    let result = bfs(&grid, start, steps);
+    let start = find_start(&grid);
 
-    return result;
+    let result = bfs(&grid, start, steps, &mut (), progress, cancel)?;
+
+    return Ok(result);
 }
 
 fn quad_regression(x: usize, a: Vec<usize>) -> usize {
@@ -108,25 +204,59 @@ fn quad_regression(x: usize, a: Vec<usize>) -> usize {
 }
 
 pub fn part2(input: &str, steps: usize) -> usize {
-    let grid = parse(input);
+    part2_with_progress(input, steps, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every step the elf takes, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    steps: usize,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let grid = parse(input).unwrap();
 
-    let start = grid
+    let start = find_start(&grid);
+
+    let result = bfs(&grid, start, steps, &mut (), progress, cancel)?;
+
+    return Ok(result);
+}
+
+/// Draws how far every plot of the garden is from the start, highlighting the plots
+/// the elf can end up on after exactly `steps` steps
+pub fn render(input: &str, steps: usize, palette: &Palette) -> Image {
+    let grid = parse(input).unwrap();
+    let distances = distances(&grid, find_start(&grid));
+
+    // Every other step the elf can return to where it was
+    let reachable = distances
         .iter()
-        .enumerate()
-        .find_map(|(y, row)| {
-            row.iter().enumerate().find_map(|(x, c)| {
-                if c == &GardenPos::Start {
-                    Some((x, y))
-                } else {
-                    None
-                }
-            })
-        })
-        .unwrap();
+        .filter(|(_, d)| **d <= steps && (steps - **d).is_multiple_of(2))
+        .map(|(pos, _)| *pos)
+        .collect_vec();
+
+    let mut image = Image::from_grid(&grid, |pos| match pos {
+        GardenPos::Rock => palette.foreground,
+        _ => palette.background,
+    });
+    image.heatmap(distances.iter().map(|(pos, d)| (*pos, *d as u64)), palette);
+    image.overlay(reachable, palette.highlight);
+
+    return image;
+}
 
-    let result = bfs(&grid, start, steps);
+/// Records the plots the elf can be on after every step, for at most `steps` steps
+pub fn animate(input: &str, steps: usize, recorder: &mut impl Recorder) {
+    let grid = parse(input).unwrap();
+    let start = find_start(&grid);
 
-    return result;
+    bfs(&grid, start, steps, recorder, &mut (), &Token::new()).unwrap();
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = parse(input);
 }
 
 pub fn process(input: String) {
@@ -140,6 +270,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::Animation;
 
     const EXAMPLE: &str = "...........
 .....###.#.
@@ -183,4 +314,77 @@ mod tests {
         let result = part2(input, 26501365);
         assert_eq!(result, 609708004316870);
     }
+
+    #[test]
+    fn part2_stops_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part2_with_progress(EXAMPLE, 500, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 500 steps taken");
+    }
+
+    #[test]
+    fn animate_spreads_from_the_start() {
+        let palette = Palette::default();
+        let mut animation = Animation::new(palette.clone());
+        animate(EXAMPLE, 6, &mut animation);
+
+        let frames = animation.frames();
+        assert_eq!(frames.len(), 6);
+        assert_eq!((frames[0].width(), frames[0].height()), (33, 33));
+
+        let plots = |image: &Image| {
+            (0..33)
+                .cartesian_product(0..33)
+                .filter(|(x, y)| image.get(*x, *y) == Some(palette.path))
+                .count()
+        };
+        assert_eq!(plots(&frames[0]), 2);
+        assert_eq!(plots(&frames[5]), part1(EXAMPLE, 6));
+    }
+
+    #[test]
+    fn ascii_shows_nearby_distances() {
+        let text = ascii(EXAMPLE);
+        let lines = text.lines().collect_vec();
+
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[5], ".##210####.");
+        assert_eq!(lines[1], ".....###.#.");
+    }
+
+    #[test]
+    fn render_highlights_reachable_plots() {
+        let palette = Palette::default();
+        let image = render(EXAMPLE, 6, &palette);
+
+        assert_eq!((image.width(), image.height()), (11, 11));
+        let reachable = (0..11)
+            .cartesian_product(0..11)
+            .filter(|(x, y)| image.get(*x, *y) == Some(palette.highlight))
+            .count();
+        assert_eq!(reachable, part1(EXAMPLE, 6));
+        assert_eq!(image.get(5, 1), Some(palette.foreground));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_gardens() {
+        use ParseErrorKind::*;
+        assert_eq!(parse(""), Err(Missing("a garden").at(1)));
+        assert_eq!(parse("\n"), Err(Missing("a garden").at(1)));
+        assert!(matches!(
+            parse("...\n.S"),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            parse("...\n.S.\n.S."),
+            Err(Duplicate("S".to_string()).at(3))
+        );
+        assert_eq!(parse("...\n..."), Err(Missing("a start `S`").at(3)));
+        assert_eq!(parse("...\n.Sx"), Err(UnexpectedChar('x').at(2)));
+    }
 }
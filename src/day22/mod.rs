@@ -1,3 +1,8 @@
+use crate::cancel::{TimedOut, Token};
+use crate::parallel;
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+use crate::render::{Image, Palette, Recorder};
 use glam::IVec3;
 use itertools::Itertools;
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -10,25 +15,34 @@ struct Brick {
 }
 
 impl Brick {
-    fn parse(id: usize, input: &str) -> Brick {
-        let (from_str, to_str) = input.split_once('~').unwrap();
-
-        let f = from_str
-            .split(',')
-            .map(str::parse)
-            .map(Result::unwrap)
-            .collect_vec();
-        let t = to_str
-            .split(',')
-            .map(str::parse)
-            .map(Result::unwrap)
-            .collect_vec();
-
-        Brick {
-            id,
-            from: IVec3::new(f[0], f[1], f[2]),
-            to: IVec3::new(t[0], t[1], t[2]),
+    fn parse(id: usize, input: &str) -> Result<Brick, ParseErrorKind> {
+        let (from_str, to_str) = input
+            .split_once('~')
+            .ok_or(ParseErrorKind::Missing("`<from>~<to>`"))?;
+
+        let coordinates = |s: &str| {
+            let c: Vec<i32> = s
+                .split(',')
+                .map(parse::number)
+                .collect::<Result<_, ParseErrorKind>>()?;
+            match c[..] {
+                [x, y, z] => Ok(IVec3::new(x, y, z)),
+                _ => Err(ParseErrorKind::Invalid(format!(
+                    "Expected three coordinates, got `{s}`"
+                ))),
+            }
+        };
+        let from = coordinates(from_str)?;
+        let to = coordinates(to_str)?;
+
+        // Bricks are listed from their lowest to their highest corner, above the ground
+        if from.cmpgt(to).any() || from.z < 1 {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Brick `{input}` isn't ordered or lies below the ground"
+            )));
         }
+
+        Ok(Brick { id, from, to })
     }
 
     fn intersects(&self, other: &Brick) -> bool {
@@ -43,11 +57,22 @@ impl Brick {
     }
 }
 
-fn parse(input: &str) -> Vec<Brick> {
+impl std::fmt::Display for Brick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (from, to) = (self.from, self.to);
+        write!(
+            f,
+            "{},{},{}~{},{},{}",
+            from.x, from.y, from.z, to.x, to.y, to.z
+        )
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Brick>, ParseError> {
     input
         .lines()
         .enumerate()
-        .map(|(i, l)| Brick::parse(i, l))
+        .map(|(i, l)| Brick::parse(i, l).map_err(|kind| kind.at(i + 1)))
         .collect()
 }
 
@@ -58,14 +83,24 @@ struct Graph {
     below: HashMap<usize, Vec<usize>>,
 }
 impl Graph {
-    fn new(bricks: Vec<Brick>) -> Graph {
+    /// Lets the bricks fall from the lowest up, capturing a frame after every brick lands,
+    /// or stops between bricks when `cancel` does
+    fn new(
+        bricks: Vec<Brick>,
+        recorder: &mut impl Recorder,
+        progress: &mut dyn Progress,
+        cancel: &Token,
+    ) -> Result<Graph, TimedOut> {
         let bricks = bricks.into_iter().sorted_by_key(|b| b.from.z).collect_vec();
 
         let mut layers: HashMap<usize, usize> = HashMap::new();
         let mut above: HashMap<usize, Vec<usize>> = bricks.iter().map(|b| (b.id, vec![])).collect();
         let mut below: HashMap<usize, Vec<usize>> = bricks.iter().map(|b| (b.id, vec![])).collect();
 
+        progress.start("Falling bricks", Some(bricks.len() as u64));
         for i in 0..bricks.len() {
+            cancel.check(|| format!("{i} of {} bricks settled", bricks.len()))?;
+            progress.advance(1);
             let brick = &bricks[i];
             let intersections = bricks[..i]
                 .iter()
@@ -80,6 +115,7 @@ impl Graph {
                 .unwrap_or(1);
 
             layers.insert(brick.id, layer);
+            recorder.capture(|palette| draw(&bricks, &layers, brick, palette));
 
             let below_this = intersections
                 .iter()
@@ -103,12 +139,14 @@ impl Graph {
             }
         }
 
-        Graph {
+        progress.finish();
+
+        Ok(Graph {
             bricks,
             layers,
             above,
             below,
-        }
+        })
     }
 
     fn is_brick_stable(&self, brick: &Brick) -> bool {
@@ -156,9 +194,63 @@ impl Graph {
     }
 }
 
+/// Draws the bricks seen from the front, the bricks in `layers` have already landed
+fn draw(
+    bricks: &[Brick],
+    layers: &HashMap<usize, usize>,
+    landed: &Brick,
+    palette: &Palette,
+) -> Image {
+    let min_x = bricks.iter().map(|b| b.from.x).min().unwrap_or(0);
+    let max_x = bricks.iter().map(|b| b.to.x).max().unwrap_or(0);
+    let max_z = bricks.iter().map(|b| b.to.z).max().unwrap_or(0);
+
+    let mut image = Image::new(
+        (max_x - min_x + 1) as usize,
+        max_z as usize + 1,
+        palette.background,
+    );
+    // The ground
+    image.overlay(
+        (0..image.width()).map(|x| (x, max_z as usize)),
+        palette.foreground,
+    );
+
+    let cells = |brick: &Brick| {
+        let bottom = layers.get(&brick.id).map_or(brick.from.z, |l| *l as i32);
+        let top = bottom + brick.height() - 1;
+
+        (brick.from.x..=brick.to.x)
+            .cartesian_product(bottom..=top)
+            .map(|(x, z)| ((x - min_x) as usize, (max_z - z) as usize))
+            .collect_vec()
+    };
+
+    let (settled, falling): (Vec<_>, Vec<_>) =
+        bricks.iter().partition(|b| layers.contains_key(&b.id));
+    for brick in falling {
+        image.overlay(cells(brick), palette.foreground);
+    }
+    for brick in settled {
+        image.overlay(cells(brick), palette.path);
+    }
+    image.overlay(cells(landed), palette.highlight);
+
+    return image;
+}
+
 pub fn part1(input: &str) -> usize {
-    let incoming_bricks = parse(input);
-    let graph = Graph::new(incoming_bricks);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every brick that settles, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let incoming_bricks = parse(input).unwrap();
+    let graph = Graph::new(incoming_bricks, &mut (), progress, cancel)?;
 
     let stable_bricks = graph
         .bricks
@@ -166,20 +258,49 @@ pub fn part1(input: &str) -> usize {
         .filter(|brick| graph.is_brick_stable(brick))
         .collect_vec();
 
-    return stable_bricks.len();
+    return Ok(stable_bricks.len());
 }
 
 pub fn part2(input: &str) -> usize {
-    let incoming_bricks = parse(input);
-    let graph = Graph::new(incoming_bricks);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let result = graph
-        .bricks
-        .iter()
-        .map(|brick| graph.dependant_bricks(brick))
-        .sum::<usize>();
+/// Reports every brick that settles and then every brick removed, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let incoming_bricks = parse(input).unwrap();
+    let graph = Graph::new(incoming_bricks, &mut (), progress, cancel)?;
+
+    let bricks = &graph.bricks;
+    progress.start("Removed bricks", Some(bricks.len() as u64));
+    let mut result = 0;
+    for (i, chunk) in bricks.chunks(64).enumerate() {
+        cancel.check(|| format!("{} of {} bricks removed", i * 64, bricks.len()))?;
+        result += parallel::map(chunk, |brick| graph.dependant_bricks(brick))
+            .iter()
+            .sum::<usize>();
+        progress.advance(chunk.len() as u64);
+    }
+    progress.finish();
+
+    return Ok(result);
+}
+
+/// Records the bricks settling one by one
+pub fn animate(input: &str, recorder: &mut impl Recorder) {
+    let bricks = parse(input).unwrap();
 
-    return result;
+    Graph::new(bricks, recorder, &mut (), &Token::new()).unwrap();
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(bricks) = parse(input) {
+        assert_eq!(parse(&bricks.iter().join("\n")), Ok(bricks));
+    }
 }
 
 pub fn process(input: String) {
@@ -193,6 +314,8 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::Animation;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "1,0,1~1,2,1
 0,0,2~2,0,2
@@ -227,4 +350,75 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 79122);
     }
+
+    #[test]
+    fn bricks_stop_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part1_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 7 bricks settled");
+        let timed_out = part2_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 of 7 bricks settled");
+    }
+
+    #[test]
+    fn animate_drops_every_brick() {
+        let palette = Palette::default();
+        let mut animation = Animation::new(palette.clone());
+        animate(EXAMPLE, &mut animation);
+
+        let frames = animation.frames();
+        assert_eq!(frames.len(), 7);
+        assert_eq!((frames[0].width(), frames[0].height()), (3, 10));
+
+        // The last brick falls from the top down onto the brick at height 4
+        assert_eq!(frames[5].get(1, 0), Some(palette.foreground));
+        assert_eq!(frames[6].get(1, 0), Some(palette.background));
+        assert_eq!(frames[6].get(1, 3), Some(palette.highlight));
+        assert_eq!(frames[6].get(1, 4), Some(palette.highlight));
+        assert_eq!(frames[6].get(1, 5), Some(palette.path));
+        assert_eq!(frames[6].get(1, 9), Some(palette.foreground));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_bricks() {
+        use ParseErrorKind::*;
+        let invalid = |input| {
+            matches!(
+                parse(input),
+                Err(ParseError {
+                    line: 1,
+                    kind: Invalid(_)
+                })
+            )
+        };
+
+        assert_eq!(parse("1,0,1"), Err(Missing("`<from>~<to>`").at(1)));
+        assert!(invalid("1,0,1~1,2"));
+        assert!(invalid("1,0,1~1,2,1,4"));
+        assert_eq!(
+            parse("1,0,1~1,2,1\n1,0,1~1,x,1"),
+            Err(InvalidNumber("x".to_string()).at(2))
+        );
+        assert!(invalid("1,2,1~1,0,1"));
+        assert!(invalid("1,0,0~1,2,0"));
+    }
+
+    #[test]
+    fn bricks_round_trip() {
+        for input in [EXAMPLE, include_str!("input.txt")] {
+            let bricks = parse(input).unwrap();
+            assert_eq!(bricks.iter().join("\n"), input);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_bricks(input in "(-?[0-9]{1,10},[0-9]{1,2},[0-9]{1,2}~[0-9]{1,2},[0-9]{1,2},[0-9]{1,2}\n){0,5}") {
+            if let Ok(bricks) = parse(&input) {
+                prop_assert_eq!(parse(&bricks.iter().join("\n")), Ok(bricks));
+            }
+        }
+    }
 }
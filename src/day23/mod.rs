@@ -1,6 +1,11 @@
 use std::collections::{BTreeMap, HashSet, VecDeque};
 
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::{Bar, Progress};
+use crate::render::{Image, Palette};
 use itertools::Itertools;
+use tracing::{debug, instrument};
 
 #[derive(Debug, PartialEq, Eq)]
 enum Pos {
@@ -16,10 +21,9 @@ impl Pos {
 
         match input {
             '#' => Forest,
-            '.' => Trail,
             '>' => SlopeRight,
             'v' => SlopeDown,
-            other => unreachable!("Encountered an unexpected grid char `{other}`"),
+            _ => Trail,
         }
     }
 
@@ -32,18 +36,29 @@ impl Pos {
 }
 
 #[derive(Debug)]
-struct HikingMap {
+pub struct HikingMap {
     grid: Vec<Vec<Pos>>,
 }
 
 impl HikingMap {
-    fn parse(input: &str) -> HikingMap {
-        let grid = input
-            .lines()
-            .map(|line| line.chars().map(Pos::parse).collect())
+    pub fn parse(input: &str) -> Result<HikingMap, ParseError> {
+        let grid: Vec<Vec<Pos>> = parse::grid(input, "a map", "#.>v")?
+            .into_iter()
+            .map(|row| row.into_iter().map(Pos::parse).collect())
             .collect();
 
-        HikingMap { grid }
+        // The hike ends next to the bottom right corner, so the map needs two columns
+        if grid[0].len() < 2 {
+            return Err(ParseErrorKind::Invalid(
+                "Map should be at least two tiles wide".to_string(),
+            )
+            .at(1));
+        }
+        if !grid[0].contains(&Pos::Trail) {
+            return Err(ParseErrorKind::Missing("a trail leaving the top row").at(1));
+        }
+
+        Ok(HikingMap { grid })
     }
 
     fn get(&self, x: usize, y: usize) -> Option<&Pos> {
@@ -53,7 +68,12 @@ impl HikingMap {
     fn successors(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
         let (x, y) = pos;
         let options = match self.grid.get(y).unwrap().get(x).unwrap() {
-            Pos::Trail => vec![(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)],
+            Pos::Trail => vec![
+                (x + 1, y),
+                (x.wrapping_sub(1), y),
+                (x, y + 1),
+                (x, y.wrapping_sub(1)),
+            ],
             Pos::Forest => vec![],
             Pos::SlopeRight => vec![(x + 1, y)],
             Pos::SlopeDown => vec![(x, y + 1)],
@@ -71,7 +91,12 @@ impl HikingMap {
         visited: &HashSet<(usize, usize)>,
     ) -> Vec<(usize, usize)> {
         let (x, y) = pos;
-        let options = vec![(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)];
+        let options = vec![
+            (x + 1, y),
+            (x.wrapping_sub(1), y),
+            (x, y + 1),
+            (x, y.wrapping_sub(1)),
+        ];
 
         options
             .into_iter()
@@ -80,49 +105,58 @@ impl HikingMap {
             .collect()
     }
 
-    fn successors_no_slopes_skip(&self, start: (usize, usize)) -> Vec<((usize, usize), usize)> {
+    /// The tiles of every corridor leaving `start`, up to the next junction or dead end
+    fn corridors(&self, start: (usize, usize)) -> Vec<Vec<(usize, usize)>> {
         self.successors_no_slopes(start, &HashSet::new())
             .into_iter()
             .map(|first_step| {
                 let mut visited: HashSet<_> = vec![start, first_step].into_iter().collect();
-                let mut distance = 0;
-                let mut node = first_step;
+                let mut tiles = vec![first_step];
 
                 loop {
-                    let nexts = self.successors_no_slopes(node, &visited);
+                    let nexts = self.successors_no_slopes(*tiles.last().unwrap(), &visited);
                     if nexts.len() == 1 {
-                        node = nexts[0];
-                        distance += 1;
-                        visited.insert(node);
+                        tiles.push(nexts[0]);
+                        visited.insert(nexts[0]);
                     } else {
-                        return (node, distance + 1);
+                        return tiles;
                     }
                 }
             })
             .collect()
     }
 
-    fn longest_path(
+    fn successors_no_slopes_skip(&self, start: (usize, usize)) -> Vec<((usize, usize), usize)> {
+        self.corridors(start)
+            .into_iter()
+            .map(|tiles| (*tiles.last().unwrap(), tiles.len()))
+            .collect()
+    }
+
+    pub fn longest_path(
         &self,
         start: (usize, usize),
         end: (usize, usize),
         visited: HashSet<(usize, usize)>,
-    ) -> usize {
+        cancel: &Token,
+    ) -> Result<Option<usize>, TimedOut> {
         if start == end {
-            0
-        } else {
-            self.successors(start)
-                .into_iter()
-                .filter(|n| !visited.contains(n))
-                .map(|next| {
-                    let mut new_visited = visited.clone();
-                    new_visited.insert(next);
-                    let longest = self.longest_path(next, end, new_visited) + 1;
-                    longest
-                })
-                .max()
-                .unwrap_or(0)
+            return Ok(Some(0));
+        }
+        cancel.check(|| format!("{} tiles into a hike", visited.len()))?;
+
+        let mut longest = None;
+        for next in self.successors(start) {
+            if !visited.contains(&next) {
+                let mut new_visited = visited.clone();
+                new_visited.insert(next);
+
+                let length = self.longest_path(next, end, new_visited, cancel)?;
+                longest = longest.max(length.map(|l| l + 1));
+            }
         }
+
+        return Ok(longest);
     }
 
     fn print_path(&self, path: &HashSet<(usize, usize)>) -> String {
@@ -144,12 +178,16 @@ impl HikingMap {
     }
 }
 
+/// The junctions a hike passes, from the end back to the start, and its length
+type Hike = (Vec<(usize, usize)>, usize);
+
 struct HikingGraph {
     distances: BTreeMap<(usize, usize), BTreeMap<(usize, usize), usize>>,
     neighbors: BTreeMap<(usize, usize), HashSet<(usize, usize)>>,
 }
 
 impl HikingGraph {
+    #[instrument(skip(map))]
     fn new(map: &HikingMap, start: (usize, usize)) -> HikingGraph {
         let mut distances: BTreeMap<(usize, usize), BTreeMap<(usize, usize), usize>> =
             BTreeMap::new();
@@ -166,7 +204,9 @@ impl HikingGraph {
                 let distances_of_node = distances.entry(node).or_default();
                 let neighbors_of_node = neighbors.entry(node).or_default();
                 for (next, distance) in map.successors_no_slopes_skip(node) {
-                    distances_of_node.insert(next, distance);
+                    // Two corridors can join the same junctions, only the longer one matters
+                    let longest = distances_of_node.entry(next).or_default();
+                    *longest = (*longest).max(distance);
                     neighbors_of_node.insert(next);
                     queue.push_back(next);
                 }
@@ -179,37 +219,59 @@ impl HikingGraph {
         }
     }
 
+    /// The length of the longest hike and the junctions it passes, from `end` back to `start`,
+    /// advancing `progress` for every hike that reaches the end
     fn longest_path(
         &self,
         start: (usize, usize),
         end: (usize, usize),
         visited: HashSet<(usize, usize)>,
-    ) -> Option<(HashSet<(usize, usize)>, usize)> {
+        progress: &mut dyn Progress,
+        cancel: &Token,
+    ) -> Result<Option<Hike>, TimedOut> {
         if start == end {
-            let mut new_visited = visited.clone();
-            new_visited.insert(end);
-            return Some((new_visited, 0));
-        } else {
-            let successors = self.neighbors.get(&start).unwrap();
-
-            return successors
-                .into_iter()
-                .filter(|n| !visited.contains(n))
-                .filter_map(|next| {
-                    let mut new_visited = visited.clone();
-                    new_visited.insert(*next);
-
-                    let distance = self.distances.get(&start).unwrap().get(&next).unwrap();
-                    self.longest_path(*next, end, new_visited)
-                        .map(|(p, d)| (p, d + distance))
-                })
-                .max_by_key(|(_, d)| *d);
+            progress.advance(1);
+            return Ok(Some((vec![end], 0)));
+        }
+        cancel.check(|| format!("{} junctions into a hike", visited.len()))?;
+
+        let mut longest: Option<Hike> = None;
+        for next in self.neighbors.get(&start).unwrap() {
+            if !visited.contains(next) {
+                let mut new_visited = visited.clone();
+                new_visited.insert(*next);
+
+                let distance = self.distances.get(&start).unwrap().get(next).unwrap();
+                let hike = self.longest_path(*next, end, new_visited, progress, cancel)?;
+                if let Some((mut path, d)) = hike {
+                    // Ties keep the last hike found
+                    match &longest {
+                        Some((_, l)) if *l > d + distance => (),
+                        _ => {
+                            path.push(start);
+                            longest = Some((path, d + distance));
+                        }
+                    }
+                }
+            }
         }
+
+        return Ok(longest);
     }
 }
 
 pub fn part1(input: &str) -> usize {
-    let map = HikingMap::parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every start searched for the longest hike, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let map = HikingMap::parse(input).unwrap();
 
     let starts = map
         .grid
@@ -223,17 +285,30 @@ pub fn part1(input: &str) -> usize {
 
     let end = (map.grid.last().unwrap().len() - 2, map.grid.len() - 1);
 
-    let result = starts
-        .iter()
-        .map(|start| map.longest_path(*start, end, HashSet::new()))
-        .max()
-        .unwrap();
+    progress.start("Starts", Some(starts.len() as u64));
+    let mut result = None;
+    for start in &starts {
+        let length = map.longest_path(*start, end, HashSet::new(), cancel)?;
+        result = result.max(length);
+        progress.advance(1);
+    }
+    progress.finish();
 
-    return result;
+    return Ok(result.unwrap());
 }
 
 pub fn part2(input: &str) -> usize {
-    let map = HikingMap::parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every hike found while searching for the longest one, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let map = HikingMap::parse(input).unwrap();
 
     let starts = map
         .grid
@@ -249,16 +324,72 @@ pub fn part2(input: &str) -> usize {
 
     let graph = HikingGraph::new(&map, starts[0]);
     let nodes = graph.neighbors.keys().collect_vec();
-    let (_path, distance) = graph.longest_path(starts[0], end, HashSet::new()).unwrap();
-    // println!("End: {:?}, nodes: {:?}", end, nodes);
+    debug!(?end, ?nodes, "Junctions");
 
-    return distance;
+    progress.start("Hikes", None);
+    let (_path, distance) = graph
+        .longest_path(starts[0], end, HashSet::new(), progress, cancel)?
+        .unwrap();
+    progress.finish();
+
+    return Ok(distance);
+}
+
+/// Every tile of the longest hike of part 2
+fn longest_hike(map: &HikingMap) -> Vec<(usize, usize)> {
+    let start_x = map.grid[0].iter().position(|p| *p == Pos::Trail).unwrap();
+    let start = (start_x, 0);
+    let end = (map.grid[0].len() - 2, map.grid.len() - 1);
+
+    let graph = HikingGraph::new(map, start);
+    let (junctions, _) = graph
+        .longest_path(start, end, HashSet::new(), &mut (), &Token::new())
+        .unwrap()
+        .unwrap();
+
+    // Junctions can be joined by several corridors, the hike takes the longest one
+    let hike = junctions.iter().tuple_windows().flat_map(|(a, b)| {
+        map.corridors(*a)
+            .into_iter()
+            .filter(|tiles| tiles.last() == Some(b))
+            .max_by_key(Vec::len)
+            .unwrap()
+    });
+
+    return hike.chain([end]).collect();
+}
+
+/// The map with the longest hike of part 2 drawn with `O`
+pub fn ascii(input: &str) -> String {
+    let map = HikingMap::parse(input).unwrap();
+    let hike = longest_hike(&map).into_iter().collect();
+
+    return map.print_path(&hike);
+}
+
+/// Draws the map with the longest hike of part 2 on top of it
+pub fn render(input: &str, palette: &Palette) -> Image {
+    let map = HikingMap::parse(input).unwrap();
+
+    let mut image = Image::from_grid(&map.grid, |pos| match pos {
+        Pos::Forest => palette.foreground,
+        Pos::Trail => palette.background,
+        Pos::SlopeRight | Pos::SlopeDown => palette.highlight,
+    });
+    image.overlay(longest_hike(&map), palette.path);
+
+    return image;
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = HikingMap::parse(input);
 }
 
 pub fn process(input: String) {
     use std::time::Instant;
     let now = Instant::now();
-    let result = part2(&input);
+    let result = part2_with_progress(&input, &mut Bar::default(), &Token::new()).unwrap();
     println!("Result: {result}");
     println!("Finished in: {:.2?}", now.elapsed());
 }
@@ -310,10 +441,64 @@ mod tests {
         assert_eq!(result, 154);
     }
 
+    #[test]
+    fn hikes_stop_when_cancelled() {
+        let cancel = Token::new();
+        cancel.cancel();
+
+        let timed_out = part1_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 tiles into a hike");
+        let timed_out = part2_with_progress(EXAMPLE, &mut (), &cancel).unwrap_err();
+        assert_eq!(timed_out.partial, "0 junctions into a hike");
+    }
+
     #[test]
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
         assert_eq!(result, 6230);
     }
+
+    #[test]
+    fn render_draws_the_longest_hike() {
+        let palette = Palette::default();
+        let image = render(EXAMPLE, &palette);
+
+        assert_eq!((image.width(), image.height()), (23, 23));
+        let hike = (0..23)
+            .cartesian_product(0..23)
+            .filter(|(x, y)| image.get(*x, *y) == Some(palette.path))
+            .count();
+        // Every step of the hike and the tile it starts on
+        assert_eq!(hike, part2(EXAMPLE) + 1);
+        assert_eq!(image.get(1, 0), Some(palette.path));
+        assert_eq!(image.get(21, 22), Some(palette.path));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_maps() {
+        use ParseErrorKind::*;
+        let error = |input| HikingMap::parse(input).err();
+
+        assert_eq!(error(""), Some(Missing("a map").at(1)));
+        assert!(matches!(
+            error(".\n."),
+            Some(ParseError {
+                line: 1,
+                kind: Invalid(_)
+            })
+        ));
+        assert!(matches!(
+            error("#.#\n#."),
+            Some(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            error("###\n#.#"),
+            Some(Missing("a trail leaving the top row").at(1))
+        );
+        assert_eq!(error("#.#\n#<#"), Some(UnexpectedChar('<').at(2)));
+    }
 }
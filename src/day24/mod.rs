@@ -3,33 +3,40 @@ use itertools::{iproduct, Itertools};
 use z3::{Config, Context, Solver};
 use z3::ast::{Ast, Int};
 
-#[derive(Debug)]
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+#[derive(Debug, PartialEq)]
 struct Hailstone {
     position: I64Vec3,
     velocity: I64Vec3,
 }
 
 impl Hailstone {
-    fn parse(input: &str) -> Hailstone {
-        let (pos_str, traj_str) = input.split_once(" @ ").unwrap();
-        let p = pos_str
-            .split(", ")
-            .map(str::trim)
-            .map(str::parse)
-            .map(Result::unwrap)
-            .collect_vec();
-
-        let t = traj_str
-            .split(", ")
-            .map(str::trim)
-            .map(str::parse)
-            .map(Result::unwrap)
-            .collect_vec();
-
-        Hailstone {
-            position: I64Vec3::new(p[0], p[1], p[2]),
-            velocity: I64Vec3::new(t[0], t[1], t[2]),
-        }
+    fn parse(input: &str) -> Result<Hailstone, ParseErrorKind> {
+        let (pos_str, traj_str) = input
+            .split_once(" @ ")
+            .ok_or(ParseErrorKind::Missing("` @ `"))?;
+
+        let vector = |s: &str| {
+            let v: Vec<i64> = s
+                .split(", ")
+                .map(str::trim)
+                .map(parse::number)
+                .collect::<Result<_, ParseErrorKind>>()?;
+            match v[..] {
+                [x, y, z] => Ok(I64Vec3::new(x, y, z)),
+                _ => Err(ParseErrorKind::Invalid(format!(
+                    "Expected three components, got `{s}`"
+                ))),
+            }
+        };
+
+        Ok(Hailstone {
+            position: vector(pos_str)?,
+            velocity: vector(traj_str)?,
+        })
     }
 
     fn intersection_2d(&self, other: &Hailstone) -> Option<DVec2> {
@@ -80,30 +87,67 @@ impl Hailstone {
     }
 }
 
-fn parse(input: &str) -> Vec<Hailstone> {
-    input.lines().map(Hailstone::parse).collect_vec()
+impl std::fmt::Display for Hailstone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (p, v) = (self.position, self.velocity);
+        write!(f, "{}, {}, {} @ {}, {}, {}", p.x, p.y, p.z, v.x, v.y, v.z)
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Hailstone>, ParseError> {
+    parse::lines(input, Hailstone::parse)
 }
 
 pub fn part1(input: &str, from: i64, to: i64) -> usize {
-    let hail = parse(input);
-
-    let intersections = hail
-        .iter()
-        .tuple_combinations()
-        .map(|(a, b)| (a, b, a.intersection_2d(b)))
-        .filter_map(|(a, b, io)| io.map(|i| (a, b, i))) // Only those who crossed
-        .filter(|(_, _, i)| {
-            // Only those who crossed in the area
-            i.x >= from as f64 && i.x <= to as f64 && i.y >= from as f64 && i.y <= to as f64
-        })
-        .filter(|(a, b, i)| a.along_trajectory(i) && b.along_trajectory(i)) // Only those who crossed in the direction of travel
-        .collect_vec();
+    part1_with_progress(input, from, to, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every pair of hailstones crossed, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    from: i64,
+    to: i64,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let hail = parse(input).unwrap();
+
+    let pairs = hail.len() * hail.len().saturating_sub(1) / 2;
+    progress.start("Hailstone pairs", Some(pairs as u64));
+    let mut result = 0;
+    for (i, (a, b)) in hail.iter().tuple_combinations().enumerate() {
+        cancel.check(|| format!("{i} of {pairs} hailstone pairs crossed"))?;
+        // Only those who crossed, in the area, in the direction of travel
+        let crossed = a.intersection_2d(b).is_some_and(|i| {
+            i.x >= from as f64
+                && i.x <= to as f64
+                && i.y >= from as f64
+                && i.y <= to as f64
+                && a.along_trajectory(&i)
+                && b.along_trajectory(&i)
+        });
+        if crossed {
+            result += 1;
+        }
+        progress.advance(1);
+    }
+    progress.finish();
 
-    return intersections.len();
+    return Ok(result);
 }
 
 pub fn part2(input: &str) -> usize {
-    let hail = parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every hailstone constrained, or stops before handing the constraints to the solver
+/// when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let hail = parse(input).unwrap();
 
     let cfg = Config::new();
     let ctx = Context::new(&cfg);
@@ -116,7 +160,10 @@ pub fn part2(input: &str) -> usize {
     let vy = Int::new_const(&ctx, "vy");
     let vz = Int::new_const(&ctx, "vz");
 
-    for hailstone in hail {
+    let count = hail.len();
+    progress.start("Hailstones", Some(count as u64));
+    for (i, hailstone) in hail.into_iter().enumerate() {
+        cancel.check(|| format!("{i} of {count} hailstones constrained"))?;
         let pxn = Int::from_i64(&ctx, hailstone.position.x);
         let pyn = Int::from_i64(&ctx, hailstone.position.y);
         let pzn = Int::from_i64(&ctx, hailstone.position.z);
@@ -128,7 +175,9 @@ pub fn part2(input: &str) -> usize {
         solver.assert(&(&pxn + &vxn * &tn)._eq(&(&px + &vx * &tn)));
         solver.assert(&(&pyn + &vyn * &tn)._eq(&(&py + &vy * &tn)));
         solver.assert(&(&pzn + &vzn * &tn)._eq(&(&pz + &vz * &tn)));
+        progress.advance(1);
     }
+    progress.finish();
 
     solver.check();
     let model = solver.get_model().unwrap();
@@ -136,7 +185,14 @@ pub fn part2(input: &str) -> usize {
     let y = model.get_const_interp(&py).unwrap().as_i64().unwrap();
     let z = model.get_const_interp(&pz).unwrap().as_i64().unwrap();
 
-    return (x + y + z).try_into().unwrap();
+    return Ok((x + y + z).try_into().unwrap());
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(hail) = parse(input) {
+        assert_eq!(parse(&hail.iter().join("\n")), Ok(hail));
+    }
 }
 
 pub fn process(input: String) {
@@ -150,6 +206,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "19, 13, 30 @ -2,  1, -2
 18, 19, 22 @ -1, -1, -2
@@ -183,4 +240,47 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 673641951253289);
     }
+
+    #[test]
+    fn parse_rejects_malformed_hailstones() {
+        use ParseErrorKind::*;
+        assert_eq!(parse("19, 13, 30"), Err(Missing("` @ `").at(1)));
+        assert!(matches!(
+            parse("19, 13 @ -2, 1, -2"),
+            Err(ParseError {
+                line: 1,
+                kind: Invalid(_)
+            })
+        ));
+        assert!(matches!(
+            parse("19, 13, 30 @ -2, 1, -2\n19, 13, 30 @ -2, 1, -2, 4"),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            parse("19, 13, x @ -2, 1, -2"),
+            Err(InvalidNumber("x".to_string()).at(1))
+        );
+    }
+
+    #[test]
+    fn hail_round_trips() {
+        let input = include_str!("input.txt");
+        let hail = parse(input).unwrap();
+        assert_eq!(hail.iter().join("\n"), input);
+
+        // The example pads its columns, so only the parsed hail has to survive
+        let example = parse(EXAMPLE).unwrap();
+        assert_eq!(parse(&example.iter().join("\n")), Ok(example));
+    }
+
+    proptest! {
+        #[test]
+        fn hailstone_round_trips(hailstone in "-?[1-9][0-9]{0,14}(, -?[1-9][0-9]{0,14}){2} @ -?[1-9][0-9]{0,2}(, -?[1-9][0-9]{0,2}){2}") {
+            let printed = Hailstone::parse(&hailstone).map(|h| h.to_string());
+            prop_assert_eq!(printed, Ok(hailstone));
+        }
+    }
 }
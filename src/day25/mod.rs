@@ -1,26 +1,54 @@
-use indicatif::ProgressIterator;
+use crate::cancel::{TimedOut, Token};
+use crate::parallel;
+use crate::parse::{ParseError, ParseErrorKind};
+use crate::progress::{Bar, Progress};
 use itertools::Itertools;
-use std::collections::{BTreeMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter,
+};
+use tracing::{debug, instrument};
 
 #[derive(Debug, Clone)]
 struct Graph<'a> {
-    nodes: BTreeMap<&'a str, HashSet<&'a str>>,
+    nodes: BTreeMap<&'a str, BTreeSet<&'a str>>,
 }
 
 impl<'a> Graph<'a> {
-    fn parse(input: &str) -> Graph {
-        let edges = input
-            .lines()
-            .map(|line| {
-                let (name, edges_str) = line.split_once(": ").unwrap();
+    fn parse(input: &'a str) -> Result<Graph<'a>, ParseError> {
+        let mut nodes = BTreeMap::new();
 
-                let vertices = edges_str.split(' ').collect();
+        for (i, line) in input.lines().enumerate() {
+            let (name, edges_str) = line
+                .split_once(": ")
+                .ok_or(ParseErrorKind::Missing("`<name>: <edges>`").at(i + 1))?;
 
-                (name, vertices)
-            })
-            .collect();
+            let vertices: BTreeSet<&str> = edges_str.split(' ').collect();
 
-        Graph { nodes: edges }
+            for n in iter::once(name).chain(vertices.iter().copied()) {
+                if n.is_empty() {
+                    return Err(ParseErrorKind::Missing("a component name").at(i + 1));
+                }
+                if let Some(c) = n.chars().find(|c| !c.is_ascii_alphanumeric()) {
+                    return Err(ParseErrorKind::UnexpectedChar(c).at(i + 1));
+                }
+            }
+            if vertices.contains(name) {
+                return Err(ParseErrorKind::Invalid(format!(
+                    "Component `{name}` is connected to itself"
+                ))
+                .at(i + 1));
+            }
+            if nodes.insert(name, vertices).is_some() {
+                return Err(ParseErrorKind::Duplicate(name.to_string()).at(i + 1));
+            }
+        }
+
+        if nodes.is_empty() {
+            return Err(ParseErrorKind::Missing("a component").at(1));
+        }
+
+        Ok(Graph { nodes })
     }
 
     fn edges(&self) -> Vec<(&'a str, &'a str)> {
@@ -39,7 +67,7 @@ impl<'a> Graph<'a> {
                 .and_modify(|froms| {
                     froms.insert(from);
                 })
-                .or_insert(HashSet::from([from]));
+                .or_insert(BTreeSet::from([from]));
         }
     }
 
@@ -64,8 +92,8 @@ impl<'a> Graph<'a> {
         self.nodes.get_mut(to).unwrap().insert(from);
     }
 
-    fn strongly_connected_component(&self, start: &'a str) -> HashSet<&'a str> {
-        let mut visited = HashSet::new();
+    fn strongly_connected_component(&self, start: &'a str) -> BTreeSet<&'a str> {
+        let mut visited = BTreeSet::new();
         let mut queue = vec![];
         queue.push(start);
 
@@ -96,42 +124,56 @@ impl<'a> Graph<'a> {
         return path;
     }
 
-    fn min_cut_candidates(&self, n: usize) -> Vec<&str> {
+    #[instrument(skip(self, progress, cancel))]
+    fn min_cut_candidates(
+        &self,
+        n: usize,
+        progress: &mut dyn Progress,
+        cancel: &Token,
+    ) -> Result<Vec<&str>, TimedOut> {
         let mut counts = BTreeMap::new();
 
-        for (from, to) in self
-            .nodes
-            .keys()
-            .tuple_combinations()
-            .take(n)
-            // .progress_count(n as u64)
-        {
-            let path = self.shortest_path(from, to);
-            for node in path {
+        progress.start("Shortest paths", Some(n as u64));
+        let pairs = self.nodes.keys().tuple_combinations().take(n).collect_vec();
+        for (i, chunk) in pairs.chunks(256).enumerate() {
+            cancel.check(|| format!("{} of {n} shortest paths", i * 256))?;
+            let paths = parallel::map(chunk, |(from, to)| self.shortest_path(from, to));
+            for node in paths.into_iter().flatten() {
                 counts
                     .entry(node)
                     .and_modify(|count| *count += 1)
                     .or_insert(1);
             }
+            progress.advance(chunk.len() as u64);
         }
+        progress.finish();
 
-        return counts
+        return Ok(counts
             .iter()
             .sorted_by_key(|(_, count)| -*count)
             .take(6)
             .map(|(node, _)| *node)
-            .collect();
+            .collect());
     }
 }
 
 pub fn part1(input: &str) -> usize {
-    let mut graph = Graph::parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports the shortest paths searched for the edges to cut, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let mut graph = Graph::parse(input).unwrap();
     graph.fix_edges();
 
     let max_n = graph.nodes.len() * (graph.nodes.len() - 1);
-    let candidates = graph.min_cut_candidates(200.max(max_n / 100));
-
-    // dbg!(&candidates);
+    let candidates = graph.min_cut_candidates(200.max(max_n / 100), progress, cancel)?;
+    debug!(?candidates);
 
     let mut partitioned_graph = graph.clone();
     for (from, to) in candidates.iter().tuple_combinations() {
@@ -144,16 +186,20 @@ pub fn part1(input: &str) -> usize {
         .sorted()
         .dedup()
         .collect_vec();
+    debug!(?sizes);
 
-    // dbg!(&sizes);
+    return Ok(sizes.iter().product());
+}
 
-    return sizes.iter().product();
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = Graph::parse(input);
 }
 
 pub fn process(input: String) {
     use std::time::Instant;
     let now = Instant::now();
-    let result = part1(&input);
+    let result = part1_with_progress(&input, &mut Bar::default(), &Token::new()).unwrap();
     println!("Result: {result}");
     println!("Finished in: {:.2?}", now.elapsed());
 }
@@ -161,6 +207,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "jqt: rhn xhk nvd
 rsh: frs pzl lsr
@@ -189,4 +236,40 @@ frs: qnr lhk lsr";
         let result = part1(input);
         assert_eq!(result, 568214);
     }
+
+    #[test]
+    fn parse_rejects_malformed_wiring() {
+        use ParseErrorKind::*;
+        let error = |input| Graph::parse(input).err();
+
+        assert_eq!(error(""), Some(Missing("a component").at(1)));
+        assert_eq!(
+            error("jqt rhn xhk"),
+            Some(Missing("`<name>: <edges>`").at(1))
+        );
+        assert_eq!(error("jqt: "), Some(Missing("a component name").at(1)));
+        assert_eq!(
+            error("jqt: rhn  xhk"),
+            Some(Missing("a component name").at(1))
+        );
+        assert_eq!(error("jqt: rhn-xhk"), Some(UnexpectedChar('-').at(1)));
+        assert!(matches!(
+            error("jqt: jqt"),
+            Some(ParseError {
+                line: 1,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            error("jqt: rhn\njqt: xhk"),
+            Some(Duplicate("jqt".to_string()).at(2))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_wiring(input in "([a-c]{1,3}: [a-c]{1,3}( [a-c]{0,3}){0,3}\n){0,6}") {
+            let _ = Graph::parse(&input);
+        }
+    }
 }
@@ -1,160 +1,190 @@
-struct Number {
-    value: u32,
-    x: usize,
-    y: usize,
-    length: usize,
+use std::collections::HashMap;
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+/// A part number spanning `length` digits from `x` to the right
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Number {
+    pub value: u32,
+    pub x: usize,
+    pub y: usize,
+    pub length: usize,
 }
 
-struct Grid {
-    grid: Vec<Vec<char>>,
-    numbers: Vec<Number>,
-    gears: Vec<(usize, usize)>,
+/// Any character other than a digit or `.`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub char: char,
+    pub x: usize,
+    pub y: usize,
 }
 
-impl Grid {
-    pub fn parse(input: &str) -> Grid {
-        let grid = input
-            .lines()
-            .map(|row| row.chars().collect::<Vec<_>>())
-            .collect::<Vec<_>>();
+/// The numbers and symbols of an engine schematic of any width, linked to the ones they touch,
+/// diagonals included
+pub struct Schematic {
+    pub numbers: Vec<Number>,
+    pub symbols: Vec<Symbol>,
+    /// The numbers touching every symbol, by index
+    numbers_of: Vec<Vec<usize>>,
+    /// The symbols touching every number, by index
+    symbols_of: Vec<Vec<usize>>,
+}
 
+impl Schematic {
+    pub fn parse(input: &str) -> Result<Schematic, ParseError> {
         let mut numbers: Vec<Number> = Vec::new();
-        let mut gears: Vec<(usize, usize)> = Vec::new();
-
-        let mut value: u32 = 0;
-        let mut length: usize = 0;
-
-        fn terminate_number(
-            value: &mut u32,
-            numbers: &mut Vec<Number>,
-            length: &mut usize,
-            x: &usize,
-            y: &usize,
-        ) {
-            if *value > 0 {
-                numbers.push(Number {
-                    value: *value,
-                    x: *x - *length,
-                    y: *y,
-                    length: *length,
-                });
-                *value = 0;
-                *length = 0;
+        let mut symbols: Vec<Symbol> = Vec::new();
+
+        for (y, line) in input.lines().enumerate() {
+            // The row ends a number like any other non-digit
+            let chars = line.chars().chain(std::iter::once('.'));
+            let mut digits: Option<(u32, usize)> = None;
+            for (x, char) in chars.enumerate() {
+                if let Some(digit) = char.to_digit(10) {
+                    let (value, start) = digits.unwrap_or((0, x));
+                    let value = value
+                        .checked_mul(10)
+                        .and_then(|v| v.checked_add(digit))
+                        .ok_or_else(|| {
+                            let number = line.chars().skip(start).take_while(char::is_ascii_digit);
+                            ParseErrorKind::InvalidNumber(number.collect()).at(y + 1)
+                        })?;
+                    digits = Some((value, start));
+                    continue;
+                }
+
+                if let Some((value, start)) = digits.take() {
+                    numbers.push(Number {
+                        value,
+                        x: start,
+                        y,
+                        length: x - start,
+                    });
+                }
+                if char != '.' {
+                    symbols.push(Symbol { char, x, y });
+                }
             }
         }
 
-        for (y, line) in grid.iter().enumerate() {
-            for (x, char) in line.iter().enumerate() {
-                match char {
-                    n if n.is_ascii_digit() => {
-                        value = 10 * value + (*n as u32 - '0' as u32);
-                        length += 1;
-                    }
-                    '*' => {
-                        gears.push((x, y));
-                        terminate_number(&mut value, &mut numbers, &mut length, &x, &y);
-                    }
-                    _ => {
-                        terminate_number(&mut value, &mut numbers, &mut length, &x, &y);
+        let positions: HashMap<(usize, usize), usize> = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, s)| ((s.x, s.y), i))
+            .collect();
+        let mut numbers_of = vec![Vec::new(); symbols.len()];
+        let mut symbols_of = vec![Vec::new(); numbers.len()];
+        for (n, number) in numbers.iter().enumerate() {
+            for y in number.y.saturating_sub(1)..=number.y + 1 {
+                for x in number.x.saturating_sub(1)..=number.x + number.length {
+                    if let Some(&s) = positions.get(&(x, y)) {
+                        numbers_of[s].push(n);
+                        symbols_of[n].push(s);
                     }
                 }
             }
-
-            terminate_number(&mut value, &mut numbers, &mut length, &140, &y);
         }
 
-        return Grid {
-            grid,
+        return Ok(Schematic {
             numbers,
-            gears,
-        };
+            symbols,
+            numbers_of,
+            symbols_of,
+        });
     }
-}
-
-impl Number {
-    pub fn has_symbol_neighbor(&self, grid: &Grid) -> bool {
-        let mut coords: Vec<(i32, i32)> = Vec::new();
 
-        // Coordinates to check
-        coords.push((self.x as i32 - 1, self.y as i32));
-        coords.push((self.x as i32 + self.length as i32, self.y as i32));
+    /// The numbers touching the symbol at index `symbol`
+    pub fn numbers_touching(&self, symbol: usize) -> impl Iterator<Item = &Number> {
+        self.numbers_of[symbol].iter().map(|&n| &self.numbers[n])
+    }
 
-        for x in (self.x as i32 - 1)..=(self.x as i32 + self.length as i32) {
-            coords.push((x, self.y as i32 - 1));
-            coords.push((x, self.y as i32 + 1));
-        }
+    /// The symbols touching the number at index `number`
+    pub fn symbols_touching(&self, number: usize) -> impl Iterator<Item = &Symbol> {
+        self.symbols_of[number].iter().map(|&s| &self.symbols[s])
+    }
 
-        // Check all the adjacent coordinates
-        for (x, y) in coords {
-            if x >= 0 && y >= 0 {
-                if let Some(line) = grid.grid.get(y as usize) {
-                    if let Some(char) = line.get(x as usize) {
-                        match char {
-                            '.' => (),
-                            x if x.is_ascii_digit() => (),
-                            _ => return true,
-                        }
-                    }
-                }
-            }
-        }
+    /// The indices of the symbols drawn as `char`
+    pub fn symbols_drawn(&self, char: char) -> impl Iterator<Item = usize> + '_ {
+        (0..self.symbols.len()).filter(move |&s| self.symbols[s].char == char)
+    }
 
-        return false;
+    /// The indices of the symbols touching exactly `count` numbers
+    pub fn symbols_with_neighbors(&self, count: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.symbols.len()).filter(move |&s| self.numbers_of[s].len() == count)
     }
 
-    pub fn is_neighbor_of(&self, x: &usize, y: &usize) -> bool {
-        let mut coords: Vec<(i32, i32)> = Vec::new();
+    /// The numbers touching at least one symbol
+    pub fn part_numbers(&self) -> impl Iterator<Item = &Number> {
+        (0..self.numbers.len())
+            .filter(|&n| !self.symbols_of[n].is_empty())
+            .map(|n| &self.numbers[n])
+    }
 
-        // Coordinates to check
-        coords.push((self.x as i32 - 1, self.y as i32));
-        coords.push((self.x as i32 + self.length as i32, self.y as i32));
+    /// The product of the two numbers of every `*` touching exactly two
+    pub fn gear_ratios(&self) -> impl Iterator<Item = u64> + '_ {
+        self.symbols_with_neighbors(2)
+            .filter(|&s| self.symbols[s].char == '*')
+            .map(|s| {
+                self.numbers_touching(s)
+                    .map(|n| u64::from(n.value))
+                    .product()
+            })
+    }
+}
 
-        for x in (self.x as i32 - 1)..=(self.x as i32 + self.length as i32) {
-            coords.push((x, self.y as i32 - 1));
-            coords.push((x, self.y as i32 + 1));
-        }
+pub fn part1(input: &str) -> u32 {
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-        // Check all the adjacent coordinates
-        for (cx, cy) in coords {
-            if cx == *x as i32 && cy == *y as i32 {
-                return true;
-            }
-        }
+/// Reports every part number added up, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u32, TimedOut> {
+    let schematic = Schematic::parse(input).unwrap();
 
-        return false;
+    progress.start("Part numbers", None);
+    let mut result: u32 = 0;
+    for (i, number) in schematic.part_numbers().enumerate() {
+        cancel.check(|| format!("{i} part numbers added"))?;
+        result += number.value;
+        progress.advance(1);
     }
+    progress.finish();
+
+    return Ok(result);
 }
 
-pub fn part1(input: &str) -> u32 {
-    let grid = Grid::parse(input);
+pub fn part2(input: &str) -> u64 {
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every gear ratio added up, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, TimedOut> {
+    let schematic = Schematic::parse(input).unwrap();
 
-    let result: u32 = grid
-        .numbers
-        .iter()
-        .filter(|n| n.has_symbol_neighbor(&grid))
-        .map(|n| n.value)
-        .sum();
+    progress.start("Gear ratios", None);
+    let mut result: u64 = 0;
+    for (i, ratio) in schematic.gear_ratios().enumerate() {
+        cancel.check(|| format!("{i} gear ratios added"))?;
+        result += ratio;
+        progress.advance(1);
+    }
+    progress.finish();
 
-    return result;
+    return Ok(result);
 }
 
-pub fn part2(input: &str) -> u32 {
-    let grid = Grid::parse(input);
-
-    let result: u32 = grid
-        .gears
-        .iter()
-        .map(|(g_x, g_y)| {
-            grid.numbers
-                .iter()
-                .filter(|n| n.is_neighbor_of(g_x, g_y))
-                .collect::<Vec<_>>()
-        })
-        .filter(|parts| parts.len() == 2)
-        .map(|parts| parts.iter().map(|p| p.value).product::<u32>())
-        .sum();
-
-    return result;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let _ = Schematic::parse(input);
 }
 
 pub fn process(input: String) {
@@ -165,6 +195,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -211,4 +242,74 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 84266818);
     }
+
+    #[test]
+    fn parse_rejects_overflowing_numbers() {
+        assert_eq!(
+            Schematic::parse("1\n12345678901*").err(),
+            Some(ParseErrorKind::InvalidNumber("12345678901".to_string()).at(2))
+        );
+        assert!(Schematic::parse("4294967295*").is_ok());
+    }
+
+    #[test]
+    fn symbols_link_to_the_numbers_they_touch() {
+        let schematic = Schematic::parse("467..114..\n...*......\n..35..633#\n0.....#...").unwrap();
+
+        assert_eq!(
+            schematic.symbols.iter().map(|s| s.char).collect::<String>(),
+            "*##"
+        );
+        let star = schematic.symbols_drawn('*').next().unwrap();
+        let touching = schematic.numbers_touching(star).map(|n| n.value);
+        assert_eq!(touching.collect::<Vec<_>>(), [467, 35]);
+        assert_eq!(
+            schematic.symbols_with_neighbors(1).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(schematic.symbols_with_neighbors(0).count(), 0);
+
+        let numbers = schematic.part_numbers().map(|n| n.value);
+        assert_eq!(numbers.collect::<Vec<_>>(), [467, 35, 633]);
+        let symbols = schematic.symbols_touching(3).map(|s| (s.x, s.y));
+        assert_eq!(symbols.collect::<Vec<_>>(), [(9, 2), (6, 3)]);
+        assert_eq!(schematic.gear_ratios().collect::<Vec<_>>(), [467 * 35]);
+    }
+
+    #[test]
+    fn numbers_end_with_their_row_at_any_width() {
+        let wide = format!("{}12\n*{}", ".".repeat(200), ".".repeat(201));
+        let schematic = Schematic::parse(&wide).unwrap();
+        assert_eq!(schematic.numbers[0].x, 200);
+        assert_eq!(schematic.part_numbers().count(), 0);
+
+        let schematic = Schematic::parse("..12\n....*\n0#").unwrap();
+        assert_eq!(schematic.numbers[0].length, 2);
+        let numbers = schematic.part_numbers().map(|n| n.value);
+        assert_eq!(numbers.collect::<Vec<_>>(), [12, 0]);
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_schematics(input in "([0-9.*#$]{0,160}\n){0,10}") {
+            if let Ok(schematic) = Schematic::parse(&input) {
+                let _ = schematic.part_numbers().count();
+                let _ = schematic.gear_ratios().count();
+            }
+        }
+
+        #[test]
+        fn links_only_join_neighbors(input in "([0-9.*#]{0,12}\n){0,6}") {
+            let schematic = Schematic::parse(&input).unwrap();
+            for (n, number) in schematic.numbers.iter().enumerate() {
+                for symbol in schematic.symbols_touching(n) {
+                    prop_assert!(symbol.x + 1 >= number.x && symbol.x <= number.x + number.length);
+                    prop_assert!(symbol.y + 1 >= number.y && symbol.y <= number.y + 1);
+                }
+            }
+            let links = (0..schematic.symbols.len()).map(|s| schematic.numbers_touching(s).count());
+            let reverse = (0..schematic.numbers.len()).map(|n| schematic.symbols_touching(n).count());
+            prop_assert_eq!(links.sum::<usize>(), reverse.sum::<usize>());
+        }
+    }
 }
@@ -1,46 +1,61 @@
-use std::collections::HashMap;
+use std::{collections::VecDeque, fmt::Display, io::BufRead};
 
-struct ScratchCard {
-    id: u32,
-    winning_numbers: Vec<u32>,
-    our_numbers: Vec<u32>,
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+#[derive(Debug, PartialEq)]
+pub struct ScratchCard {
+    pub id: u32,
+    pub winning_numbers: Vec<u32>,
+    pub our_numbers: Vec<u32>,
+}
+
+impl std::fmt::Display for ScratchCard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let numbers = |numbers: &Vec<u32>| {
+            numbers
+                .iter()
+                .map(|n| format!("{n:>2}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        write!(
+            f,
+            "Card {}: {} | {}",
+            self.id,
+            numbers(&self.winning_numbers),
+            numbers(&self.our_numbers)
+        )
+    }
 }
 
 impl ScratchCard {
-    pub fn parse(line: &str) -> ScratchCard {
-        let title_split = line.split(": ").collect::<Vec<_>>();
-
-        let id: u32 = title_split
-            .first()
-            .unwrap()
-            .split_ascii_whitespace()
-            .last()
-            .unwrap()
-            .parse()
-            .unwrap();
+    pub fn parse(line: &str) -> Result<ScratchCard, ParseErrorKind> {
+        let (title, numbers) = line
+            .split_once(": ")
+            .ok_or(ParseErrorKind::Missing("`: `"))?;
 
-        let number_split = title_split.last().unwrap().split(" | ").collect::<Vec<_>>();
-        let winning_numbers = number_split
-            .first()
-            .unwrap()
-            .trim()
-            .split_ascii_whitespace()
-            .map(|n| n.parse().unwrap())
-            .collect::<Vec<u32>>();
-
-        let our_numbers = number_split
-            .last()
-            .unwrap()
-            .trim()
-            .split_ascii_whitespace()
-            .map(|n| n.parse().unwrap())
-            .collect::<Vec<u32>>();
-
-        return ScratchCard {
-            id,
-            winning_numbers,
-            our_numbers,
+        let id = title
+            .strip_prefix("Card")
+            .ok_or(ParseErrorKind::Missing("`Card <id>`"))?;
+        let id: u32 = parse::number(id.trim())?;
+
+        let (winning, ours) = numbers
+            .split_once(" | ")
+            .ok_or(ParseErrorKind::Missing("` | `"))?;
+        let parse_numbers = |numbers: &str| {
+            numbers
+                .split_ascii_whitespace()
+                .map(parse::number)
+                .collect::<Result<Vec<u32>, _>>()
         };
+
+        return Ok(ScratchCard {
+            id,
+            winning_numbers: parse_numbers(winning)?,
+            our_numbers: parse_numbers(ours)?,
+        });
     }
 
     pub fn matches(&self) -> usize {
@@ -52,55 +67,202 @@ impl ScratchCard {
 }
 
 pub fn part1(input: &str) -> u32 {
-    let result: u32 = input
-        .lines()
-        .map(|line| {
-            let card = ScratchCard::parse(line);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every card scored, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u32, TimedOut> {
+    let lines: Vec<&str> = input.lines().collect();
 
-            let matches = card.matches();
+    progress.start("Cards", Some(lines.len() as u64));
+    let mut result: u32 = 0;
+    for (i, line) in lines.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} cards scored", lines.len()))?;
+        let card = ScratchCard::parse(line).unwrap();
 
-            return if matches > 0 {
-                2_u32.pow(matches as u32 - 1)
-            } else {
-                0
-            };
-        })
-        .sum();
+        let matches = card.matches();
 
-    return result;
+        if matches > 0 {
+            result += 2_u32.pow(matches as u32 - 1);
+        }
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(result);
 }
 
-pub fn part2(input: &str) -> u32 {
-    let cards: Vec<ScratchCard> = input.lines().map(|line| ScratchCard::parse(line)).collect();
+/// How one card played out, and the earlier cards whose wins copied it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cascaded {
+    pub id: u32,
+    pub matches: usize,
+    /// The original card and every copy won
+    pub copies: u128,
+    /// The id of every card that won copies of this one, with how many
+    pub contributors: Vec<(u32, u128)>,
+}
 
-    let mut card_count: HashMap<u32, u32> = cards.iter().map(|card| (card.id, 1)).collect();
+/// Plays the cards in order, each winning copies of the next ones. Only the copies won for the
+/// next `max_matches` cards are kept, so the cards can stream from a source of any size.
+/// Copies go to the following cards in the stream whatever their ids, and the cascade stops at
+/// the first error
+pub struct Cascade<I> {
+    cards: I,
+    max_matches: usize,
+    /// The copies won for each of the next cards
+    window: VecDeque<Vec<(u32, u128)>>,
+    failed: bool,
+}
 
-    for card in cards {
-        let wins = card.matches();
+impl<I: Iterator<Item = Result<ScratchCard, String>>> Cascade<I> {
+    pub fn new(cards: I, max_matches: usize) -> Cascade<I> {
+        Cascade {
+            cards,
+            max_matches,
+            window: VecDeque::from(vec![Vec::new(); max_matches]),
+            failed: false,
+        }
+    }
 
-        if wins > 0 {
-            let duplicates = *card_count.get(&card.id).unwrap();
+    fn play(&mut self, card: ScratchCard) -> Result<Cascaded, String> {
+        let matches = card.matches();
+        if matches > self.max_matches {
+            return Err(format!(
+                "Card {} has {matches} matches, more than the {} the cascade looks ahead",
+                card.id, self.max_matches
+            ));
+        }
 
-            for i in 1..=wins {
-                card_count
-                    .entry(card.id + i as u32)
-                    .and_modify(|a| *a += duplicates);
-            }
+        let contributors = self.window.pop_front().unwrap_or_default();
+        self.window.push_back(Vec::new());
+        let copies = contributors
+            .iter()
+            .try_fold(1u128, |copies, (_, c)| copies.checked_add(*c))
+            .ok_or(format!("Copies overflow u128 at card {}", card.id))?;
+
+        for won in self.window.iter_mut().take(matches) {
+            won.push((card.id, copies));
         }
+
+        return Ok(Cascaded {
+            id: card.id,
+            matches,
+            copies,
+            contributors,
+        });
+    }
+}
+
+impl<I: Iterator<Item = Result<ScratchCard, String>>> Iterator for Cascade<I> {
+    type Item = Result<Cascaded, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = self.cards.next()?.and_then(|card| self.play(card));
+        self.failed = result.is_err();
+        return Some(result);
+    }
+}
+
+/// Cascades the cards of `reader` one line at a time
+pub fn stream(
+    reader: impl BufRead,
+    max_matches: usize,
+) -> Cascade<impl Iterator<Item = Result<ScratchCard, String>>> {
+    let cards = reader.lines().enumerate().map(|(i, line)| {
+        let line = line.map_err(|e| format!("Couldn't read card: {e}"))?;
+        return ScratchCard::parse(&line).map_err(|kind| kind.at(i + 1).to_string());
+    });
+
+    return Cascade::new(cards, max_matches);
+}
+
+/// Why the cards have no total of copies
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Invalid(ParseError),
+    /// The cascade failed, or its copies overflow when added up
+    Cascade(String),
+    TimedOut(TimedOut),
+}
+
+impl From<TimedOut> for Error {
+    fn from(timed_out: TimedOut) -> Error {
+        Error::TimedOut(timed_out)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Invalid(error) => write!(f, "{error}"),
+            Error::Cascade(reason) => write!(f, "{reason}"),
+            Error::TimedOut(timed_out) => write!(f, "{timed_out}"),
+        }
+    }
+}
+
+pub fn part2(input: &str) -> Result<u128, Error> {
+    part2_with_progress(input, &mut (), &Token::new())
+}
+
+/// Reports every card played with its copies, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u128, Error> {
+    let cards = parse::lines(input, ScratchCard::parse).map_err(Error::Invalid)?;
+    let count = cards.len();
+
+    let max_matches = cards.iter().map(ScratchCard::matches).max();
+    let cascade = Cascade::new(cards.into_iter().map(Ok), max_matches.unwrap_or(0));
+
+    progress.start("Cards", Some(count as u64));
+    let mut result: u128 = 0;
+    for (i, card) in cascade.enumerate() {
+        cancel.check(|| format!("{i} of {count} cards played"))?;
+        let card = card.map_err(Error::Cascade)?;
+        result = result
+            .checked_add(card.copies)
+            .ok_or(Error::Cascade("Total copies overflow u128".to_string()))?;
+        progress.advance(1);
     }
+    progress.finish();
 
-    let result: u32 = card_count.values().sum();
-    return result;
+    return Ok(result);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(card) = ScratchCard::parse(input) {
+        let printed = card.to_string();
+        assert_eq!(
+            ScratchCard::parse(&printed).map(|c| c.to_string()),
+            Ok(printed)
+        );
+    }
 }
 
 pub fn process(input: String) {
-    let result = part2(&input);
-    println!("Result: {}", result);
+    match part2(&input) {
+        Ok(result) => println!("Result: {result}"),
+        Err(e) => println!("{e}"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -130,13 +292,123 @@ Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
 Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
 Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
         let result = part2(input);
-        assert_eq!(result, 30);
+        assert_eq!(result, Ok(30));
     }
 
     #[test]
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
-        assert_eq!(result, 10378710);
+        assert_eq!(result, Ok(10378710));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_cards() {
+        use ParseErrorKind::*;
+
+        assert_eq!(ScratchCard::parse("Card 1: 1 2 3"), Err(Missing("` | `")));
+        assert_eq!(
+            ScratchCard::parse("Card one: 1 | 2"),
+            Err(InvalidNumber("one".to_string()))
+        );
+        assert_eq!(
+            ScratchCard::parse("Card 1: 1 x | 2"),
+            Err(InvalidNumber("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn cascade_records_who_copied_every_card() {
+        let input = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
+        let cards = stream(input.as_bytes(), 4)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let copies = cards.iter().map(|c| c.copies).collect::<Vec<_>>();
+        assert_eq!(copies, [1, 2, 4, 8, 14, 1]);
+        assert_eq!(cards[3].matches, 1);
+        assert_eq!(cards[4].contributors, [(1, 1), (3, 4), (4, 8)]);
+        assert!(cards[5].contributors.is_empty());
+    }
+
+    #[test]
+    fn cascade_stops_at_cards_past_its_window() {
+        let input = "Card 1: 1 2 3 | 1 2 3\nCard 2: 1 | 2\nCard 3: 1 | 1";
+        let mut cascade = stream(input.as_bytes(), 2);
+
+        assert!(cascade.next().unwrap().is_err());
+        assert_eq!(cascade.next(), None);
+
+        let input = "Card 1: 1 | 1\nCard two: 1 | 2\nCard 3: 1 | 1";
+        let results = stream(input.as_bytes(), 1).collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1], Err("Line 2: Invalid number `two`".to_string()));
+    }
+
+    #[test]
+    fn copies_grow_past_u64() {
+        // Every card copies the next four, so the copies almost double from card to card
+        let input = (1..=100)
+            .map(|id| format!("Card {id}: 1 2 3 4 | 1 2 3 4"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let last = stream(input.as_bytes(), 4).last().unwrap().unwrap();
+
+        assert!(last.copies > u128::from(u64::MAX));
+        assert_eq!(last.contributors.len(), 4);
+    }
+
+    #[test]
+    fn copies_overflowing_u128_are_an_error() {
+        let input = (1..=200)
+            .map(|id| format!("Card {id}: 1 2 3 4 | 1 2 3 4"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let result = stream(input.as_bytes(), 4).find_map(Result::err);
+
+        assert!(result.is_some_and(|e| e.starts_with("Copies overflow u128 at card ")));
+    }
+
+    #[test]
+    fn part2_windows_fit_repeated_numbers() {
+        let result = part2("Card 1: 1 | 1 1\nCard 2: 1 | 2\nCard 3: 1 | 2");
+        assert_eq!(result, Ok(5));
+    }
+
+    #[test]
+    fn part2_reports_bad_cards_and_overflows() {
+        assert_eq!(
+            part2("Card 1: 1 | 1\nCard two: 1 | 2"),
+            Err(Error::Invalid(
+                ParseErrorKind::InvalidNumber("two".to_string()).at(2)
+            ))
+        );
+
+        let input = (1..=200)
+            .map(|id| format!("Card {id}: 1 2 3 4 | 1 2 3 4"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        // The total overflows before the copies of any single card do
+        assert_eq!(
+            part2(&input),
+            Err(Error::Cascade("Total copies overflow u128".to_string()))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn card_round_trips(
+            id in 1..1000u32,
+            winning_numbers in prop::collection::vec(0..100u32, 0..10),
+            our_numbers in prop::collection::vec(0..100u32, 0..25),
+        ) {
+            let card = ScratchCard { id, winning_numbers, our_numbers };
+            prop_assert_eq!(ScratchCard::parse(&card.to_string()), Ok(card));
+        }
     }
 }
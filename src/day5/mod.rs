@@ -1,8 +1,19 @@
 use itertools::Itertools;
 use regex::Regex;
+use std::collections::HashSet;
+use std::ops;
+use tracing::warn;
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+fn parse_numbers(input: &str) -> Result<Vec<i64>, ParseErrorKind> {
+    input.split(" ").map(parse::number).collect()
+}
 
 #[derive(Debug)]
-struct Range {
+pub struct Range {
     start: i64,
     length: i64,
 }
@@ -11,42 +22,127 @@ impl Range {
     pub fn new(start: i64, length: i64) -> Self {
         Self { start, length }
     }
+
+    pub fn span(&self) -> ops::Range<i64> {
+        self.start..(self.start + self.length)
+    }
 }
 
-#[derive(Debug)]
-struct Mapping {
+#[derive(Debug, PartialEq)]
+pub struct Mapping {
     destination_start: i64,
     source_start: i64,
     length: i64,
 }
 
 impl Mapping {
-    pub fn parse(input: &str) -> Mapping {
-        let split: Vec<i64> = input.split(" ").map(str::parse).flatten().collect();
+    pub fn parse(input: &str) -> Result<Mapping, ParseErrorKind> {
+        let numbers: Vec<i64> = parse_numbers(input)?;
+
+        let [destination_start, source_start, length] = numbers[..] else {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Expected three numbers in mapping `{input}`"
+            )));
+        };
 
-        Mapping {
-            destination_start: *split.get(0).unwrap(),
-            source_start: *split.get(1).unwrap(),
-            length: *split.get(2).unwrap(),
+        // Both ranges have to fit in an i64, and empty or negative ranges map nothing
+        let fits = |start: i64| start.checked_add(length).is_some();
+        if length <= 0 || !fits(destination_start) || !fits(source_start) {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Mapping `{input}` doesn't describe a valid range"
+            )));
         }
+
+        Ok(Mapping {
+            destination_start,
+            source_start,
+            length,
+        })
     }
 }
 
-#[derive(Debug)]
-struct Projection {
+impl std::fmt::Display for Mapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.destination_start, self.source_start, self.length
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Projection {
+    source: String,
+    destination: String,
     mappings: Vec<Mapping>,
 }
 
 impl Projection {
-    pub fn parse(input: &str) -> Projection {
-        let mappings: Vec<Mapping> = input
-            .lines()
-            .skip(1)
-            .map(Mapping::parse)
+    /// Parses a map section, its header is line 1
+    pub fn parse(input: &str) -> Result<Projection, ParseError> {
+        let mut lines = input.lines();
+
+        let header = lines
+            .next()
+            .ok_or(ParseErrorKind::Missing("a map header").at(1))?;
+        let (source, destination) = header
+            .strip_suffix(" map:")
+            .and_then(|name| name.split_once("-to-"))
+            .ok_or(ParseErrorKind::Missing("`<source>-to-<destination> map:`").at(1))?;
+
+        let mappings: Vec<Mapping> = lines
+            .enumerate()
+            .map(|(i, line)| Mapping::parse(line).map_err(|kind| kind.at(i + 2)))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
             .sorted_by_key(|m| m.source_start)
             .collect();
 
-        Projection { mappings }
+        Ok(Projection {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            mappings,
+        })
+    }
+
+    fn name(&self) -> String {
+        format!("{}-to-{}", self.source, self.destination)
+    }
+
+    /// Reports mappings whose source ranges overlap and unmapped gaps between them
+    fn validate(&self) -> Vec<AlmanacWarning> {
+        let mut warnings = vec![];
+
+        // The mapping reaching furthest so far, a later one can overlap it without touching the
+        // one just before
+        let Some(first) = self.mappings.first() else {
+            return warnings;
+        };
+        let mut furthest = first.source_start..first.source_start + first.length;
+
+        for b in self.mappings.iter().skip(1) {
+            let b_range = b.source_start..b.source_start + b.length;
+
+            if b_range.start < furthest.end {
+                warnings.push(AlmanacWarning::Overlap {
+                    map: self.name(),
+                    first: furthest.clone(),
+                    second: b_range.clone(),
+                });
+            } else if b_range.start > furthest.end {
+                warnings.push(AlmanacWarning::Gap {
+                    map: self.name(),
+                    gap: furthest.end..b_range.start,
+                });
+            }
+
+            if b_range.end > furthest.end {
+                furthest = b_range;
+            }
+        }
+
+        return warnings;
     }
 
     pub fn map_to(&self, source: i64) -> i64 {
@@ -66,11 +162,7 @@ impl Projection {
 
         while l > 0 {
             // Pass mappings where i > mapping.start + mapping.length (they end before this range starts)
-            if let Some(mapping) = self
-                .mappings
-                .iter()
-                .find(|m| i < m.source_start + m.length - 1)
-            {
+            if let Some(mapping) = self.mappings.iter().find(|m| i < m.source_start + m.length) {
                 if i >= mapping.source_start {
                     // We are inside of the mapping, advance to the end of the mapping or as long as `l` goes
                     let count = l.min(mapping.source_start + mapping.length - i);
@@ -80,7 +172,7 @@ impl Projection {
                     l -= count;
                 } else {
                     // We are before the mapping but `l` ends in or after it, advance to the start of the mapping with an isomorphic projection
-                    let count = mapping.source_start - i;
+                    let count = l.min(mapping.source_start - i);
                     dest_ranges.push(Range::new(i, count));
                     i += count;
                     l -= count;
@@ -134,36 +226,341 @@ impl Projection {
     pub fn map_ranges(&self, ranges: Vec<Range>) -> Vec<Range> {
         ranges.iter().flat_map(|r| self.map_range_fast(r)).collect()
     }
+
+    /// The projection as a function defined on the whole number line,
+    /// unmapped values keep an offset of zero
+    pub fn to_piecewise(&self) -> Result<PiecewiseLinear, String> {
+        let mut pieces = vec![];
+        let mut i = i64::MIN;
+
+        for m in &self.mappings {
+            if i < m.source_start {
+                pieces.push(Piece::new(i..m.source_start, 0));
+            }
+            // Overlapping mappings are resolved like `map_to`, the earlier mapping wins
+            let start = i.max(m.source_start);
+            let end = i.max(m.source_start + m.length);
+            let offset = m
+                .destination_start
+                .checked_sub(m.source_start)
+                .ok_or_else(|| format!("Mapping `{m}` moves values too far for an i64"))?;
+            pieces.push(Piece::new(start..end, offset));
+            i = end;
+        }
+        pieces.push(Piece::new(i..i64::MAX, 0));
+
+        Ok(PiecewiseLinear::new(pieces))
+    }
 }
 
-#[derive(Debug)]
+impl std::fmt::Display for Projection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} map:", self.name())?;
+        self.mappings
+            .iter()
+            .try_for_each(|mapping| write!(f, "\n{mapping}"))
+    }
+}
+
+/// A set of integers stored as sorted, disjoint and non-adjacent half-open intervals
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IntervalSet {
+    intervals: Vec<ops::Range<i64>>,
+}
+
+impl IntervalSet {
+    pub fn new(intervals: impl IntoIterator<Item = ops::Range<i64>>) -> Self {
+        let mut merged: Vec<ops::Range<i64>> = vec![];
+
+        for r in intervals
+            .into_iter()
+            .filter(|r| !r.is_empty())
+            .sorted_by_key(|r| r.start)
+        {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+
+        Self { intervals: merged }
+    }
+
+    pub fn from_range(range: ops::Range<i64>) -> Self {
+        Self::new(std::iter::once(range))
+    }
+
+    pub fn intervals(&self) -> &[ops::Range<i64>] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        let i = self.intervals.partition_point(|r| r.end <= value);
+        self.intervals.get(i).is_some_and(|r| r.contains(&value))
+    }
+
+    pub fn min(&self) -> Option<i64> {
+        self.intervals.first().map(|r| r.start)
+    }
+
+    pub fn max(&self) -> Option<i64> {
+        self.intervals.last().map(|r| r.end - 1)
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        IntervalSet::new(self.intervals.iter().chain(&other.intervals).cloned())
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let a = &self.intervals[i];
+            let b = &other.intervals[j];
+
+            result.push(a.start.max(b.start)..a.end.min(b.end));
+
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet::new(result)
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = vec![];
+        let mut j = 0;
+
+        for a in &self.intervals {
+            let mut start = a.start;
+
+            while j < other.intervals.len() && other.intervals[j].end <= start {
+                j += 1;
+            }
+
+            let mut k = j;
+            while k < other.intervals.len() && other.intervals[k].start < a.end {
+                let b = &other.intervals[k];
+                result.push(start..b.start);
+                start = start.max(b.end);
+                k += 1;
+            }
+
+            result.push(start..a.end);
+        }
+
+        IntervalSet::new(result)
+    }
+}
+
+/// A single linear piece `x -> x + offset` for `x` in `domain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    domain: ops::Range<i64>,
+    offset: i64,
+}
+
+impl Piece {
+    fn new(domain: ops::Range<i64>, offset: i64) -> Self {
+        Self { domain, offset }
+    }
+
+    fn image(&self) -> ops::Range<i64> {
+        (self.domain.start + self.offset)..(self.domain.end + self.offset)
+    }
+}
+
+/// A function made of translated pieces whose domains tile the number line in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiecewiseLinear {
+    pieces: Vec<Piece>,
+}
+
+impl PiecewiseLinear {
+    /// Builds the function from pieces sorted by domain, merging neighbours with the same offset
+    fn new(pieces: Vec<Piece>) -> Self {
+        let mut merged: Vec<Piece> = vec![];
+
+        for piece in pieces.into_iter().filter(|p| !p.domain.is_empty()) {
+            match merged.last_mut() {
+                Some(last)
+                    if last.offset == piece.offset && last.domain.end == piece.domain.start =>
+                {
+                    last.domain.end = piece.domain.end
+                }
+                _ => merged.push(piece),
+            }
+        }
+
+        Self { pieces: merged }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(vec![Piece::new(i64::MIN..i64::MAX, 0)])
+    }
+
+    pub fn apply(&self, x: i64) -> i64 {
+        let i = self.pieces.partition_point(|p| p.domain.end <= x);
+        match self.pieces.get(i) {
+            Some(piece) => x + piece.offset,
+            // Mapped ranges end before i64::MAX, so no map moves it
+            None => x,
+        }
+    }
+
+    /// Returns the function `x -> next(self(x))`
+    pub fn then(&self, next: &PiecewiseLinear) -> PiecewiseLinear {
+        let mut pieces = vec![];
+
+        for piece in &self.pieces {
+            let image = piece.image();
+            let first = next.pieces.partition_point(|p| p.domain.end <= image.start);
+
+            for n in next.pieces[first..]
+                .iter()
+                .take_while(|p| p.domain.start < image.end)
+            {
+                let start = image.start.max(n.domain.start);
+                let end = image.end.min(n.domain.end);
+                pieces.push(Piece::new(
+                    (start - piece.offset)..(end - piece.offset),
+                    piece.offset + n.offset,
+                ));
+            }
+        }
+
+        PiecewiseLinear::new(pieces)
+    }
+
+    /// All values the function takes on the given inputs
+    pub fn image(&self, inputs: &IntervalSet) -> IntervalSet {
+        IntervalSet::new(
+            self.overlaps(inputs)
+                .map(|(r, offset)| (r.start + offset)..(r.end + offset)),
+        )
+    }
+
+    /// All inputs that map into the given outputs
+    pub fn preimage(&self, outputs: &IntervalSet) -> IntervalSet {
+        let images = self
+            .pieces
+            .iter()
+            .map(|p| IntervalSet::from_range(p.image()));
+
+        IntervalSet::new(self.pieces.iter().zip(images).flat_map(|(piece, image)| {
+            image
+                .intersection(outputs)
+                .intervals
+                .into_iter()
+                .map(|r| (r.start - piece.offset)..(r.end - piece.offset))
+        }))
+    }
+
+    pub fn min_over(&self, inputs: &IntervalSet) -> Option<i64> {
+        self.overlaps(inputs)
+            .map(|(r, offset)| r.start + offset)
+            .min()
+    }
+
+    pub fn max_over(&self, inputs: &IntervalSet) -> Option<i64> {
+        self.overlaps(inputs)
+            .map(|(r, offset)| r.end - 1 + offset)
+            .max()
+    }
+
+    /// Intersections of the inputs with each piece's domain, along with that piece's offset
+    fn overlaps<'a>(
+        &'a self,
+        inputs: &'a IntervalSet,
+    ) -> impl Iterator<Item = (ops::Range<i64>, i64)> + 'a {
+        inputs.intervals.iter().flat_map(move |r| {
+            let first = self.pieces.partition_point(|p| p.domain.end <= r.start);
+            self.pieces[first..]
+                .iter()
+                .take_while(move |p| p.domain.start < r.end)
+                .map(move |p| {
+                    (
+                        r.start.max(p.domain.start)..r.end.min(p.domain.end),
+                        p.offset,
+                    )
+                })
+        })
+    }
+}
+
+/// Something suspicious about a map that doesn't prevent using it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlmanacWarning {
+    Overlap {
+        map: String,
+        first: ops::Range<i64>,
+        second: ops::Range<i64>,
+    },
+    Gap {
+        map: String,
+        gap: ops::Range<i64>,
+    },
+}
+
+impl std::fmt::Display for AlmanacWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlmanacWarning::Overlap { map, first, second } => {
+                write!(f, "{map}: source ranges {first:?} and {second:?} overlap")
+            }
+            AlmanacWarning::Gap { map, gap } => {
+                write!(f, "{map}: source range {gap:?} is not mapped")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 struct Almanac {
     seeds: Vec<i64>,
     projections: Vec<Projection>,
 }
 
 impl Almanac {
-    pub fn parse(input: &str) -> Self {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
         let double_line_ending: Regex = Regex::new(r"\r?\n\r?\n").unwrap();
 
         let sections = double_line_ending.split(input).collect_vec();
 
-        let seeds: Vec<i64> = sections
-            .first()
-            .unwrap()
-            .trim_start_matches("seeds: ")
-            .split(" ")
-            .map(str::parse)
-            .flatten()
-            .collect_vec();
+        let seeds = sections[0]
+            .strip_prefix("seeds: ")
+            .ok_or(ParseErrorKind::Missing("`seeds: `").at(1))?;
+        let seeds: Vec<i64> = parse_numbers(seeds).map_err(|kind| kind.at(1))?;
+
+        if seeds.len() % 2 == 1 {
+            let pairs = "Seeds don't come in start and length pairs".to_string();
+            return Err(ParseErrorKind::Invalid(pairs).at(1));
+        }
+        if seeds
+            .chunks(2)
+            .any(|w| w[1] < 0 || w[0].checked_add(w[1]).is_none())
+        {
+            let range = "Seed range doesn't fit in an i64".to_string();
+            return Err(ParseErrorKind::Invalid(range).at(1));
+        }
 
         let projections = sections
             .iter()
             .skip(1)
-            .map(|section: &&str| Projection::parse(&section))
-            .collect_vec();
+            .map(|section| {
+                Projection::parse(section).map_err(|e| e.offset(parse::line_of(input, section) - 1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-        Self { seeds, projections }
+        Ok(Self { seeds, projections })
     }
 
     fn map_to(&self, source: i64) -> i64 {
@@ -175,23 +572,141 @@ impl Almanac {
             .iter()
             .fold(ranges, |i, p: &Projection| p.map_ranges(i))
     }
+
+    /// Composes the maps from seed to location into a single function
+    pub fn compose(&self) -> Result<PiecewiseLinear, String> {
+        self.chain("seed", "location")
+    }
+
+    /// Checks that every map picks up where the previous one left off
+    pub fn validate(&self) -> Result<Vec<AlmanacWarning>, String> {
+        match self.projections.first() {
+            Some(p) if p.source != "seed" => {
+                return Err(format!("Chain starts at `{}` instead of `seed`", p.source))
+            }
+            None => return Err("Almanac has no maps".to_string()),
+            _ => {}
+        }
+
+        for (a, b) in self.projections.iter().tuple_windows() {
+            if a.destination != b.source {
+                return Err(format!(
+                    "`{}` map is followed by `{}` map, expected a map from `{}`",
+                    a.name(),
+                    b.name(),
+                    a.destination
+                ));
+            }
+        }
+
+        return Ok(self
+            .projections
+            .iter()
+            .flat_map(Projection::validate)
+            .collect());
+    }
+
+    /// Composes the maps leading from one category to another along the declared chain
+    pub fn chain(&self, from: &str, to: &str) -> Result<PiecewiseLinear, String> {
+        let mut f = PiecewiseLinear::identity();
+        let mut category = from;
+        let mut visited = HashSet::from([from]);
+
+        while category != to {
+            let projection = self
+                .projections
+                .iter()
+                .find(|p| p.source == category)
+                .ok_or_else(|| format!("No map leads from `{category}` towards `{to}`"))?;
+
+            f = f.then(&projection.to_piecewise()?);
+            category = &projection.destination;
+
+            if !visited.insert(category) {
+                return Err(format!(
+                    "Maps from `{from}` form a cycle without reaching `{to}`"
+                ));
+            }
+        }
+
+        return Ok(f);
+    }
+
+    fn seed_ranges(&self) -> IntervalSet {
+        IntervalSet::new(self.seeds.chunks(2).map(|w| w[0]..(w[0] + w[1])))
+    }
+}
+
+impl std::fmt::Display for Almanac {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "seeds: {}", self.seeds.iter().join(" "))?;
+        self.projections
+            .iter()
+            .try_for_each(|projection| write!(f, "\n\n{projection}"))
+    }
 }
 
 pub fn part1(input: &str) -> i64 {
-    let almanac = Almanac::parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let result = almanac
-        .seeds
-        .iter()
-        .map(|s| almanac.map_to(*s))
-        .min()
-        .unwrap();
+/// Reports every seed followed to its location, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<i64, TimedOut> {
+    let almanac = Almanac::parse(input).unwrap();
+    let seeds = &almanac.seeds;
 
-    return result;
+    progress.start("Seeds", Some(seeds.len() as u64));
+    let mut result = i64::MAX;
+    for (i, seed) in seeds.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} seeds located", seeds.len()))?;
+        result = result.min(almanac.map_to(*seed));
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return Ok(result);
 }
 
-pub fn part2(input: &str) -> i64 {
-    let almanac = Almanac::parse(input);
+pub fn part2(input: &str) -> Result<i64, String> {
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports composing the maps into the seed to location function, or stops before when `cancel`
+/// does. The inner error says why the almanac has no answer, its warnings are logged
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<Result<i64, String>, TimedOut> {
+    progress.start("Almanac", Some(1));
+    cancel.check(|| "No maps composed".to_string())?;
+    let result = lowest_location(input);
+    progress.advance(1);
+    progress.finish();
+
+    return Ok(result);
+}
+
+/// The lowest location of any seed range, found through the maps composed into one function
+fn lowest_location(input: &str) -> Result<i64, String> {
+    let almanac = Almanac::parse(input)?;
+    for warning in almanac.validate()? {
+        warn!("{warning}");
+    }
+
+    let f = almanac.compose()?;
+    return f
+        .min_over(&almanac.seed_ranges())
+        .ok_or("Almanac has no seeds".to_string());
+}
+
+/// Splits the seed ranges at every mapping boundary, one projection at a time
+pub fn part2_range_split(input: &str) -> i64 {
+    let almanac = Almanac::parse(input).unwrap();
 
     let seed_ranges: Vec<Range> = almanac
         .seeds
@@ -213,7 +728,7 @@ pub fn part2(input: &str) -> i64 {
 }
 
 pub fn part2_brute_force(input: &str) -> i64 {
-    let almanac = Almanac::parse(input);
+    let almanac = Almanac::parse(input).unwrap();
 
     let seeds: Vec<i64> = almanac
         .seeds
@@ -226,17 +741,33 @@ pub fn part2_brute_force(input: &str) -> i64 {
     return result;
 }
 
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(almanac) = Almanac::parse(input) {
+        if almanac.compose().is_ok() {
+            for seed in &almanac.seeds {
+                let location = almanac.chain("seed", "location").map(|f| f.apply(*seed));
+                assert_eq!(location, Ok(almanac.map_to(*seed)));
+            }
+        }
+        assert_eq!(Almanac::parse(&almanac.to_string()), Ok(almanac));
+    }
+}
+
 pub fn process(input: String) {
     use std::time::Instant;
     let now = Instant::now();
-    let result = part2(&input);
-    println!("Result: {:?}", result);
+    match part2(&input) {
+        Ok(result) => println!("Result: {result}"),
+        Err(e) => println!("{e}"),
+    }
     println!("Finished in: {:.2?}", now.elapsed());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "seeds: 79 14 55 13
 
@@ -288,13 +819,260 @@ humidity-to-location map:
     #[test]
     fn part2_example() {
         let result = part2(EXAMPLE);
-        assert_eq!(result, 46);
+        assert_eq!(result, Ok(46));
     }
 
     #[test]
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
+        assert_eq!(result, Ok(7873084));
+    }
+
+    #[test]
+    fn part2_reports_broken_chains() {
+        let skipped = EXAMPLE.replacen("seed-to-soil", "seed-to-dirt", 1);
+        assert_eq!(
+            part2(&skipped),
+            Err("`seed-to-dirt` map is followed by `soil-to-fertilizer` map, expected a map from `dirt`".to_string())
+        );
+        assert!(part2("seeds: 1 2").is_err());
+    }
+
+    #[test]
+    fn part2_range_split_input() {
+        let input = include_str!("input.txt");
+        let result = part2_range_split(input);
         assert_eq!(result, 7873084);
     }
+
+    #[test]
+    fn category_chain() {
+        let almanac = Almanac::parse(EXAMPLE).unwrap();
+
+        assert_eq!(almanac.validate(), Ok(vec![]));
+        assert_eq!(almanac.chain("seed", "soil").map(|f| f.apply(79)), Ok(81));
+        assert_eq!(almanac.chain("soil", "water").map(|f| f.apply(81)), Ok(81));
+        assert_eq!(almanac.chain("seed", "location").map(|f| f.apply(14)), Ok(43));
+        assert_eq!(almanac.chain("light", "light").map(|f| f.apply(5)), Ok(5));
+        assert!(almanac.chain("location", "seed").is_err());
+    }
+
+    #[test]
+    fn validation() {
+        let almanac = Almanac::parse(
+            "seeds: 1 2
+
+seed-to-soil map:
+50 10 5
+60 12 5
+70 20 5",
+        )
+        .unwrap();
+        assert_eq!(
+            almanac.validate(),
+            Ok(vec![
+                AlmanacWarning::Overlap {
+                    map: "seed-to-soil".to_string(),
+                    first: 10..15,
+                    second: 12..17
+                },
+                AlmanacWarning::Gap {
+                    map: "seed-to-soil".to_string(),
+                    gap: 17..20
+                }
+            ])
+        );
+        assert_eq!(almanac.chain("seed", "soil").unwrap().apply(13), 53);
+
+        let broken = Almanac::parse(
+            "seeds: 1 2
+
+seed-to-soil map:
+50 10 5
+
+water-to-light map:
+50 10 5",
+        )
+        .unwrap();
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn validation_compares_with_the_furthest_mapping() {
+        let almanac = Almanac::parse(
+            "seeds: 1 2
+
+seed-to-soil map:
+50 0 20
+60 5 5
+70 15 10",
+        )
+        .unwrap();
+        assert_eq!(
+            almanac.validate(),
+            Ok(vec![
+                AlmanacWarning::Overlap {
+                    map: "seed-to-soil".to_string(),
+                    first: 0..20,
+                    second: 5..10
+                },
+                AlmanacWarning::Overlap {
+                    map: "seed-to-soil".to_string(),
+                    first: 0..20,
+                    second: 15..25
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn chains_stop_at_cycles_away_from_the_start() {
+        let almanac = Almanac::parse(
+            "seeds: 1 2
+
+a-to-b map:
+0 0 1
+
+b-to-c map:
+0 0 1
+
+c-to-b map:
+0 0 1",
+        )
+        .unwrap();
+        assert!(almanac
+            .chain("a", "z")
+            .unwrap_err()
+            .contains("form a cycle"));
+        assert!(almanac
+            .chain("b", "z")
+            .unwrap_err()
+            .contains("form a cycle"));
+    }
+
+    #[test]
+    fn interval_set_operations() {
+        let a = IntervalSet::new([0..10, 20..30]);
+        let b = IntervalSet::new([5..25, 28..29]);
+
+        assert_eq!(a.union(&b), IntervalSet::from_range(0..30));
+        assert_eq!(
+            a.intersection(&b),
+            IntervalSet::new([5..10, 20..25, 28..29])
+        );
+        assert_eq!(a.difference(&b), IntervalSet::new([0..5, 25..28, 29..30]));
+        assert_eq!(b.difference(&a), IntervalSet::from_range(10..20));
+        assert!(a.contains(29) && !a.contains(10));
+    }
+
+    #[test]
+    fn piecewise_covers_the_whole_number_line() {
+        let projection = Projection::parse("seed-to-soil map:\n50 98 2\n52 50 48").unwrap();
+        let f = projection.to_piecewise().unwrap();
+
+        assert_eq!(f.apply(i64::MIN), i64::MIN);
+        assert_eq!(f.apply(i64::MAX), i64::MAX);
+        assert_eq!(f.apply(99), 51);
+
+        let far = format!("seed-to-soil map:\n{} {} 1", i64::MAX - 1, -2);
+        let far = Projection::parse(&far).unwrap();
+        assert!(far.to_piecewise().is_err());
+    }
+
+    #[test]
+    fn composed_almanac() {
+        let almanac = Almanac::parse(EXAMPLE).unwrap();
+        let f = almanac.compose().unwrap();
+
+        for seed in 0..120 {
+            assert_eq!(f.apply(seed), almanac.map_to(seed));
+        }
+
+        let seeds = almanac.seed_ranges();
+        assert_eq!(
+            f.max_over(&seeds),
+            seeds
+                .intervals()
+                .iter()
+                .flat_map(|r| r.clone())
+                .map(|s| almanac.map_to(s))
+                .max()
+        );
+
+        // Location 46 comes from seed 82, the answer to part 2
+        let candidates = f.preimage(&IntervalSet::from_range(46..47));
+        assert!(candidates.contains(82));
+        assert_eq!(
+            candidates.intersection(&seeds),
+            IntervalSet::from_range(82..83)
+        );
+        assert_eq!(f.image(&candidates), IntervalSet::from_range(46..47));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_almanacs() {
+        use ParseErrorKind::*;
+        let error = |input: &str| Almanac::parse(input).unwrap_err();
+
+        assert!(matches!(
+            error("seeds: 1 2 3"),
+            ParseError {
+                line: 1,
+                kind: Invalid(_)
+            }
+        ));
+        assert_eq!(error("seeds: 1 x"), InvalidNumber("x".to_string()).at(1));
+        assert_eq!(
+            error("1 2\n\nseed-to-soil map:\n1 2 3"),
+            Missing("`seeds: `").at(1)
+        );
+        assert_eq!(
+            error("seeds: 1 2\n\nseed-to-soil:\n1 2 3"),
+            Missing("`<source>-to-<destination> map:`").at(3)
+        );
+        let mapping = |m: &str| error(&format!("seeds: 1 2\n\nseed-to-soil map:\n0 0 1\n{m}"));
+        assert!(matches!(
+            mapping("1 2"),
+            ParseError {
+                line: 5,
+                kind: Invalid(_)
+            }
+        ));
+        assert!(matches!(
+            mapping("1 2 0"),
+            ParseError {
+                line: 5,
+                kind: Invalid(_)
+            }
+        ));
+        assert!(matches!(
+            mapping("9223372036854775807 0 2"),
+            ParseError {
+                line: 5,
+                kind: Invalid(_)
+            }
+        ));
+        assert_eq!(error("seeds: 1 2\n\n"), Missing("a map header").at(3));
+    }
+
+    #[test]
+    fn almanac_round_trips() {
+        for input in [EXAMPLE, include_str!("input.txt")] {
+            let almanac = Almanac::parse(input).unwrap();
+            assert_eq!(Almanac::parse(&almanac.to_string()), Ok(almanac));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_almanacs(
+            input in "seeds:( -?[0-9]{1,20}){0,4}(\n\n[a-z]{1,3}-to-[a-z]{1,3} map:(\n-?[0-9]{1,20} -?[0-9]{1,20} -?[0-9]{1,20}){0,3}){0,3}"
+        ) {
+            if let Ok(almanac) = Almanac::parse(&input) {
+                let _ = almanac.validate();
+                prop_assert_eq!(Almanac::parse(&almanac.to_string()), Ok(almanac));
+            }
+        }
+    }
 }
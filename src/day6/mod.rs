@@ -1,15 +1,20 @@
-use indicatif::ProgressIterator;
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
 use itertools::Itertools;
 use nom::{
     bytes::complete::tag,
     character::complete::{digit1, line_ending, multispace1},
     combinator::map_res,
+    error::ErrorKind,
     multi::separated_list1,
     sequence::{preceded, separated_pair},
     IResult, Parser,
 };
+use std::ops::RangeInclusive;
+use tracing::{instrument, trace};
 
-pub fn parse(input: &str) -> (Vec<u64>, Vec<u64>) {
+pub fn parse(input: &str) -> Result<(Vec<u64>, Vec<u64>), ParseError> {
     pub fn number(input: &str) -> IResult<&str, u64> {
         map_res(digit1, str::parse)(input)
     }
@@ -26,56 +31,235 @@ pub fn parse(input: &str) -> (Vec<u64>, Vec<u64>) {
         ),
     );
 
-    let (_, result) = parser.parse(input).unwrap();
+    let (_, (times, distances)) = parser.parse(input).map_err(|e| {
+        let (rest, code) = match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => (e.input, e.code),
+            nom::Err::Incomplete(_) => (&input[input.len()..], ErrorKind::Eof),
+        };
+        let line = parse::line_of(input, rest);
 
-    return result;
+        let kind = match (code, rest.trim_start_matches(' ').chars().next()) {
+            (ErrorKind::MapRes, _) => ParseErrorKind::InvalidNumber(
+                rest.chars().take_while(char::is_ascii_digit).collect(),
+            ),
+            (ErrorKind::Tag, _) if line == 1 => ParseErrorKind::Missing("`Time:`"),
+            (ErrorKind::Tag, _) => ParseErrorKind::Missing("`Distance:`"),
+            (ErrorKind::CrLf, Some(c)) => ParseErrorKind::UnexpectedChar(c),
+            (ErrorKind::CrLf, None) => ParseErrorKind::Missing("the distances"),
+            _ => ParseErrorKind::Missing("a number"),
+        };
+        return kind.at(line);
+    })?;
+
+    if times.len() != distances.len() {
+        let counts = format!(
+            "Found {} times but {} distances",
+            times.len(),
+            distances.len()
+        );
+        return Err(ParseErrorKind::Invalid(counts).at(2));
+    }
+
+    return Ok((times, distances));
 }
 
-fn winning_races_brute_force(time: u64, record_distance: u64) -> usize {
+#[instrument(skip(progress))]
+pub fn winning_races_brute_force(
+    time: u64,
+    record_distance: u64,
+    progress: &mut dyn Progress,
+) -> usize {
+    progress.start("Hold times", Some(time.saturating_sub(1)));
+
     let result = (1..time)
-        .progress_count(time)
+        .inspect(|_| progress.advance(1))
         .map(|hold| {
             let speed = hold;
             let time_remaining = time - hold;
             let distance = speed * time_remaining;
-            // println!("Hold for {hold}, distance {distance} limit {limit}");
+            trace!(hold, distance, record_distance);
             return distance;
         })
         .filter(|a| a > &record_distance)
         .count();
 
+    progress.finish();
     return result;
 }
 
-/// distance traveled is equal to total time - time pressed, times time pressed
-///
-/// total time: t
-/// time pressed: x
-/// recordDistance = d
-///
-/// (t - x) * x = d => -x^2 + xt - d = 0
-///
-/// equation solutions are min and max time pressed to beat record
-fn winning_races_equation(time: u64, record_distance: u64) -> usize {
-    let t = time as f64;
-    let d = record_distance as f64;
+/// How far a boat goes in a race of `time` depending on how long the button is held
+pub trait DistanceModel {
+    fn distance(&self, time: u64, hold: u64) -> u128;
+
+    /// The hold time going furthest, the distance never falls before it nor rises after it
+    fn peak(&self, time: u64) -> u64;
+
+    /// Every hold time beating `record`, found by bisecting both sides of the peak
+    fn winning_holds(&self, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        let wins = |hold: u64| self.distance(time, hold) > u128::from(record);
+        let peak = self.peak(time);
+        if !wins(peak) {
+            return None;
+        }
+
+        let min_hold = first_where(0, peak, wins);
+        let max_hold = match wins(time) {
+            true => time,
+            false => first_where(peak, time, |hold| !wins(hold)) - 1,
+        };
+
+        return Some(min_hold..=max_hold);
+    }
+}
+
+/// The first of `low..=high` where `predicate` holds, given it holds at `high` and keeps holding
+/// once it does
+fn first_where(mut low: u64, mut high: u64, predicate: impl Fn(u64) -> bool) -> u64 {
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match predicate(middle) {
+            true => high = middle,
+            false => low = middle + 1,
+        }
+    }
+
+    return high;
+}
+
+/// Counts every hold time beating `record`
+pub fn winning_races(model: &impl DistanceModel, time: u64, record: u64) -> u64 {
+    model
+        .winning_holds(time, record)
+        .map_or(0, |holds| holds.end() - holds.start() + 1)
+}
+
+/// A boat gaining `acceleration` millimeters per millisecond of speed for every millisecond the
+/// button is held, 1 in the puzzle
+#[derive(Debug, Clone, Copy)]
+pub struct Boat {
+    pub acceleration: u64,
+}
+
+impl DistanceModel for Boat {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        let speed = u128::from(self.acceleration) * u128::from(hold);
+        speed.saturating_mul(u128::from(time - hold))
+    }
+
+    fn peak(&self, time: u64) -> u64 {
+        time / 2
+    }
+
+    /// distance traveled is equal to total time - time pressed, times time pressed
+    ///
+    /// total time: t
+    /// time pressed: x
+    /// recordDistance = d
+    ///
+    /// (t - x) * x = d => -x^2 + xt - d = 0
+    ///
+    /// equation solutions are min and max time pressed to beat record, found with an integer
+    /// square root so it stays exact for any `u64`
+    fn winning_holds(&self, time: u64, record: u64) -> Option<RangeInclusive<u64>> {
+        if self.acceleration == 0 {
+            return None;
+        }
+
+        // Holds travel whole millimeters, so beating the record by acceleration is the same as
+        // beating its floor
+        let t = u128::from(time);
+        let d = u128::from(record / self.acceleration);
+        let wins = |x: u128| x * (t - x) > d;
+
+        // * D = b^2 - 4ac
+        //  * so, t^2 - 4d, which fits as t < 2^64
+        let discriminant = (t * t).checked_sub(4 * d)?;
+
+        // * x_1 = (-b - sqrt of D)/2, rounded down so it's within one of the first winning hold
+        let mut min_time = (t - discriminant.isqrt()) / 2;
+        while min_time > 0 && wins(min_time - 1) {
+            min_time -= 1;
+        }
+        while min_time <= t / 2 && !wins(min_time) {
+            min_time += 1;
+        }
+        if min_time > t / 2 {
+            return None;
+        }
+
+        // The parabola is symmetric around t / 2
+        let max_time = t - min_time;
+        trace!(%min_time, %max_time, "Hold times beating the record");
+
+        return Some(min_time as u64..=max_time as u64);
+    }
+}
+
+/// A boat only moving once its speed overcomes a constant `drag`
+#[derive(Debug, Clone, Copy)]
+pub struct DraggedBoat {
+    pub acceleration: u64,
+    pub drag: u64,
+}
+
+impl DistanceModel for DraggedBoat {
+    fn distance(&self, time: u64, hold: u64) -> u128 {
+        let speed = u128::from(self.acceleration) * u128::from(hold);
+        let speed = speed.saturating_sub(u128::from(self.drag));
+        speed.saturating_mul(u128::from(time - hold))
+    }
+
+    /// (a x - k) (t - x) peaks at x = (a t + k) / 2a, whole holds peak on either side of it
+    fn peak(&self, time: u64) -> u64 {
+        if self.acceleration == 0 {
+            return 0;
+        }
 
-    // * D = b^2 - 4ac
-    //  * so, t^2 - 4d
-    let d_root = (t * t - 4. * d).sqrt();
+        let a = u128::from(self.acceleration);
+        let vertex = (a * u128::from(time) + u128::from(self.drag)) / (2 * a);
+        let vertex = vertex.min(u128::from(time)) as u64;
 
-    // * x_1,2 = (-b +- sqrt of D)/2
-    let a = (t - d_root) / 2.;
-    let b = (t + d_root) / 2.;
+        return [vertex, (vertex + 1).min(time)]
+            .into_iter()
+            .max_by_key(|hold| self.distance(time, *hold))
+            .unwrap();
+    }
+}
 
-    let min_time = (a + 0.0001).ceil() as usize;
-    let max_time = (b - 0.0001).floor() as usize;
+#[instrument]
+pub fn winning_races_equation(time: u64, record_distance: u64) -> usize {
+    return winning_races(&Boat { acceleration: 1 }, time, record_distance) as usize;
+}
+
+/// The product of the ways to win every race, reporting every race solved
+fn solve_races(
+    races: &[(u64, u64)],
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    progress.start("Races", Some(races.len() as u64));
+    let mut result = 1;
+    for (i, (t, d)) in races.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} races solved", races.len()))?;
+        result *= winning_races_equation(*t, *d);
+        progress.advance(1);
+    }
+    progress.finish();
 
-    return max_time - min_time + 1;
+    return Ok(result);
 }
 
 pub fn part1(input: &str) -> usize {
-    let (times, distances) = parse(&input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every race solved, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let (times, distances) = parse(input).unwrap();
 
     let races = times
         .iter()
@@ -83,28 +267,34 @@ pub fn part1(input: &str) -> usize {
         .map(|(time, distance)| (*time, *distance))
         .collect_vec();
 
-    let result = races
-        .iter()
-        .map(|(t, d)| winning_races_equation(*t, *d))
-        .product::<usize>();
-
-    return result;
+    return solve_races(&races, progress, cancel);
 }
 
 pub fn part2(input: &str) -> usize {
-    let (times, distances) = parse(&input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Solves the one long race, unless `cancel` stopped it before it began
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let (times, distances) = parse(input).unwrap();
 
     let time: u64 = times.iter().join("").parse().unwrap();
     let distance: u64 = distances.iter().join("").parse().unwrap();
 
     let races = vec![(time, distance)];
 
-    let result = races
-        .iter()
-        .map(|(t, d)| winning_races_equation(*t, *d))
-        .product::<usize>();
+    return solve_races(&races, progress, cancel);
+}
 
-    return result;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Err(error) = parse(input) {
+        assert!(error.line <= input.lines().count() + 1);
+    }
 }
 
 pub fn process(input: String) {
@@ -118,6 +308,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = "Time:      7  15   30
 Distance:  9  40  200";
@@ -147,4 +338,118 @@ Distance:  9  40  200";
         let result = part2(input);
         assert_eq!(result, 42515755);
     }
+
+    #[test]
+    fn parse_rejects_malformed_sheets() {
+        use ParseErrorKind::*;
+
+        assert!(matches!(
+            parse("Time: 7 15\nDistance: 9"),
+            Err(ParseError {
+                line: 2,
+                kind: Invalid(_)
+            })
+        ));
+        assert_eq!(
+            parse("Time: 7\nRecord: 9"),
+            Err(Missing("`Distance:`").at(2))
+        );
+        assert_eq!(parse("Time: 7"), Err(Missing("the distances").at(1)));
+        assert_eq!(
+            parse("Time: 7 x\nDistance: 9"),
+            Err(UnexpectedChar('x').at(1))
+        );
+        assert_eq!(
+            parse("Time: 7\nDistance: x"),
+            Err(Missing("a number").at(2))
+        );
+        assert_eq!(
+            parse("Time: 99999999999999999999\nDistance: 9"),
+            Err(InvalidNumber("99999999999999999999".to_string()).at(1))
+        );
+    }
+
+    #[test]
+    fn touching_the_record_doesnt_win() {
+        let boat = Boat { acceleration: 1 };
+        assert_eq!(boat.winning_holds(30, 200), Some(11..=19));
+        assert_eq!(boat.winning_holds(30, 224), Some(15..=15));
+        assert_eq!(boat.winning_holds(30, 225), None);
+        assert_eq!(boat.winning_holds(0, 0), None);
+        assert_eq!(Boat { acceleration: 0 }.winning_holds(30, 0), None);
+    }
+
+    #[test]
+    fn equation_stays_exact_past_f64() {
+        let time = u64::MAX;
+        let record = u64::MAX - 1;
+        let holds = Boat { acceleration: 1 }
+            .winning_holds(time, record)
+            .unwrap();
+
+        assert_eq!(holds, 2..=u64::MAX - 2);
+        // Past 2^53 an f64 can't hold every whole number any more
+        let time = (1 << 60) + 3;
+        let record = 1 << 61;
+        let boat = Boat { acceleration: 1 };
+        let wins = |hold: u64| boat.distance(time, hold) > u128::from(record);
+        let holds = boat.winning_holds(time, record).unwrap();
+        assert!(wins(*holds.start()) && !wins(holds.start() - 1));
+        assert!(wins(*holds.end()) && !wins(holds.end() + 1));
+        assert_eq!(holds.start() + holds.end(), time);
+    }
+
+    #[test]
+    fn models_bisect_their_peak() {
+        let boat = Boat { acceleration: 3 };
+        let bisected = DraggedBoat {
+            acceleration: 3,
+            drag: 0,
+        };
+        assert_eq!(boat.winning_holds(30, 600), Some(11..=19));
+        assert_eq!(bisected.winning_holds(30, 600), Some(11..=19));
+
+        // Holding 3 only just overcomes the drag, 16 goes furthest with (32 - 5) * 14
+        let dragged = DraggedBoat {
+            acceleration: 2,
+            drag: 5,
+        };
+        assert_eq!(dragged.peak(30), 16);
+        assert_eq!(dragged.distance(30, 16), 378);
+        assert_eq!(dragged.winning_holds(30, 0), Some(3..=29));
+        assert_eq!(dragged.winning_holds(30, 376), Some(16..=17));
+        assert_eq!(winning_races(&dragged, 30, 378), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn equation_matches_brute_force(time in 0..2000u64, record in 0..1_000_000u64) {
+            prop_assert_eq!(
+                winning_races_equation(time, record),
+                winning_races_brute_force(time, record, &mut ())
+            );
+        }
+
+        #[test]
+        fn equation_matches_bisection(time: u64, record: u64, acceleration in 1..100u64) {
+            let boat = Boat { acceleration };
+            let bisected = DraggedBoat { acceleration, drag: 0 };
+            prop_assert_eq!(boat.winning_holds(time, record), bisected.winning_holds(time, record));
+        }
+
+        #[test]
+        fn bisection_matches_every_hold(time in 0..300u64, record in 0..20_000u64, acceleration in 0..5u64, drag in 0..50u64) {
+            let model = DraggedBoat { acceleration, drag };
+            let holds = (0..=time).filter(|h| model.distance(time, *h) > u128::from(record)).collect_vec();
+            let expected = holds.first().map(|first| *first..=*holds.last().unwrap());
+            prop_assert_eq!(model.winning_holds(time, record), expected);
+        }
+
+        #[test]
+        fn parse_reads_every_race(races in prop::collection::vec((0..1000u64, 0..1000u64), 1..5)) {
+            let (times, distances): (Vec<u64>, Vec<u64>) = races.iter().copied().unzip();
+            let input = format!("Time: {}\nDistance: {}", times.iter().join(" "), distances.iter().join(" "));
+            prop_assert_eq!(parse(&input), Ok((times, distances)));
+        }
+    }
 }
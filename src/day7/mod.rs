@@ -1,210 +1,510 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Card {
-    A,
-    K,
-    Q,
-    J,
-    T,
-    N(u8),
-}
+use std::{cmp::Ordering, io::Write};
 
-impl Card {
-    pub fn value(&self) -> u8 {
-        match &self {
-            Card::A => 14,
-            Card::K => 13,
-            Card::Q => 12,
-            Card::J => 11,
-            Card::T => 10,
-            Card::N(n) => *n,
-        }
-    }
+use itertools::Itertools;
+
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError, ParseErrorKind};
+use crate::progress::Progress;
 
-    pub fn value2(&self) -> u8 {
-        match &self {
-            Card::A => 14,
-            Card::K => 13,
-            Card::Q => 12,
-            Card::J => 1,
-            Card::T => 10,
-            Card::N(n) => *n,
+/// A category of hands, like a full house, needing a group of cards of the same value for every
+/// entry of `groups`, largest first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Category {
+    pub name: String,
+    pub groups: Vec<usize>,
+}
+
+impl Category {
+    pub fn new(name: &str, groups: &[usize]) -> Category {
+        Category {
+            name: name.to_string(),
+            groups: groups.to_vec(),
         }
     }
 
-    pub fn parse(input: char) -> Option<Card> {
-        match input {
-            'A' => Some(Card::A),
-            'K' => Some(Card::K),
-            'Q' => Some(Card::Q),
-            'J' => Some(Card::J),
-            'T' => Some(Card::T),
-            c if c.is_digit(10) => Some(Card::N(c.to_digit(10).unwrap() as u8)),
-            _ => None,
-        }
+    /// The categories of Camel Cards, strongest first
+    pub fn camel_cards() -> Vec<Category> {
+        vec![
+            Category::new("Five of a kind", &[5]),
+            Category::new("Four of a kind", &[4]),
+            Category::new("Full house", &[3, 2]),
+            Category::new("Three of a kind", &[3]),
+            Category::new("Two pairs", &[2, 2]),
+            Category::new("One pair", &[2]),
+            Category::new("High card", &[]),
+        ]
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
-enum Kind {
-    FiveOfAKind,
-    FourOfAKind,
-    FullHouse,
-    ThreeOfAKind,
-    TwoPairs,
-    OnePair,
-    HighCard,
+/// The rules of a game ranking hands, first by category then card by card
+#[derive(Debug, Clone)]
+pub struct Rules {
+    /// Every card from weakest to strongest
+    order: Vec<char>,
+    /// Cards standing in for whichever cards make the strongest category
+    wildcards: Vec<char>,
+    hand_length: usize,
+    /// Strongest first, the last one takes any hand
+    categories: Vec<Category>,
 }
 
-impl Kind {
-    pub fn rank(&self) -> usize {
-        match self {
-            Kind::FiveOfAKind => 7,
-            Kind::FourOfAKind => 6,
-            Kind::FullHouse => 5,
-            Kind::ThreeOfAKind => 4,
-            Kind::TwoPairs => 3,
-            Kind::OnePair => 2,
-            Kind::HighCard => 1,
+impl Rules {
+    pub fn new(
+        order: &str,
+        wildcards: &str,
+        hand_length: usize,
+        categories: Vec<Category>,
+    ) -> Result<Rules, String> {
+        let order = order.chars().collect_vec();
+        if let Some(card) = order.iter().duplicates().next() {
+            return Err(format!("Card `{card}` ranked twice"));
+        }
+        if let Some(card) = wildcards.chars().find(|c| !order.contains(c)) {
+            return Err(format!("Wildcard `{card}` isn't a card"));
+        }
+        for category in &categories {
+            let needed = category.groups.iter().sum::<usize>();
+            if needed > hand_length || category.groups.contains(&0) {
+                return Err(format!("No hand of {hand_length} fits {}", category.name));
+            }
+            if !category.groups.windows(2).all(|w| w[0] >= w[1]) {
+                return Err(format!(
+                    "Groups of {} should be largest first",
+                    category.name
+                ));
+            }
+        }
+        if categories.last().is_none_or(|c| !c.groups.is_empty()) {
+            return Err("The last category should take any hand".to_string());
         }
+
+        Ok(Rules {
+            order,
+            wildcards: wildcards.chars().collect(),
+            hand_length,
+            categories,
+        })
     }
-}
 
-#[derive(Debug, PartialEq)]
-struct Hand {
-    cards: Vec<Card>,
-}
+    /// Part 1
+    pub fn camel_cards() -> Rules {
+        Rules::new("23456789TJQKA", "", 5, Category::camel_cards()).unwrap()
+    }
+
+    /// Part 2, jokers are the weakest card but stand in for any other
+    pub fn camel_cards_with_jokers() -> Rules {
+        Rules::new("J23456789TQKA", "J", 5, Category::camel_cards()).unwrap()
+    }
+
+    pub fn parse_hand(&self, input: &str) -> Result<Hand, ParseErrorKind> {
+        let cards = input.chars().collect_vec();
+        if let Some(c) = cards.iter().find(|c| !self.order.contains(c)) {
+            return Err(ParseErrorKind::UnexpectedChar(*c));
+        }
+        if cards.len() != self.hand_length {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Hand `{input}` doesn't have {} cards",
+                self.hand_length
+            )));
+        }
 
-impl Hand {
-    pub fn new(cards: Vec<Card>) -> Self {
-        Self { cards }
+        Ok(Hand { cards })
     }
 
-    pub fn parse(input: &str) -> Self {
-        let cards: Vec<Card> = input.chars().map(Card::parse).flatten().collect();
-        Hand::new(cards)
+    fn rank(&self, card: char) -> usize {
+        self.order.iter().position(|c| *c == card).unwrap()
     }
 
-    pub fn replace_joker(&self, card: Card) -> Hand {
-        let cards = self
+    /// The strongest category the wildcards can make of `hand`, and the cards they stand for
+    pub fn evaluate(&self, hand: &Hand) -> Evaluation {
+        let wildcards = hand
             .cards
             .iter()
-            .map(|c| {
-                if c.value2() == Card::J.value2() {
-                    card.clone()
-                } else {
-                    c.clone()
-                }
+            .filter(|c| self.wildcards.contains(c))
+            .count();
+
+        // Groups of the same card, largest then strongest first. Pairing them with the largest
+        // groups of a category first leaves the wildcards the fewest cards to fill in
+        let groups = hand
+            .cards
+            .iter()
+            .filter(|c| !self.wildcards.contains(c))
+            .counts()
+            .into_iter()
+            .sorted_by_key(|(card, count)| {
+                (
+                    std::cmp::Reverse(*count),
+                    std::cmp::Reverse(self.rank(**card)),
+                )
             })
-            .collect();
-        return Hand::new(cards);
-    }
+            .map(|(card, count)| (*card, count))
+            .collect_vec();
+        let unused = self
+            .order
+            .iter()
+            .rev()
+            .filter(|c| !self.wildcards.contains(c) && !groups.iter().any(|(g, _)| g == *c))
+            .copied()
+            .collect_vec();
+
+        for (index, category) in self.categories.iter().enumerate() {
+            let new_groups = category.groups.len().saturating_sub(groups.len());
+            if new_groups > unused.len() {
+                continue;
+            }
+
+            // What every wildcard stands for, in the order they're filled in
+            let mut stand_ins = Vec::new();
+            for (i, needed) in category.groups.iter().enumerate() {
+                let (card, count) = groups
+                    .get(i)
+                    .copied()
+                    .unwrap_or_else(|| (unused[i - groups.len()], 0));
+                stand_ins.extend(std::iter::repeat_n(card, needed.saturating_sub(count)));
+            }
+            if stand_ins.len() > wildcards {
+                continue;
+            }
 
-    pub fn kind(&self) -> Kind {
-        let mut ordered_cards = self.cards.to_owned();
-        ordered_cards.sort_by_key(|c| c.value());
+            // The rest join the largest group, or the strongest card if there's none
+            let largest = stand_ins
+                .first()
+                .or(groups.first().map(|(c, _)| c))
+                .or(unused.first());
+            if let Some(card) = largest.copied() {
+                stand_ins.resize(wildcards, card);
+            }
 
-        let matching_tripples = ordered_cards
-            .windows(3)
-            .filter(|w| w[0].value() == w[1].value() && w[1].value() == w[2].value())
-            .count();
+            let mut stand_ins = stand_ins.into_iter();
+            let best = hand
+                .cards
+                .iter()
+                .map(|c| match self.wildcards.contains(c) {
+                    true => stand_ins.next().unwrap_or(*c),
+                    false => *c,
+                })
+                .collect();
+
+            return Evaluation {
+                category: index,
+                name: category.name.clone(),
+                best: Hand { cards: best },
+            };
+        }
 
-        let matching_pairs = ordered_cards
-            .windows(2)
-            .filter(|w| w[0].value() == w[1].value())
-            .count();
+        unreachable!("The last category takes any hand");
+    }
 
-        match (matching_tripples, matching_pairs) {
-            (3, _) => Kind::FiveOfAKind,
-            (2, _) => Kind::FourOfAKind,
-            (1, 3) => Kind::FullHouse,
-            (1, _) => Kind::ThreeOfAKind,
-            (0, 2) => Kind::TwoPairs,
-            (0, 1) => Kind::OnePair,
-            _ => Kind::HighCard,
+    /// Orders two hands, telling what decided it
+    pub fn compare(&self, a: &Hand, b: &Hand) -> Comparison {
+        let (a_eval, b_eval) = (self.evaluate(a), self.evaluate(b));
+        if a_eval.category != b_eval.category {
+            // Categories are listed strongest first
+            let ordering = b_eval.category.cmp(&a_eval.category);
+            let (stronger, weaker) = match ordering {
+                Ordering::Greater => (a_eval.name, b_eval.name),
+                _ => (b_eval.name, a_eval.name),
+            };
+            return Comparison {
+                ordering,
+                reason: Reason::Category { stronger, weaker },
+            };
         }
+
+        let differing = a
+            .cards
+            .iter()
+            .zip(&b.cards)
+            .enumerate()
+            .find(|(_, (x, y))| x != y);
+        let Some((position, (x, y))) = differing else {
+            return Comparison {
+                ordering: Ordering::Equal,
+                reason: Reason::Tie,
+            };
+        };
+
+        let ordering = self.rank(*x).cmp(&self.rank(*y));
+        let (stronger, weaker) = match ordering {
+            Ordering::Greater => (*x, *y),
+            _ => (*y, *x),
+        };
+        return Comparison {
+            ordering,
+            reason: Reason::Card {
+                position,
+                stronger,
+                weaker,
+            },
+        };
     }
 
-    pub fn kind2(&self) -> Kind {
-        let replacements = "23456789TQKA".chars().map(Card::parse).flatten();
-        let highest_kind_hand = replacements
-            .map(|r| self.replace_joker(r))
-            .max_by_key(|a| a.kind().rank())
-            .unwrap();
-        return highest_kind_hand.kind();
+    /// Sorts hands from weakest to strongest
+    fn sort_key(&self, hand: &Hand) -> (std::cmp::Reverse<usize>, Vec<usize>) {
+        let category = self.evaluate(hand).category;
+        let ranks = hand.cards.iter().map(|c| self.rank(*c)).collect();
+        return (std::cmp::Reverse(category), ranks);
     }
+}
 
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let kind = self.kind();
-        let other_kind = other.kind();
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Hand {
+    cards: Vec<char>,
+}
 
-        if kind.rank() == other_kind.rank() {
-            for (card, other_card) in self.cards.iter().zip(&other.cards) {
-                if card.value() != other_card.value() {
-                    return card.value().cmp(&other_card.value());
-                }
-            }
-            return std::cmp::Ordering::Equal;
-        } else {
-            return kind.rank().cmp(&other_kind.rank());
-        }
+impl std::fmt::Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.cards.iter().try_for_each(|card| write!(f, "{card}"))
     }
+}
+
+/// The category of a hand, `category` indexes the rules' categories
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    pub category: usize,
+    pub name: String,
+    /// The hand with every wildcard replaced by the card it stands for
+    pub best: Hand,
+}
 
-    fn cmp2(&self, other: &Self) -> std::cmp::Ordering {
-        let kind = self.kind2();
-        let other_kind = other.kind2();
+/// What decided between two hands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Category {
+        stronger: String,
+        weaker: String,
+    },
+    /// The first card that differs, counting from 0
+    Card {
+        position: usize,
+        stronger: char,
+        weaker: char,
+    },
+    Tie,
+}
 
-        if kind.rank() == other_kind.rank() {
-            for (card, other_card) in self.cards.iter().zip(&other.cards) {
-                if card.value2() != other_card.value2() {
-                    return card.value2().cmp(&&other_card.value2());
-                }
-            }
-            return std::cmp::Ordering::Equal;
-        } else {
-            return kind.rank().cmp(&other_kind.rank());
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::Category { stronger, weaker } => write!(f, "{stronger} beats {weaker}"),
+            Reason::Card {
+                position,
+                stronger,
+                weaker,
+            } => write!(f, "{stronger} beats {weaker} as card {}", position + 1),
+            Reason::Tie => write!(f, "Same cards"),
         }
     }
 }
 
-fn parse(input: &str) -> Vec<(Hand, usize)> {
-    input
-        .lines()
-        .map(|line| {
-            let parts: Vec<&str> = line.split(" ").collect();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// How the first hand compares to the second
+    pub ordering: Ordering,
+    pub reason: Reason,
+}
+
+fn parse(input: &str, rules: &Rules) -> Result<Vec<(Hand, usize)>, ParseError> {
+    parse::lines(input, |line| {
+        let (hand, bid) = line
+            .split_once(" ")
+            .ok_or(ParseErrorKind::Missing("a hand and a bid"))?;
+
+        let hand = rules.parse_hand(hand)?;
+        let value: usize = parse::number(bid)?;
+
+        return Ok((hand, value));
+    })
+}
 
-            let hand = Hand::parse(parts[0]);
+/// A hand of the final table and what it won
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Standing {
+    /// From 1 for the weakest hand
+    pub rank: usize,
+    pub hand: Hand,
+    pub category: String,
+    /// The hand with its wildcards replaced by the cards they stand for
+    pub best: Hand,
+    pub bid: usize,
+    /// The bid times the rank
+    pub winnings: usize,
+}
 
-            let value: usize = parts[1].parse().unwrap();
+/// Every hand of `input` from weakest to strongest
+pub fn standings(input: &str, rules: &Rules) -> Result<Vec<Standing>, String> {
+    let mut table = parse(input, rules)?;
+    table.sort_by_cached_key(|(hand, _)| rules.sort_key(hand));
 
-            return (hand, value);
+    let result = table
+        .into_iter()
+        .enumerate()
+        .map(|(i, (hand, bid))| {
+            let evaluation = rules.evaluate(&hand);
+            Standing {
+                rank: i + 1,
+                hand,
+                category: evaluation.name,
+                best: evaluation.best,
+                bid,
+                winnings: bid * (i + 1),
+            }
         })
-        .collect()
+        .collect();
+
+    return Ok(result);
 }
 
-pub fn part1(input: &str) -> usize {
-    let mut table = parse(&input);
-    table.sort_by(|(a, _), (b, _)| a.cmp(b));
+/// Every bid times the rank of its hand
+pub fn winnings(input: &str, rules: &Rules) -> Result<usize, String> {
+    let result: usize = standings(input, rules)?.iter().map(|s| s.winnings).sum();
+
+    return Ok(result);
+}
 
-    let result: usize = table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn parse(input: &str) -> Result<Format, String> {
+        match input {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            f => Err(format!(
+                "Unknown standings format `{f}`, expected csv or json"
+            )),
+        }
+    }
+}
+
+/// Writes the standings as CSV with a header row, or as a JSON array of objects
+pub fn export(standings: &[Standing], format: Format, out: &mut impl Write) -> std::io::Result<()> {
+    // Cards are any char, so hands and category names may need quoting
+    let csv = |field: String| match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field,
+    };
+    let json = |field: String| {
+        let escaped = field
+            .chars()
+            .map(|c| match c {
+                '"' => "\\\"".to_string(),
+                '\\' => "\\\\".to_string(),
+                c if c.is_control() => format!("\\u{:04x}", c as u32),
+                c => c.to_string(),
+            })
+            .collect::<String>();
+        format!("\"{escaped}\"")
+    };
+
+    match format {
+        Format::Csv => {
+            writeln!(out, "rank,hand,category,best,bid,winnings")?;
+            for s in standings {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    s.rank,
+                    csv(s.hand.to_string()),
+                    csv(s.category.clone()),
+                    csv(s.best.to_string()),
+                    s.bid,
+                    s.winnings
+                )?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (i, s) in standings.iter().enumerate() {
+                let separator = if i + 1 < standings.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    r#"  {{"rank":{},"hand":{},"category":{},"best":{},"bid":{},"winnings":{}}}{separator}"#,
+                    s.rank,
+                    json(s.hand.to_string()),
+                    json(s.category.clone()),
+                    json(s.best.to_string()),
+                    s.bid,
+                    s.winnings
+                )?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// The winnings of every hand under `rules`, reporting every hand evaluated
+fn ranked_winnings(
+    input: &str,
+    rules: &Rules,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    let table = parse(input, rules).unwrap();
+
+    progress.start("Hands", Some(table.len() as u64));
+    let mut ranked = Vec::with_capacity(table.len());
+    for (i, (hand, bid)) in table.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} hands evaluated", table.len()))?;
+        ranked.push((rules.sort_key(hand), *bid));
+        progress.advance(1);
+    }
+    progress.finish();
+    ranked.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    return Ok(ranked
         .iter()
         .enumerate()
-        .map(|(i, (_h, b))| b * (i + 1))
-        .sum();
+        .map(|(i, (_, bid))| bid * (i + 1))
+        .sum());
+}
+
+pub fn part1(input: &str) -> usize {
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    return result;
+/// Reports every hand evaluated, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    return ranked_winnings(input, &Rules::camel_cards(), progress, cancel);
 }
 
 pub fn part2(input: &str) -> usize {
-    let mut table = parse(input);
-    table.sort_by(|(a, _), (b, _)| a.cmp2(b));
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let result: usize = table
-        .iter()
-        .enumerate()
-        .map(|(i, (_h, b))| b * (i + 1))
-        .sum();
+/// Reports every hand evaluated with its jokers, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<usize, TimedOut> {
+    return ranked_winnings(input, &Rules::camel_cards_with_jokers(), progress, cancel);
+}
+
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    let rules = Rules::camel_cards_with_jokers();
+    let _ = parse(input, &rules);
+
+    if let Ok(hand) = rules.parse_hand(input) {
+        let printed = hand.to_string();
+        assert_eq!(rules.parse_hand(&printed), Ok(hand.clone()));
 
-    return result;
+        let best = rules.evaluate(&hand).best;
+        assert_eq!(
+            Rules::camel_cards().evaluate(&best).category,
+            rules.evaluate(&hand).category
+        );
+    }
 }
 
 pub fn process(input: String) {
@@ -215,16 +515,95 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    fn category(rules: &Rules, hand: &str) -> String {
+        return rules.evaluate(&rules.parse_hand(hand).unwrap()).name;
+    }
 
     #[test]
     fn hand_kind() {
-        assert_eq!(Hand::parse("AAAAA").kind(), Kind::FiveOfAKind);
-        assert_eq!(Hand::parse("AATAA").kind(), Kind::FourOfAKind);
-        assert_eq!(Hand::parse("33322").kind(), Kind::FullHouse);
-        assert_eq!(Hand::parse("33321").kind(), Kind::ThreeOfAKind);
-        assert_eq!(Hand::parse("6565Q").kind(), Kind::TwoPairs);
-        assert_eq!(Hand::parse("333AQ").kind(), Kind::ThreeOfAKind);
-        assert_eq!(Hand::parse("23456").kind(), Kind::HighCard);
+        let rules = Rules::camel_cards();
+        assert_eq!(category(&rules, "AAAAA"), "Five of a kind");
+        assert_eq!(category(&rules, "AATAA"), "Four of a kind");
+        assert_eq!(category(&rules, "33322"), "Full house");
+        assert_eq!(category(&rules, "33324"), "Three of a kind");
+        assert_eq!(category(&rules, "6565Q"), "Two pairs");
+        assert_eq!(category(&rules, "333AQ"), "Three of a kind");
+        assert_eq!(category(&rules, "23456"), "High card");
+    }
+
+    #[test]
+    fn wildcards_stand_in_for_the_best_cards() {
+        let rules = Rules::camel_cards_with_jokers();
+        let best = |hand: &str| {
+            rules
+                .evaluate(&rules.parse_hand(hand).unwrap())
+                .best
+                .to_string()
+        };
+
+        assert_eq!(category(&rules, "KTJJT"), "Four of a kind");
+        assert_eq!(best("KTJJT"), "KTTTT");
+        assert_eq!(best("QJJQ2"), "QQQQ2");
+        assert_eq!(best("2J3J4"), "24344");
+        assert_eq!(best("JJJJJ"), "AAAAA");
+        assert_eq!(best("23J45"), "23545");
+        assert_eq!(category(&rules, "2233J"), "Full house");
+        assert_eq!(best("2233J"), "22333");
+    }
+
+    #[test]
+    fn rules_are_configurable() {
+        // Three cards, aces low, sevens wild, with a run of pairs beating a triple
+        let categories = vec![
+            Category::new("Pair", &[2]),
+            Category::new("Triple", &[3]),
+            Category::new("Nothing", &[]),
+        ];
+        let rules = Rules::new("A234567", "7", 3, categories).unwrap();
+        assert_eq!(category(&rules, "333"), "Pair");
+        assert_eq!(category(&rules, "7A2"), "Pair");
+        assert_eq!(category(&rules, "A23"), "Nothing");
+        assert!(matches!(
+            rules.parse_hand("A234"),
+            Err(ParseErrorKind::Invalid(_))
+        ));
+        assert_eq!(
+            rules.parse_hand("A2K"),
+            Err(ParseErrorKind::UnexpectedChar('K'))
+        );
+
+        assert!(Rules::new("AA2", "", 3, Category::camel_cards()).is_err());
+        assert!(Rules::new("A23", "K", 3, vec![Category::new("Nothing", &[])]).is_err());
+        assert!(Rules::new("A23", "", 3, vec![Category::new("Pair", &[2])]).is_err());
+        assert!(Rules::new("A23", "", 3, Category::camel_cards()).is_err());
+    }
+
+    #[test]
+    fn comparisons_explain_themselves() {
+        let rules = Rules::camel_cards_with_jokers();
+        let compare = |a: &str, b: &str| {
+            let comparison =
+                rules.compare(&rules.parse_hand(a).unwrap(), &rules.parse_hand(b).unwrap());
+            (comparison.ordering, comparison.reason.to_string())
+        };
+
+        assert_eq!(
+            compare("KTJJT", "KK677"),
+            (
+                Ordering::Greater,
+                "Four of a kind beats Two pairs".to_string()
+            )
+        );
+        assert_eq!(
+            compare("JKKK2", "QQQQ2"),
+            (Ordering::Less, "Q beats J as card 1".to_string())
+        );
+        assert_eq!(
+            compare("T55J5", "T55J5"),
+            (Ordering::Equal, "Same cards".to_string())
+        );
     }
 
     #[test]
@@ -262,4 +641,115 @@ QQQJA 483";
         let result = part2(input);
         assert_eq!(result, 248029057);
     }
+
+    #[test]
+    fn parse_rejects_unknown_cards() {
+        use ParseErrorKind::*;
+
+        let rules = Rules::camel_cards();
+        assert_eq!(rules.parse_hand("3332X"), Err(UnexpectedChar('X')));
+        assert_eq!(rules.parse_hand("33321"), Err(UnexpectedChar('1')));
+        assert!(matches!(rules.parse_hand("3332"), Err(Invalid(_))));
+        assert!(matches!(rules.parse_hand("333222"), Err(Invalid(_))));
+        assert_eq!(
+            parse("32T3K", &rules),
+            Err(Missing("a hand and a bid").at(1))
+        );
+        assert_eq!(
+            parse("32T3K 765\n32T3K -5", &rules),
+            Err(InvalidNumber("-5".to_string()).at(2))
+        );
+    }
+
+    #[test]
+    fn standings_list_every_hand_weakest_first() {
+        let input = "32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483";
+        let table = standings(input, &Rules::camel_cards_with_jokers()).unwrap();
+
+        let hands = table.iter().map(|s| s.hand.to_string()).collect::<Vec<_>>();
+        assert_eq!(hands, ["32T3K", "KK677", "T55J5", "QQQJA", "KTJJT"]);
+        assert_eq!(table[4].category, "Four of a kind");
+        assert_eq!(table[4].best.to_string(), "KTTTT");
+        assert_eq!(table[4].winnings, 5 * 220);
+        assert_eq!(table.iter().map(|s| s.winnings).sum::<usize>(), 5905);
+    }
+
+    #[test]
+    fn standings_export_as_csv_and_json() {
+        let table = standings("T55J5 684\n32T3K 765", &Rules::camel_cards_with_jokers()).unwrap();
+
+        let mut csv = Vec::new();
+        export(&table, Format::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "rank,hand,category,best,bid,winnings
+1,32T3K,One pair,32T3K,765,765
+2,T55J5,Four of a kind,T5555,684,1368
+"
+        );
+
+        let mut json = Vec::new();
+        export(&table, Format::Json, &mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"[
+  {"rank":1,"hand":"32T3K","category":"One pair","best":"32T3K","bid":765,"winnings":765},
+  {"rank":2,"hand":"T55J5","category":"Four of a kind","best":"T5555","bid":684,"winnings":1368}
+]
+"#
+        );
+
+        assert_eq!(Format::parse("json"), Ok(Format::Json));
+        assert!(Format::parse("xml").is_err());
+    }
+
+    #[test]
+    fn exports_quote_unusual_cards() {
+        let categories = vec![
+            Category::new("Pair, \"loose\"", &[2]),
+            Category::new("None", &[]),
+        ];
+        let rules = Rules::new("\",a", "", 2, categories).unwrap();
+        let table = standings("\"\" 1", &rules).unwrap();
+
+        let mut csv = Vec::new();
+        export(&table, Format::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.ends_with("1,\"\"\"\"\"\",\"Pair, \"\"loose\"\"\",\"\"\"\"\"\",1,1\n"));
+
+        let mut json = Vec::new();
+        export(&table, Format::Json, &mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#""hand":"\"\"","category":"Pair, \"loose\"""#));
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics_on_near_hands(input in "([2-9AKQJTX1]{4,6} -?[0-9]{1,4}\n){0,5}") {
+            let _ = parse(&input, &Rules::camel_cards());
+        }
+
+        #[test]
+        fn hand_round_trips(hand in "[2-9AKQJT]{5}") {
+            prop_assert_eq!(Rules::camel_cards().parse_hand(&hand).unwrap().to_string(), hand);
+        }
+
+        #[test]
+        fn wildcards_match_trying_every_card(hand in "[2-9AKQJT]{5}") {
+            let jokers = Rules::camel_cards_with_jokers();
+            let plain = Rules::camel_cards();
+            let category = jokers.evaluate(&jokers.parse_hand(&hand).unwrap()).category;
+
+            // Jokers all turning into the same card is always best for Camel Cards
+            let strongest = "23456789TQKA"
+                .chars()
+                .map(|c| plain.evaluate(&plain.parse_hand(&hand.replace('J', &c.to_string())).unwrap()).category)
+                .min();
+            prop_assert_eq!(Some(category), strongest);
+        }
+    }
 }
@@ -1,37 +1,100 @@
-use std::collections::BTreeMap;
+use itertools::Itertools;
 use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use tracing::{debug, instrument};
 
-fn parse(input: &str) -> (&str, BTreeMap<&str, (&str, &str)>) {
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{ParseError, ParseErrorKind};
+use crate::progress::Progress;
+
+#[derive(Debug, PartialEq)]
+struct Network<'a> {
+    instructions: &'a str,
+    nodes: BTreeMap<&'a str, (&'a str, &'a str)>,
+}
+
+impl std::fmt::Display for Network<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|(node, (l, r))| format!("{node} = ({l}, {r})"))
+            .join("\n");
+        write!(f, "{}\n\n{nodes}", self.instructions)
+    }
+}
+
+fn parse(input: &str) -> Result<Network<'_>, ParseError> {
     let lines: Vec<&str> = input.lines().collect();
-    let instructions = lines.first().unwrap();
+    let instructions = *lines
+        .first()
+        .ok_or(ParseErrorKind::Missing("the instructions").at(1))?;
+
+    if instructions.is_empty() {
+        return Err(ParseErrorKind::Missing("the instructions").at(1));
+    }
+    if let Some(c) = instructions.chars().find(|c| *c != 'L' && *c != 'R') {
+        return Err(ParseErrorKind::UnexpectedChar(c).at(1));
+    }
+    if lines.get(1).is_some_and(|line| !line.is_empty()) {
+        return Err(ParseErrorKind::Missing("an empty line after the instructions").at(2));
+    }
 
-    let re = Regex::new(r"([\w]+) = \(([\w]+), ([\w]+)\)").unwrap();
+    let re = Regex::new(r"^([\w]+) = \(([\w]+), ([\w]+)\)$").unwrap();
 
-    let map: BTreeMap<&str, (&str, &str)> = lines
-        .iter()
-        .skip(2)
-        .map(|line| {
-            let matches = re.captures(line).unwrap();
-            return (
-                matches.get(1).unwrap().as_str(),
-                (
-                    matches.get(2).unwrap().as_str(),
-                    matches.get(3).unwrap().as_str(),
-                ),
-            );
-        })
-        .collect();
+    let mut map: BTreeMap<&str, (&str, &str)> = BTreeMap::new();
+    for (i, line) in lines.iter().enumerate().skip(2) {
+        let matches = re
+            .captures(line)
+            .ok_or(ParseErrorKind::Missing("`<node> = (<left>, <right>)`").at(i + 1))?;
+        let node = matches.get(1).unwrap().as_str();
+        let edges = (
+            matches.get(2).unwrap().as_str(),
+            matches.get(3).unwrap().as_str(),
+        );
+
+        if map.insert(node, edges).is_some() {
+            return Err(ParseErrorKind::Duplicate(node.to_string()).at(i + 1));
+        }
+    }
+
+    // Only whole lines got this far, so the undefined node is blamed on the first line naming it
+    for (i, line) in lines.iter().enumerate().skip(2) {
+        let (l, r) = map[&line[..line.find(' ').unwrap()]];
+        if let Some(missing) = [l, r].into_iter().find(|n| !map.contains_key(n)) {
+            return Err(ParseErrorKind::Unknown(missing.to_string()).at(i + 1));
+        }
+    }
 
-    return (instructions, map);
+    return Ok(Network {
+        instructions,
+        nodes: map,
+    });
 }
 
 pub fn part1(input: &str) -> u64 {
-    let (directions, nodes) = parse(input);
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every step from `AAA`, or stops when `cancel` does
+#[instrument(skip_all)]
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, TimedOut> {
+    let Network {
+        instructions: directions,
+        nodes,
+    } = parse(input).unwrap();
 
     let mut i = 0;
     let mut current_node = "AAA";
 
+    progress.start("Steps", None);
     for direction in directions.chars().cycle() {
+        cancel.check(|| format!("{i} steps without reaching ZZZ"))?;
         let (l, r) = nodes.get(current_node).unwrap();
 
         match direction {
@@ -41,64 +104,246 @@ pub fn part1(input: &str) -> u64 {
         }
 
         i += 1;
+        progress.advance(1);
 
         if current_node == "ZZZ" {
             break;
         }
     }
+    progress.finish();
 
-    return i;
+    return Ok(i);
 }
 
-pub fn part2(input: &str) -> u64{
-    let (directions, nodes) = parse(input);
-
-    let mut cycles: Vec<u64> = vec![];
+/// Where a ghost walking from one node stands on an end node. The walk is a path through the
+/// states (node, instruction index) that ends up looping, so it hits end nodes at a few steps
+/// before the loop and then at a few offsets into every lap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Orbit {
+    /// Steps before the first state of the loop
+    pub prefix: u64,
+    /// Steps in every lap of the loop
+    pub cycle: u64,
+    /// Steps before `prefix` ending on an end node, from step 1
+    pub prefix_hits: Vec<u64>,
+    /// Offsets from `prefix` into the loop ending on an end node
+    pub cycle_hits: Vec<u64>,
+}
 
-    for starting_node in nodes.keys().filter(|n| n.ends_with('A')) {
-        let mut i: u64 = 0;
-        let mut current_node = starting_node;
-        let mut trends: Vec<(u64, u64)> = vec![];
+impl Orbit {
+    /// Whether the ghost stands on an end node after `step` steps
+    pub fn hits(&self, step: u64) -> bool {
+        match step < self.prefix {
+            true => self.prefix_hits.contains(&step),
+            false => self
+                .cycle_hits
+                .contains(&((step - self.prefix) % self.cycle)),
+        }
+    }
+}
 
-        for (direction_pos, direction) in directions.chars().enumerate().cycle() {
-            let (l, r) = nodes.get(current_node).unwrap();
+impl Network<'_> {
+    /// Walks from `start` until a state repeats, noting the steps ending on an end node
+    fn orbit(&self, start: &str, is_end: impl Fn(&str) -> bool) -> Orbit {
+        let directions = self.instructions.as_bytes();
+        let mut seen: HashMap<(&str, usize), u64> = HashMap::new();
+        let mut hits = vec![];
+        let mut node = start;
 
-            current_node = match direction {
-                'L' => l,
-                'R' => r,
+        for step in 1.. {
+            let (l, r) = self.nodes[node];
+            node = match directions[(step - 1) as usize % directions.len()] {
+                b'L' => l,
                 _ => r,
             };
 
-            i += 1;
-
-            if current_node.ends_with('Z') {
-                let (_, last) = trends.last().unwrap_or(&(0, 0));
-                let delta = i - last;
-                let new_trend = (direction_pos as u64, delta);
-                if trends.contains(&new_trend) {
-                    trends.push(new_trend);
-                    cycles.push(delta);
-                    // println!("[{starting_node}]: {trends:?}");
-                    break;
-                } else {
-                    trends.push(new_trend);
-                }
+            let state = (node, step as usize % directions.len());
+            if let Some(&first) = seen.get(&state) {
+                let (prefix_hits, cycle_hits) = hits.into_iter().partition(|hit| *hit < first);
+                return Orbit {
+                    prefix: first,
+                    cycle: step - first,
+                    prefix_hits,
+                    cycle_hits: cycle_hits.into_iter().map(|hit| hit - first).collect(),
+                };
             }
+            seen.insert(state, step);
+
+            if is_end(node) {
+                hits.push(step);
+            }
+        }
+
+        unreachable!("The walk repeats within as many steps as there are states");
+    }
+}
+
+/// The walk of the ghost from every node ending in `A`
+pub fn orbits(input: &str) -> Result<Vec<(String, Orbit)>, ParseError> {
+    let network = parse(input)?;
+    let is_end = |node: &str| node.ends_with('Z');
+
+    let result = network
+        .nodes
+        .keys()
+        .filter(|n| n.ends_with('A'))
+        .map(|start| (start.to_string(), network.orbit(start, is_end)))
+        .collect();
+
+    return Ok(result);
+}
+
+/// Solves `x = a1 (mod m1)` and `x = a2 (mod m2)` for moduli with common factors, as
+/// `x = a (mod lcm(m1, m2))`, none when the two disagree
+fn crt((a1, m1): (i128, i128), (a2, m2): (i128, i128)) -> Result<Option<(i128, i128)>, String> {
+    // Bezout coefficients of the gcd, m1 * x + m2 * y = g
+    fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+        match b {
+            0 => (a, 1, 0),
+            _ => {
+                let (g, x, y) = extended_gcd(b, a % b);
+                (g, y, x - a / b * y)
+            }
+        }
+    }
+
+    let (g, x, _) = extended_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return Ok(None);
+    }
+
+    let overflow = || format!("The ghost cycles of {m1} and {m2} steps overflow i128");
+    let lcm = (m1 / g).checked_mul(m2).ok_or_else(overflow)?;
+    let k = ((a2 - a1) / g)
+        .checked_mul(x)
+        .ok_or_else(overflow)?
+        .rem_euclid(m2 / g);
+    let a = m1
+        .checked_mul(k)
+        .and_then(|step| step.checked_add(a1))
+        .ok_or_else(overflow)?
+        .rem_euclid(lcm);
+
+    return Ok(Some((a, lcm)));
+}
+
+/// The first step after which every ghost stands on an end node at once
+pub fn synchronise(orbits: &[Orbit]) -> Result<u64, String> {
+    let Some(latest) = orbits.iter().max_by_key(|o| o.prefix) else {
+        return Err("There are no ghosts".to_string());
+    };
+
+    // Before the last ghost starts looping, only its hits on the way can be the answer
+    if let Some(step) = latest
+        .prefix_hits
+        .iter()
+        .filter(|step| orbits.iter().all(|o| o.hits(**step)))
+        .min()
+    {
+        return Ok(*step);
+    }
+
+    // Afterwards every ghost is in its loop, so the steps hitting all of them are the
+    // remainders agreeing with one hit of each
+    let mut remainders = vec![(0, 1)];
+    for orbit in orbits {
+        let cycle = i128::from(orbit.cycle);
+        let combined: Vec<Option<(i128, i128)>> = remainders
+            .iter()
+            .cartesian_product(&orbit.cycle_hits)
+            .map(|(r, hit)| crt(*r, (i128::from(orbit.prefix + hit) % cycle, cycle)))
+            .try_collect()?;
+        remainders = combined.into_iter().flatten().unique().collect();
+        debug!(remainders = remainders.len(), "Combined a ghost");
+    }
+
+    let start = i128::from(latest.prefix.max(1));
+    let first = remainders
+        .iter()
+        .map(|(a, m)| a + ((start - a).max(0) + m - 1) / m * m)
+        .min()
+        .ok_or("The ghosts never stand on end nodes together")?;
+
+    return u64::try_from(first)
+        .map_err(|_| format!("The ghosts only synchronise after {first} steps"));
+}
+
+/// Why the ghosts have no step they all end on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Invalid(ParseError),
+    /// The ghosts never stand on end nodes together, or only after more steps than fit a `u64`
+    NoSync(String),
+    TimedOut(TimedOut),
+}
+
+impl From<TimedOut> for Error {
+    fn from(timed_out: TimedOut) -> Error {
+        Error::TimedOut(timed_out)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Invalid(error) => write!(f, "{error}"),
+            Error::NoSync(reason) => write!(f, "{reason}"),
+            Error::TimedOut(timed_out) => write!(f, "{timed_out}"),
         }
     }
-    let lcm = reikna::factor::lcm_all(&cycles);
+}
+
+pub fn part2(input: &str) -> Result<u64, Error> {
+    part2_with_progress(input, &mut (), &Token::new())
+}
+
+/// Reports every ghost whose orbit was found, or stops between ghosts when `cancel` does
+#[instrument(skip_all)]
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<u64, Error> {
+    let network = parse(input).map_err(Error::Invalid)?;
+    let starts = network
+        .nodes
+        .keys()
+        .filter(|n| n.ends_with('A'))
+        .collect_vec();
+
+    progress.start("Ghosts", Some(starts.len() as u64));
+    let mut orbits = vec![];
+    for (i, start) in starts.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} ghost orbits found", starts.len()))?;
+        let orbit = network.orbit(start, |node| node.ends_with('Z'));
+        debug!(start, ?orbit, "Found a cycle");
+        orbits.push(orbit);
+        progress.advance(1);
+    }
+    progress.finish();
+
+    return synchronise(&orbits).map_err(Error::NoSync);
+}
 
-    return lcm;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(network) = parse(input) {
+        let printed = network.to_string();
+        assert_eq!(parse(&printed), Ok(network));
+    }
 }
 
 pub fn process(input: String) {
-    let result = part2(&input);
-    println!("Result: {result}");
+    match part2(&input) {
+        Ok(result) => println!("Result: {result}"),
+        Err(e) => println!("{e}"),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
 
@@ -147,13 +392,177 @@ ZZZ = (ZZZ, ZZZ)";
 22Z = (22B, 22B)
 XXX = (XXX, XXX)";
         let result = part2(input);
-        assert_eq!(result, 6);
+        assert_eq!(result, Ok(6));
     }
 
     #[test]
     fn part2_input() {
         let input = include_str!("input.txt");
         let result = part2(input);
-        assert_eq!(result, 9064949303801);
+        assert_eq!(result, Ok(9064949303801));
+    }
+
+    #[test]
+    fn part2_reports_ghosts_that_never_meet() {
+        // The first ghost ends on odd steps, the second on even ones
+        let input = "L
+
+11A = (11Z, XXX)
+11Z = (11A, XXX)
+22A = (22B, XXX)
+22B = (22Z, XXX)
+22Z = (22B, XXX)
+XXX = (XXX, XXX)";
+        let result = part2(input);
+        assert_eq!(
+            result,
+            Err(Error::NoSync(
+                "The ghosts never stand on end nodes together".to_string()
+            ))
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn orbits_find_hits_before_and_inside_the_loop() {
+        let input = "L
+
+11A = (11B, 11B)
+11B = (11Z, 11Z)
+11Z = (11C, 11C)
+11C = (11D, 11D)
+11D = (11Z, 11Z)";
+        let orbits = orbits(input).unwrap();
+
+        assert_eq!(
+            orbits[0].1,
+            Orbit {
+                prefix: 2,
+                cycle: 3,
+                prefix_hits: vec![],
+                cycle_hits: vec![0],
+            }
+        );
+        assert!(orbits[0].1.hits(2) && orbits[0].1.hits(5) && !orbits[0].1.hits(4));
+    }
+
+    #[test]
+    fn ghosts_synchronise_with_offsets_and_several_hits() {
+        // Hits at 3, 5 and every 7 steps after 2 for the first ghost, so 3 and 5 (mod 7) once
+        // looping, and at 4 then every 6 steps for the second
+        let first = Orbit {
+            prefix: 2,
+            cycle: 7,
+            prefix_hits: vec![],
+            cycle_hits: vec![1, 3],
+        };
+        let second = Orbit {
+            prefix: 4,
+            cycle: 6,
+            prefix_hits: vec![],
+            cycle_hits: vec![0],
+        };
+        let brute_force = (1..).find(|s| first.hits(*s) && second.hits(*s)).unwrap();
+        assert_eq!(synchronise(&[first.clone(), second]), Ok(brute_force));
+
+        // Hits before looping come first
+        let early = Orbit {
+            prefix: 10,
+            cycle: 4,
+            prefix_hits: vec![5],
+            cycle_hits: vec![1],
+        };
+        assert_eq!(synchronise(&[first.clone(), early]), Ok(5));
+
+        // Even steps never meet odd ones
+        let even = Orbit {
+            prefix: 0,
+            cycle: 2,
+            prefix_hits: vec![],
+            cycle_hits: vec![0],
+        };
+        let odd = Orbit {
+            prefix: 1,
+            cycle: 2,
+            prefix_hits: vec![],
+            cycle_hits: vec![0],
+        };
+        assert!(synchronise(&[even, odd]).is_err());
+        assert!(synchronise(&[]).is_err());
+    }
+
+    #[test]
+    fn synchronise_reports_overflowing_cycles() {
+        let orbit = |cycle| Orbit {
+            prefix: 0,
+            cycle,
+            prefix_hits: vec![],
+            cycle_hits: vec![1],
+        };
+
+        let result = synchronise(&[orbit(u64::MAX), orbit(u64::MAX - 1)]);
+        assert!(result.unwrap_err().ends_with("overflow i128"));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_networks() {
+        use ParseErrorKind::*;
+
+        assert_eq!(parse(""), Err(Missing("the instructions").at(1)));
+        assert_eq!(
+            parse("LXR\n\nAAA = (AAA, AAA)"),
+            Err(UnexpectedChar('X').at(1))
+        );
+        assert_eq!(
+            parse("LR\nAAA = (AAA, AAA)"),
+            Err(Missing("an empty line after the instructions").at(2))
+        );
+        assert_eq!(
+            parse("LR\n\nAAA = (AAA, AAA)\nCCC = (BBB, AAA)"),
+            Err(Unknown("BBB".to_string()).at(4))
+        );
+        assert_eq!(
+            parse("LR\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)"),
+            Err(Duplicate("AAA".to_string()).at(4))
+        );
+        assert_eq!(
+            parse("LR\n\nAAA = (AAA, AAA) trailing"),
+            Err(Missing("`<node> = (<left>, <right>)`").at(3))
+        );
+    }
+
+    #[test]
+    fn network_round_trips() {
+        let input = include_str!("input.txt");
+        let network = parse(input).unwrap();
+        assert_eq!(parse(&network.to_string()), Ok(network));
+    }
+
+    proptest! {
+        #[test]
+        fn synchronise_matches_brute_force(
+            orbits in prop::collection::vec((0..6u64, 1..8u64, prop::collection::vec(0..8u64, 0..3)), 1..4)
+        ) {
+            let orbits = orbits
+                .into_iter()
+                .map(|(prefix, cycle, hits)| Orbit {
+                    prefix,
+                    cycle,
+                    prefix_hits: hits.iter().map(|h| h + 1).filter(|h| *h < prefix).collect(),
+                    cycle_hits: hits.iter().map(|h| h % cycle).unique().collect(),
+                })
+                .collect_vec();
+
+            // Past the longest prefix the hits repeat every lcm of the cycles, 840 at most
+            let brute_force = (1..6 + 840).find(|s| orbits.iter().all(|o| o.hits(*s)));
+            prop_assert_eq!(synchronise(&orbits).ok(), brute_force);
+        }
+
+        #[test]
+        fn parse_never_panics_on_near_networks(input in "[LRX]{0,5}\n\n?([A-C]{3} = \\([A-C]{3}, [A-C]{3}\\)\n){0,6}") {
+            if let Ok(network) = parse(&input) {
+                let printed = network.to_string();
+                prop_assert_eq!(parse(&printed), Ok(network));
+            }
+        }
+    }
+}
@@ -1,15 +1,11 @@
 use itertools::Itertools;
 
-fn parse(input: &str) -> Vec<Vec<i64>> {
-    input
-        .lines()
-        .map(|line| {
-            line.split(" ")
-                .map(str::parse)
-                .map(Result::unwrap)
-                .collect()
-        })
-        .collect()
+use crate::cancel::{TimedOut, Token};
+use crate::parse::{self, ParseError};
+use crate::progress::Progress;
+
+fn parse(input: &str) -> Result<Vec<Vec<i64>>, ParseError> {
+    parse::lines(input, |line| line.split(" ").map(parse::number).collect())
 }
 
 fn extrapolate(input: &Vec<i64>) -> i64 {
@@ -22,29 +18,64 @@ fn extrapolate(input: &Vec<i64>) -> i64 {
     }
 }
 
-fn part1(input: &str) -> i64 {
-    let mut series = parse(input);
-
-    let next_numbers: Vec<i64> = series.iter().map(|s| extrapolate(s)).collect();
+pub fn part1(input: &str) -> i64 {
+    part1_with_progress(input, &mut (), &Token::new()).unwrap()
+}
 
-    let result: i64 = next_numbers.iter().sum();
+/// Reports every series extrapolated forwards, or stops when `cancel` does
+pub fn part1_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<i64, TimedOut> {
+    let series = parse(input).unwrap();
+
+    progress.start("Series", Some(series.len() as u64));
+    let mut result: i64 = 0;
+    for (i, serie) in series.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} series extrapolated", series.len()))?;
+        result += extrapolate(serie);
+        progress.advance(1);
+    }
+    progress.finish();
 
-    return result;
+    return Ok(result);
 }
 
 pub fn part2(input: &str) -> i64 {
-    let mut series = parse(input);
+    part2_with_progress(input, &mut (), &Token::new()).unwrap()
+}
+
+/// Reports every series extrapolated backwards, or stops when `cancel` does
+pub fn part2_with_progress(
+    input: &str,
+    progress: &mut dyn Progress,
+    cancel: &Token,
+) -> Result<i64, TimedOut> {
+    let mut series = parse(input).unwrap();
 
     for serie in series.iter_mut() {
         serie.reverse();
     }
 
-    let next_numbers: Vec<i64> = series.iter()
-        .map(|s| extrapolate(s)).collect();
+    progress.start("Series", Some(series.len() as u64));
+    let mut result: i64 = 0;
+    for (i, serie) in series.iter().enumerate() {
+        cancel.check(|| format!("{i} of {} series extrapolated", series.len()))?;
+        result += extrapolate(serie);
+        progress.advance(1);
+    }
+    progress.finish();
 
-    let result: i64 = next_numbers.iter().sum();
+    return Ok(result);
+}
 
-    return result;
+#[cfg(fuzzing)]
+pub fn fuzz(input: &str) {
+    if let Ok(series) = parse(input) {
+        let printed = series.iter().map(|s| s.iter().join(" ")).join("\n");
+        assert_eq!(parse(&printed), Ok(series));
+    }
 }
 
 pub fn process(input: String) {
@@ -55,6 +86,7 @@ pub fn process(input: String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn part1_example() {
@@ -87,4 +119,21 @@ mod tests {
         let result = part2(input);
         assert_eq!(result, 933);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parse_rejects_malformed_values() {
+        use crate::parse::ParseErrorKind::InvalidNumber;
+
+        assert_eq!(parse("1 2  3"), Err(InvalidNumber(String::new()).at(1)));
+        assert_eq!(parse("1\n1 2 x"), Err(InvalidNumber("x".to_string()).at(2)));
+        assert_eq!(parse("1 -2 3"), Ok(vec![vec![1, -2, 3]]));
+    }
+
+    proptest! {
+        #[test]
+        fn parse_reads_every_value(series in prop::collection::vec(prop::collection::vec(any::<i64>(), 1..10), 0..5)) {
+            let input = series.iter().map(|s| s.iter().join(" ")).join("\n");
+            prop_assert_eq!(parse(&input), Ok(series));
+        }
+    }
+}
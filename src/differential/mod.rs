@@ -0,0 +1,434 @@
+use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Debug,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{cancel::Token, day23, day5, day6, generators};
+
+/// One implementation of a computation that has several
+pub struct Variant<I, O> {
+    pub name: &'static str,
+    pub run: fn(&I) -> O,
+    /// Too slow for the real puzzle input, only run on generated inputs
+    pub slow: bool,
+}
+
+/// All variants of a computation for one day, with a way to generate and shrink inputs for it
+pub struct Registry<I, O> {
+    pub day: &'static str,
+    pub variants: Vec<Variant<I, O>>,
+    pub generate: fn(&mut StdRng, usize) -> I,
+    /// Returns smaller versions of an input, used to minimize a disagreement
+    pub shrink: fn(&I) -> Vec<I>,
+}
+
+/// Variants that produced different results for the same input
+#[derive(Debug)]
+pub struct Disagreement<I, O> {
+    pub day: &'static str,
+    pub input: I,
+    /// Each variant's result, or its panic message
+    pub outputs: Vec<(&'static str, Result<O, String>)>,
+}
+
+impl<I: Debug, O: Debug> std::fmt::Display for Disagreement<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} variants disagree on input:", self.day)?;
+        writeln!(f, "{:?}", self.input)?;
+        for (name, output) in &self.outputs {
+            writeln!(f, "  {name}: {output:?}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: Clone + Debug, O: PartialEq + Debug> Registry<I, O> {
+    fn outputs(&self, input: &I, include_slow: bool) -> Vec<(&'static str, Result<O, String>)> {
+        self.variants
+            .iter()
+            .filter(|v| include_slow || !v.slow)
+            .map(|v| {
+                let output =
+                    panic::catch_unwind(AssertUnwindSafe(|| (v.run)(input))).map_err(|e| {
+                        e.downcast_ref::<String>()
+                            .cloned()
+                            .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                            .unwrap_or_default()
+                    });
+                (v.name, output)
+            })
+            .collect()
+    }
+
+    fn agree(&self, input: &I, include_slow: bool) -> bool {
+        self.outputs(input, include_slow)
+            .iter()
+            .map(|(_, output)| output)
+            .all_equal()
+    }
+
+    /// Greedily shrinks the input for as long as the variants keep disagreeing
+    fn minimize(&self, input: I, include_slow: bool) -> I {
+        let mut current = input;
+
+        while let Some(smaller) = (self.shrink)(&current)
+            .into_iter()
+            .find(|candidate| !self.agree(candidate, include_slow))
+        {
+            current = smaller;
+        }
+
+        return current;
+    }
+
+    /// Runs every variant on the input, reporting a minimized disagreement if there is one
+    pub fn check(&self, input: I, include_slow: bool) -> Result<(), Disagreement<I, O>> {
+        if self.agree(&input, include_slow) {
+            return Ok(());
+        }
+
+        let input = self.minimize(input, include_slow);
+        let outputs = self.outputs(&input, include_slow);
+
+        Err(Disagreement {
+            day: self.day,
+            input,
+            outputs,
+        })
+    }
+
+    /// Checks all variants, slow ones included, on `count` generated inputs of the given size
+    pub fn check_random(
+        &self,
+        seed: u64,
+        count: usize,
+        size: usize,
+    ) -> Result<(), Disagreement<I, O>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for _ in 0..count {
+            let input = (self.generate)(&mut rng, size);
+            self.check(input, true)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Part 2 of day 5 computed by composing the maps, by splitting ranges and by brute force
+pub fn day5_part2() -> Registry<String, i64> {
+    Registry {
+        day: "day5",
+        variants: vec![
+            Variant {
+                name: "piecewise",
+                run: |input| day5::part2(input).unwrap(),
+                slow: false,
+            },
+            Variant {
+                name: "range_split",
+                run: |input| day5::part2_range_split(input),
+                slow: false,
+            },
+            Variant {
+                name: "brute_force",
+                run: |input| day5::part2_brute_force(input),
+                slow: true,
+            },
+        ],
+        generate: generators::day5,
+        shrink: |input| {
+            let lines = input.lines().collect_vec();
+            let seeds = lines[0]
+                .trim_start_matches("seeds: ")
+                .split(' ')
+                .collect_vec();
+
+            let without_seeds = (0..seeds.len() / 2).filter(|_| seeds.len() > 2).map(|i| {
+                let mut seeds = seeds.clone();
+                seeds.drain(i * 2..i * 2 + 2);
+                format!("seeds: {}\n{}", seeds.join(" "), lines[1..].join("\n"))
+            });
+            let without_mappings = without_mapping_lines(&lines);
+
+            without_seeds.chain(without_mappings).collect()
+        },
+    }
+}
+
+/// A single map applied to a range of seeds, by each of the range mapping implementations
+pub fn day5_projection() -> Registry<(String, i64, i64), day5::IntervalSet> {
+    fn mapped(ranges: Vec<day5::Range>) -> day5::IntervalSet {
+        day5::IntervalSet::new(ranges.iter().map(day5::Range::span))
+    }
+
+    Registry {
+        day: "day5",
+        variants: vec![
+            Variant {
+                name: "map_range",
+                run: |(map, start, length)| {
+                    let projection = day5::Projection::parse(map).unwrap();
+                    mapped(projection.map_range(&day5::Range::new(*start, *length)))
+                },
+                slow: false,
+            },
+            Variant {
+                name: "map_range_fast",
+                run: |(map, start, length)| {
+                    let projection = day5::Projection::parse(map).unwrap();
+                    mapped(projection.map_range_fast(&day5::Range::new(*start, *length)))
+                },
+                slow: false,
+            },
+            Variant {
+                name: "piecewise",
+                run: |(map, start, length)| {
+                    let projection = day5::Projection::parse(map).unwrap();
+                    let seeds = day5::IntervalSet::from_range(*start..*start + *length);
+                    projection.to_piecewise().unwrap().image(&seeds)
+                },
+                slow: false,
+            },
+        ],
+        generate: |rng, size| {
+            let almanac = generators::day5(rng, size);
+            let map = almanac.split("\n\n").nth(1).unwrap().to_string();
+            let start = rng.gen_range(0..size as i64 * 10);
+            let length = rng.gen_range(1..=size as i64 * 5);
+            (map, start, length)
+        },
+        shrink: |(map, start, length)| {
+            let lines = map.lines().collect_vec();
+            let shorter = [length / 2, length - 1]
+                .into_iter()
+                .filter(|l| *l > 0 && l != length)
+                .map(|l| (map.clone(), *start, l));
+            let without_mappings = without_mapping_lines(&lines)
+                .into_iter()
+                .map(|m| (m, *start, *length));
+
+            shorter.chain(without_mappings).collect()
+        },
+    }
+}
+
+/// Counting winning races by trying every hold time and by solving the quadratic equation
+pub fn day6() -> Registry<(u64, u64), usize> {
+    Registry {
+        day: "day6",
+        variants: vec![
+            Variant {
+                name: "brute_force",
+                run: |(time, record)| day6::winning_races_brute_force(*time, *record, &mut ()),
+                slow: false,
+            },
+            Variant {
+                name: "equation",
+                run: |(time, record)| day6::winning_races_equation(*time, *record),
+                slow: false,
+            },
+        ],
+        generate: |rng, size| {
+            let time = rng.gen_range(1..=size as u64);
+            let best = (time / 2) * (time - time / 2);
+            (time, rng.gen_range(0..best.max(1)))
+        },
+        shrink: |(time, record)| {
+            [
+                (time - 1, *record),
+                (*time, record / 2),
+                (*time, record.saturating_sub(1)),
+            ]
+            .into_iter()
+            .filter(|(t, r)| (t, r) != (time, record) && *r < (t / 2) * (t - t / 2))
+            .collect()
+        },
+    }
+}
+
+/// The longest hike on a map without slopes, on the compressed junction graph, as the tiles
+/// of the drawn hike and on the raw grid
+pub fn day23() -> Registry<String, Option<usize>> {
+    Registry {
+        day: "day23",
+        variants: vec![
+            Variant {
+                name: "graph",
+                run: |input| Some(day23::part2(input)),
+                slow: false,
+            },
+            Variant {
+                name: "drawn",
+                run: |input| day23::ascii(input).matches('O').count().checked_sub(1),
+                slow: false,
+            },
+            Variant {
+                name: "grid",
+                run: |input| {
+                    let map = day23::HikingMap::parse(input).unwrap();
+                    let (start, end) = maze_ends(input);
+                    map.longest_path(start, end, HashSet::from([start]), &Token::new())
+                        .unwrap()
+                },
+                slow: true,
+            },
+        ],
+        generate: generators::day23,
+        shrink: |input| {
+            let (start, end) = maze_ends(input);
+            let lines = input.lines().collect_vec();
+
+            lines
+                .iter()
+                .enumerate()
+                .flat_map(|(y, line)| line.char_indices().map(move |(x, c)| ((x, y), c)))
+                .filter(|(pos, c)| *c == '.' && *pos != start && *pos != end)
+                .map(|((x, y), _)| {
+                    lines
+                        .iter()
+                        .enumerate()
+                        .map(|(ly, line)| match ly == y {
+                            true => format!("{}#{}", &line[..x], &line[x + 1..]),
+                            false => line.to_string(),
+                        })
+                        .join("\n")
+                })
+                .filter(|candidate| maze_connected(candidate))
+                .collect()
+        },
+    }
+}
+
+/// Copies of the almanac text with one of the mapping lines removed
+fn without_mapping_lines(lines: &[&str]) -> Vec<String> {
+    (0..lines.len())
+        .filter(|i| lines[*i].split(' ').count() == 3 && !lines[*i].starts_with("seeds"))
+        .map(|i| {
+            let mut lines = lines.to_vec();
+            lines.remove(i);
+            lines.join("\n")
+        })
+        .collect()
+}
+
+fn maze_ends(input: &str) -> ((usize, usize), (usize, usize)) {
+    let lines = input.lines().collect_vec();
+    let start = (lines[0].find('.').unwrap(), 0);
+    let end = (lines.last().unwrap().len() - 2, lines.len() - 1);
+    (start, end)
+}
+
+fn maze_connected(input: &str) -> bool {
+    let grid = input.lines().map(|l| l.as_bytes()).collect_vec();
+    let (start, end) = maze_ends(input);
+
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some((x, y)) = queue.pop_front() {
+        for (nx, ny) in [
+            (x + 1, y),
+            (x.wrapping_sub(1), y),
+            (x, y + 1),
+            (x, y.wrapping_sub(1)),
+        ] {
+            let open = grid.get(ny).and_then(|row| row.get(nx)) == Some(&b'.');
+            if open && visited.insert((nx, ny)) {
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    return visited.contains(&end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_agree<I: Debug, O: Debug>(result: Result<(), Disagreement<I, O>>) {
+        if let Err(disagreement) = result {
+            panic!("{disagreement}");
+        }
+    }
+
+    #[test]
+    fn day5_part2_random() {
+        assert_agree(day5_part2().check_random(5, 200, 8));
+    }
+
+    #[test]
+    fn day5_part2_input() {
+        let input = include_str!("../day5/input.txt").to_string();
+        assert_agree(day5_part2().check(input, false));
+    }
+
+    #[test]
+    fn day5_projection_random() {
+        assert_agree(day5_projection().check_random(5, 500, 8));
+    }
+
+    #[test]
+    fn day6_random() {
+        assert_agree(day6().check_random(6, 500, 200));
+    }
+
+    #[test]
+    fn day6_input() {
+        let (times, records) = day6::parse(include_str!("../day6/input.txt")).unwrap();
+        for race in times.into_iter().zip(records) {
+            assert_agree(day6().check(race, false));
+        }
+    }
+
+    #[test]
+    fn day23_random() {
+        assert_agree(day23().check_random(23, 50, 4));
+    }
+
+    #[test]
+    fn day23_input() {
+        let input = include_str!("../day23/input.txt").to_string();
+        assert_agree(day23().check(input, false));
+    }
+
+    #[test]
+    fn disagreements_are_minimized() {
+        let registry = Registry {
+            day: "test",
+            variants: vec![
+                Variant {
+                    name: "sum",
+                    run: |input: &Vec<u64>| input.iter().sum::<u64>(),
+                    slow: false,
+                },
+                Variant {
+                    name: "capped_sum",
+                    run: |input: &Vec<u64>| input.iter().map(|i| *i.min(&9)).sum(),
+                    slow: false,
+                },
+            ],
+            generate: |_, _| vec![],
+            shrink: |input| {
+                (0..input.len())
+                    .map(|i| {
+                        let mut smaller = input.clone();
+                        smaller.remove(i);
+                        smaller
+                    })
+                    .collect()
+            },
+        };
+
+        let disagreement = registry.check(vec![1, 20, 3, 40], false).unwrap_err();
+        assert_eq!(disagreement.input, vec![40]);
+        assert_eq!(
+            disagreement.outputs,
+            vec![("sum", Ok(40)), ("capped_sum", Ok(9))]
+        );
+    }
+}
@@ -0,0 +1,1078 @@
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Generates a puzzle input of roughly the given size from a seeded random number generator
+pub type Generator = fn(&mut StdRng, usize) -> String;
+
+/// The input generator for a day of the calendar
+pub fn generator(day: usize) -> Option<Generator> {
+    let generators: [Generator; 25] = [
+        day1, day2, day3, day4, day5, day6, day7, day8, day9, day10, day11, day12, day13, day14,
+        day15, day16, day17, day18, day19, day20, day21, day22, day23, day24, day25,
+    ];
+
+    day.checked_sub(1).and_then(|i| generators.get(i).copied())
+}
+
+const DIGIT_WORDS: [&str; 9] = [
+    "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+
+/// A random lowercase word with a length in the given range
+fn letters(rng: &mut StdRng, length: std::ops::RangeInclusive<usize>) -> String {
+    let length = rng.gen_range(length);
+    (0..length).map(|_| rng.gen_range('a'..='z')).collect()
+}
+
+fn grid(
+    rng: &mut StdRng,
+    width: usize,
+    height: usize,
+    cell: impl Fn(&mut StdRng) -> char,
+) -> String {
+    (0..height)
+        .map(|_| (0..width).map(|_| cell(rng)).collect::<String>())
+        .join("\n")
+}
+
+/// Picks `count` distinct numbers from the range
+fn distinct(rng: &mut StdRng, range: std::ops::Range<u32>, count: usize) -> Vec<u32> {
+    let mut pool = range.collect_vec();
+    pool.shuffle(rng);
+    pool.truncate(count);
+    pool
+}
+
+/// `size` calibration lines of letters, digits and spelled out digits, each with at least one digit
+pub fn day1(rng: &mut StdRng, size: usize) -> String {
+    (0..size)
+        .map(|_| {
+            let mut tokens = (0..rng.gen_range(1..=6))
+                .map(|_| match rng.gen_range(0..3) {
+                    0 => rng.gen_range('1'..='9').to_string(),
+                    1 => DIGIT_WORDS.choose(rng).unwrap().to_string(),
+                    _ => letters(rng, 1..=4),
+                })
+                .collect_vec();
+            let at = rng.gen_range(0..=tokens.len());
+            tokens.insert(at, rng.gen_range('1'..='9').to_string());
+            tokens.join("")
+        })
+        .join("\n")
+}
+
+/// `size` games of up to six rounds drawing red, green and blue cubes
+pub fn day2(rng: &mut StdRng, size: usize) -> String {
+    (1..=size)
+        .map(|id| {
+            let rounds = (0..rng.gen_range(1..=6))
+                .map(|_| {
+                    let mut colors = ["red", "green", "blue"];
+                    colors.shuffle(rng);
+                    colors[..rng.gen_range(1..=3)]
+                        .iter()
+                        .map(|color| format!("{} {color}", rng.gen_range(1..=20)))
+                        .join(", ")
+                })
+                .join("; ");
+            format!("Game {id}: {rounds}")
+        })
+        .join("\n")
+}
+
+/// A `size` by `size` engine schematic of numbers and symbols
+pub fn day3(rng: &mut StdRng, size: usize) -> String {
+    let size = size.max(3);
+    let mut rows = vec![vec!['.'; size]; size];
+
+    for row in rows.iter_mut() {
+        let mut x = rng.gen_range(0..4);
+        while x < size {
+            if rng.gen_bool(0.2) {
+                row[x] = *b"*#+$/@=%&-".choose(rng).unwrap() as char;
+                x += 2;
+            } else {
+                let number = rng.gen_range(1..1000).to_string();
+                for (i, c) in number.chars().enumerate().take(size - x) {
+                    row[x + i] = c;
+                }
+                x += number.len() + 1;
+            }
+            x += rng.gen_range(0..6);
+        }
+    }
+
+    rows.iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
+/// `size` scratchcards with ten winning numbers and twenty five numbers you have
+pub fn day4(rng: &mut StdRng, size: usize) -> String {
+    let width = size.to_string().len();
+
+    (1..=size)
+        .map(|id| {
+            let winning = distinct(rng, 1..100, 10);
+            let mut have = distinct(rng, 1..100, 25);
+            // Make sure some cards win, since two random sets rarely overlap much
+            for (slot, number) in have.iter_mut().zip(&winning).take(rng.gen_range(0..=5)) {
+                *slot = *number;
+            }
+            let have = have.into_iter().unique().collect_vec();
+
+            format!(
+                "Card {id:>width$}: {} | {}",
+                winning.iter().map(|n| format!("{n:>2}")).join(" "),
+                have.iter().map(|n| format!("{n:>2}")).join(" ")
+            )
+        })
+        .join("\n")
+}
+
+/// An almanac with the usual seven maps, with values growing with `size`
+pub fn day5(rng: &mut StdRng, size: usize) -> String {
+    let categories = [
+        "seed",
+        "soil",
+        "fertilizer",
+        "water",
+        "light",
+        "temperature",
+        "humidity",
+        "location",
+    ];
+    let size = size.max(1);
+    let limit = size as i64 * 10;
+
+    let seeds = (0..rng.gen_range(1..=3))
+        .map(|_| format!("{} {}", rng.gen_range(0..limit), rng.gen_range(1..=size)))
+        .join(" ");
+
+    let maps = categories
+        .iter()
+        .tuple_windows()
+        .map(|(source, destination)| {
+            let mut start = 0;
+            let mut mappings = vec![];
+
+            while mappings.len() < rng.gen_range(1..=4) && start < limit {
+                start += rng.gen_range(0..size as i64);
+                let length = rng.gen_range(1..=size as i64);
+                mappings.push(format!("{} {start} {length}", rng.gen_range(0..limit)));
+                start += length;
+            }
+
+            format!("{source}-to-{destination} map:\n{}", mappings.join("\n"))
+        })
+        .join("\n\n");
+
+    format!("seeds: {seeds}\n\n{maps}")
+}
+
+/// `size` races, each with a record that can be beaten
+pub fn day6(rng: &mut StdRng, size: usize) -> String {
+    let races = (0..size.max(1))
+        .map(|_| {
+            let time: u64 = rng.gen_range(2..100);
+            let best = (time / 2) * (time - time / 2);
+            (time, rng.gen_range(0..best))
+        })
+        .collect_vec();
+
+    let width = races
+        .iter()
+        .map(|(_, d)| d.to_string().len())
+        .max()
+        .unwrap();
+
+    format!(
+        "Time:     {}\nDistance: {}",
+        races.iter().map(|(t, _)| format!("{t:>width$}")).join(" "),
+        races.iter().map(|(_, d)| format!("{d:>width$}")).join(" ")
+    )
+}
+
+/// `size` camel cards hands with their bids
+pub fn day7(rng: &mut StdRng, size: usize) -> String {
+    let cards = b"AKQJT98765432";
+
+    (0..size)
+        .map(|_| {
+            let hand: String = (0..5)
+                .map(|_| *cards.choose(rng).unwrap() as char)
+                .collect();
+            format!("{hand} {}", rng.gen_range(1..1000))
+        })
+        .join("\n")
+}
+
+/// A network where every ghost, `AAA` included, loops back to its `Z` node after a whole number
+/// of passes through the `size` instructions
+pub fn day8(rng: &mut StdRng, size: usize) -> String {
+    let size = size.max(2);
+    let instructions: String = (0..size)
+        .map(|_| *[b'L', b'R'].choose(rng).unwrap() as char)
+        .collect();
+
+    let mut prefixes = (1..26 * 26 - 1)
+        .map(|i| {
+            format!(
+                "{}{}",
+                (b'A' + (i / 26) as u8) as char,
+                (b'A' + (i % 26) as u8) as char
+            )
+        })
+        .collect_vec();
+    prefixes.shuffle(rng);
+    let mut ghost_prefixes = prefixes.split_off(prefixes.len() - 3);
+
+    // Nodes along the way end in B to Y, so they never look like a start or an end
+    let mut count = 0;
+    let mut next_name = || {
+        count += 1;
+        format!(
+            "{}{}",
+            prefixes[count / 24 % prefixes.len()],
+            (b'B' + (count % 24) as u8) as char
+        )
+    };
+
+    let mut nodes: Vec<(String, String, String)> = vec![];
+
+    for ghost in 0..rng.gen_range(1..=4) {
+        let (start, end) = match ghost {
+            0 => ("AAA".to_string(), "ZZZ".to_string()),
+            _ => {
+                let prefix = ghost_prefixes.pop().unwrap();
+                (format!("{prefix}A"), format!("{prefix}Z"))
+            }
+        };
+
+        // Each step has two interchangeable nodes, so the path length doesn't depend on the instructions
+        // Like the puzzle input, the loop length is a multiple of the instruction count
+        let steps = (1..rng.gen_range(1..=3) * size)
+            .map(|_| (next_name(), next_name()))
+            .collect_vec();
+
+        let mut targets = |(a, b): &(String, String)| match rng.gen_bool(0.5) {
+            true => (a.clone(), b.clone()),
+            false => (b.clone(), a.clone()),
+        };
+
+        let first = targets(&steps[0]);
+        nodes.push((start, first.0.clone(), first.1.clone()));
+        for (step, next) in steps.iter().tuple_windows() {
+            let (l, r) = targets(next);
+            nodes.push((step.0.clone(), l.clone(), r.clone()));
+            nodes.push((step.1.clone(), l, r));
+        }
+        let last = steps.last().unwrap();
+        nodes.push((last.0.clone(), end.clone(), end.clone()));
+        nodes.push((last.1.clone(), end.clone(), end.clone()));
+        nodes.push((end, first.0, first.1));
+    }
+
+    nodes.shuffle(rng);
+
+    format!(
+        "{instructions}\n\n{}",
+        nodes
+            .iter()
+            .map(|(node, l, r)| format!("{node} = ({l}, {r})"))
+            .join("\n")
+    )
+}
+
+/// `size` sequences of 21 values generated by polynomials of degree up to five
+pub fn day9(rng: &mut StdRng, size: usize) -> String {
+    (0..size)
+        .map(|_| {
+            let coefficients = (0..=rng.gen_range(0..=5))
+                .map(|_| rng.gen_range(-5i64..=5))
+                .collect_vec();
+
+            (0..21i64)
+                .map(|x| coefficients.iter().rev().fold(0, |acc, c| acc * x + c))
+                .join(" ")
+        })
+        .join("\n")
+}
+
+/// A unit edge between two corner points
+type Edge = ((i64, i64), (i64, i64));
+
+/// Random unit edges along the boundary of a vertically convex polygon made of `size` columns of cells
+fn polygon_boundary(rng: &mut StdRng, size: usize) -> Vec<Edge> {
+    let columns = size.max(1) as i64;
+    let height = columns.max(2);
+
+    let mut spans: Vec<(i64, i64)> = vec![];
+    for _ in 0..columns {
+        let span = loop {
+            let low = rng.gen_range(0..height);
+            let high = rng.gen_range(low + 1..=height);
+            // Neighbouring columns share at least one cell, so the outline never pinches
+            match spans.last() {
+                Some((l, h)) if low >= *h || high <= *l => continue,
+                _ => break (low, high),
+            }
+        };
+        spans.push(span);
+    }
+
+    let cells: BTreeSet<(i64, i64)> = spans
+        .iter()
+        .enumerate()
+        .flat_map(|(x, (low, high))| (*low..*high).map(move |y| (x as i64, y)))
+        .collect();
+
+    let cells = &cells;
+    cells
+        .iter()
+        .flat_map(|&(x, y)| {
+            [
+                ((0, -1), ((x, y), (x + 1, y))),
+                ((0, 1), ((x, y + 1), (x + 1, y + 1))),
+                ((-1, 0), ((x, y), (x, y + 1))),
+                ((1, 0), ((x + 1, y), (x + 1, y + 1))),
+            ]
+            .into_iter()
+            .filter(move |((dx, dy), _)| !cells.contains(&(x + dx, y + dy)))
+            .map(|(_, edge)| edge)
+        })
+        .collect()
+}
+
+/// Orders the boundary edges into a closed walk of corner points
+fn polygon_walk(edges: &[Edge]) -> Vec<(i64, i64)> {
+    let mut neighbours: BTreeMap<(i64, i64), Vec<(i64, i64)>> = BTreeMap::new();
+    for (a, b) in edges {
+        neighbours.entry(*a).or_default().push(*b);
+        neighbours.entry(*b).or_default().push(*a);
+    }
+
+    let start = *neighbours.keys().next().unwrap();
+    let mut walk = vec![start];
+    let mut previous = start;
+    let mut current = neighbours[&start][0];
+
+    while current != start {
+        walk.push(current);
+        let next = *neighbours[&current]
+            .iter()
+            .find(|n| **n != previous)
+            .unwrap();
+        previous = current;
+        current = next;
+    }
+
+    walk
+}
+
+/// A pipe loop around a random polygon `size` columns wide, surrounded by junk pipes
+pub fn day10(rng: &mut StdRng, size: usize) -> String {
+    let walk = polygon_walk(&polygon_boundary(rng, size));
+    let width = (walk.iter().map(|(x, _)| x).max().unwrap() * 2 + 3) as usize;
+    let height = (walk.iter().map(|(_, y)| y).max().unwrap() * 2 + 3) as usize;
+
+    let mut tiles = vec![vec!['.'; width]; height];
+    for row in tiles.iter_mut() {
+        for tile in row.iter_mut() {
+            if rng.gen_bool(0.5) {
+                *tile = *b"|-LJ7F".choose(rng).unwrap() as char;
+            }
+        }
+    }
+
+    // Scale the walk by two so every step becomes two tiles, offset by one to leave a border
+    let points = walk
+        .iter()
+        .map(|(x, y)| (*x as usize * 2 + 1, *y as usize * 2 + 1))
+        .collect_vec();
+    let count = points.len();
+
+    for i in 0..count {
+        let (previous, current, next) = (
+            points[(i + count - 1) % count],
+            points[i],
+            points[(i + 1) % count],
+        );
+
+        let towards = |(x, y): (usize, usize)| match (x.cmp(&current.0), y.cmp(&current.1)) {
+            (std::cmp::Ordering::Less, _) => 'W',
+            (std::cmp::Ordering::Greater, _) => 'E',
+            (_, std::cmp::Ordering::Less) => 'N',
+            _ => 'S',
+        };
+
+        let corner = match [towards(previous), towards(next)]
+            .iter()
+            .sorted()
+            .collect::<String>()
+            .as_str()
+        {
+            "NS" => '|',
+            "EW" => '-',
+            "EN" => 'L',
+            "NW" => 'J',
+            "SW" => '7',
+            _ => 'F',
+        };
+        tiles[current.1][current.0] = corner;
+
+        let between = ((current.0 + next.0) / 2, (current.1 + next.1) / 2);
+        tiles[between.1][between.0] = if current.0 == next.0 { '|' } else { '-' };
+    }
+
+    let (sx, sy) = points[rng.gen_range(0..count)];
+    tiles[sy][sx] = 'S';
+
+    // Junk next to the start could look connected to it, so only the loop itself may touch it
+    for (x, y) in [(sx - 1, sy), (sx + 1, sy), (sx, sy - 1), (sx, sy + 1)] {
+        let on_loop = (0..count).any(|i| {
+            let (current, next) = (points[i], points[(i + 1) % count]);
+            (x, y) == current || (x, y) == ((current.0 + next.0) / 2, (current.1 + next.1) / 2)
+        });
+        if !on_loop {
+            tiles[y][x] = '.';
+        }
+    }
+
+    tiles
+        .iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
+/// A `size` by `size` image of galaxies
+pub fn day11(rng: &mut StdRng, size: usize) -> String {
+    grid(
+        rng,
+        size,
+        size,
+        |rng| if rng.gen_bool(0.05) { '#' } else { '.' },
+    )
+}
+
+/// `size` rows of springs whose damage groups match at least one arrangement
+pub fn day12(rng: &mut StdRng, size: usize) -> String {
+    (0..size)
+        .map(|_| {
+            let mut springs: Vec<char> = (0..rng.gen_range(3..=20))
+                .map(|_| if rng.gen_bool(0.4) { '#' } else { '.' })
+                .collect();
+            springs[0] = '#';
+
+            let groups = springs
+                .split(|c| *c == '.')
+                .filter(|g| !g.is_empty())
+                .map(|g| g.len())
+                .join(",");
+            let unknown: String = springs
+                .iter()
+                .map(|c| if rng.gen_bool(0.5) { '?' } else { *c })
+                .collect();
+
+            format!("{unknown} {groups}")
+        })
+        .join("\n")
+}
+
+/// `size` patterns, each with a reflection line that is broken by exactly one smudge
+pub fn day13(rng: &mut StdRng, size: usize) -> String {
+    (0..size)
+        .map(|_| {
+            let width = rng.gen_range(5..=15);
+            let height = rng.gen_range(5..=15);
+            let mut rows = (0..height)
+                .map(|_| (0..width).map(|_| rng.gen_bool(0.5)).collect_vec())
+                .collect_vec();
+
+            if rng.gen_bool(0.5) {
+                let line = rng.gen_range(1..height);
+                for l in 0..line.min(height - line) {
+                    rows[line + l] = rows[line - 1 - l].clone();
+                }
+                let x = rng.gen_range(0..width);
+                let y = rng.gen_range(line - line.min(height - line)..line);
+                rows[y][x] = !rows[y][x];
+            } else {
+                let line = rng.gen_range(1..width);
+                for row in rows.iter_mut() {
+                    for l in 0..line.min(width - line) {
+                        row[line + l] = row[line - 1 - l];
+                    }
+                }
+                let x = rng.gen_range(line - line.min(width - line)..line);
+                let y = rng.gen_range(0..height);
+                rows[y][x] = !rows[y][x];
+            }
+
+            rows.iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|b| if *b { '#' } else { '.' })
+                        .collect::<String>()
+                })
+                .join("\n")
+        })
+        .join("\n\n")
+}
+
+/// A `size` by `size` platform of round and cube shaped rocks
+pub fn day14(rng: &mut StdRng, size: usize) -> String {
+    grid(rng, size, size, |rng| match rng.gen_range(0..100) {
+        0..=19 => 'O',
+        20..=34 => '#',
+        _ => '.',
+    })
+}
+
+/// `size` lens operations over a pool of labels that get reused
+pub fn day15(rng: &mut StdRng, size: usize) -> String {
+    let labels = (0..size / 4 + 1).map(|_| letters(rng, 2..=6)).collect_vec();
+
+    (0..size)
+        .map(|_| {
+            let label = labels.choose(rng).unwrap();
+            match rng.gen_bool(0.3) {
+                true => format!("{label}-"),
+                false => format!("{label}={}", rng.gen_range(1..=9)),
+            }
+        })
+        .join(",")
+}
+
+/// A `size` by `size` contraption of mirrors and splitters
+pub fn day16(rng: &mut StdRng, size: usize) -> String {
+    grid(rng, size, size, |rng| match rng.gen_range(0..100) {
+        0..=2 => '/',
+        3..=5 => '\\',
+        6..=8 => '|',
+        9..=11 => '-',
+        _ => '.',
+    })
+}
+
+/// A `size` by `size` map of heat loss digits
+pub fn day17(rng: &mut StdRng, size: usize) -> String {
+    grid(rng, size.max(2), size.max(2), |rng| {
+        rng.gen_range('1'..='9')
+    })
+}
+
+/// A dig plan tracing a polygon `size` columns wide, with colors encoding a scaled copy of it
+pub fn day18(rng: &mut StdRng, size: usize) -> String {
+    let walk = polygon_walk(&polygon_boundary(rng, size));
+    let scale = rng.gen_range(1..=3);
+    let color_scale = rng.gen_range(1..=1000);
+
+    let mut instructions: Vec<(char, i64)> = vec![];
+    for (a, b) in walk.iter().chain(walk.first()).tuple_windows() {
+        let direction = match (b.0 - a.0, b.1 - a.1) {
+            (1, _) => 'R',
+            (-1, _) => 'L',
+            (_, 1) => 'D',
+            _ => 'U',
+        };
+        match instructions.last_mut() {
+            Some((d, length)) if *d == direction => *length += scale,
+            _ => instructions.push((direction, scale)),
+        }
+    }
+
+    // The walk may start in the middle of a straight edge
+    if instructions.len() > 1 && instructions.first().unwrap().0 == instructions.last().unwrap().0 {
+        let (_, length) = instructions.pop().unwrap();
+        instructions[0].1 += length;
+    }
+
+    instructions
+        .iter()
+        .map(|(direction, length)| {
+            let code = match direction {
+                'R' => 0,
+                'D' => 1,
+                'L' => 2,
+                _ => 3,
+            };
+            // Five hex digits is all the color has room for
+            let distance = (length * color_scale).min(0xfffff);
+            format!("{direction} {length} (#{distance:05x}{code})")
+        })
+        .join("\n")
+}
+
+/// A tree of `size` workflows rating parts on x, m, a and s, followed by `size` parts
+pub fn day19(rng: &mut StdRng, size: usize) -> String {
+    let size = size.max(1);
+    let mut names = vec!["in".to_string()];
+    while names.len() < size {
+        let name = letters(rng, 2..=3);
+        if !names.contains(&name) && name != "in" {
+            names.push(name);
+        }
+    }
+
+    // Workflows only send parts to workflows further down the list, so there are no cycles
+    let mut referenced = vec![false; size];
+    let mut workflows = vec![];
+    for i in 0..size {
+        let mut target = |rng: &mut StdRng| {
+            let unreferenced = (i + 1..size).find(|j| !referenced[*j]);
+            match unreferenced {
+                Some(j) if rng.gen_bool(0.7) => {
+                    referenced[j] = true;
+                    names[j].clone()
+                }
+                _ if i + 1 < size && rng.gen_bool(0.3) => names[rng.gen_range(i + 1..size)].clone(),
+                _ => if rng.gen_bool(0.5) { "A" } else { "R" }.to_string(),
+            }
+        };
+
+        let mut rules = vec![];
+        for _ in 0..rng.gen_range(1..=3) {
+            let attribute = *b"xmas".choose(rng).unwrap() as char;
+            let comparison = if rng.gen_bool(0.5) { '<' } else { '>' };
+            let threshold = rng.gen_range(1..4000);
+            rules.push(format!(
+                "{attribute}{comparison}{threshold}:{}",
+                target(rng)
+            ));
+        }
+        rules.push(target(rng));
+        let rules = rules.join(",");
+
+        workflows.push(format!("{}{{{rules}}}", names[i]));
+    }
+
+    // Any workflow nobody sends parts to gets a rule in `in`, so all of them are reachable
+    let unreachable = (1..size).filter(|j| !referenced[*j]).collect_vec();
+    if !unreachable.is_empty() {
+        let extra = unreachable
+            .iter()
+            .map(|j| format!("x=={}:{}", rng.gen_range(1..4000), names[*j]))
+            .join(",");
+        workflows[0] = workflows[0].replacen('{', &format!("{{{extra},"), 1);
+    }
+
+    workflows.shuffle(rng);
+
+    let parts = (0..size)
+        .map(|_| {
+            let [x, m, a, s]: [u32; 4] = std::array::from_fn(|_| rng.gen_range(1..=4000));
+            format!("{{x={x},m={m},a={a},s={s}}}")
+        })
+        .join("\n");
+
+    format!("{}\n\n{parts}", workflows.join("\n"))
+}
+
+/// Four binary counters of `size` bits that each fire after a random number of button presses,
+/// feeding a conjunction in front of `rx`
+pub fn day20(rng: &mut StdRng, size: usize) -> String {
+    let bits = size.clamp(2, 12);
+    let mut used = HashSet::from(["rx".to_string(), "hub".to_string()]);
+    let mut name = |rng: &mut StdRng| loop {
+        let name = letters(rng, 2..=2);
+        if used.insert(name.clone()) {
+            break name;
+        }
+    };
+
+    let mut lines = vec![];
+    let mut firsts = vec![];
+    let mut inverters = vec![];
+
+    for _ in 0..4 {
+        // The counter resets itself once it reaches `period`, whose lowest and highest bits are set
+        let period = rng.gen_range(1 << (bits - 1)..1 << bits) | 1;
+        let flip_flops = (0..bits).map(|_| name(rng)).collect_vec();
+        let conjunction = name(rng);
+        let inverter = name(rng);
+
+        for (i, flip_flop) in flip_flops.iter().enumerate() {
+            let mut destinations = vec![];
+            if let Some(next) = flip_flops.get(i + 1) {
+                destinations.push(next.clone());
+            }
+            if period & (1 << i) != 0 {
+                destinations.push(conjunction.clone());
+            }
+            lines.push(format!("%{flip_flop} -> {}", destinations.join(", ")));
+        }
+
+        let resets = flip_flops
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i == 0 || period & (1 << i) == 0)
+            .map(|(_, f)| f.clone())
+            .chain([inverter.clone()])
+            .join(", ");
+        lines.push(format!("&{conjunction} -> {resets}"));
+        lines.push(format!("&{inverter} -> hub"));
+
+        firsts.push(flip_flops[0].clone());
+        inverters.push(inverter);
+    }
+
+    lines.push(format!("broadcaster -> {}", firsts.join(", ")));
+    lines.push("&hub -> rx".to_string());
+    lines.shuffle(rng);
+
+    lines.join("\n")
+}
+
+/// A square garden `2 * size + 1` plots wide with the start in the middle, and the middle row,
+/// middle column and border free of rocks
+pub fn day21(rng: &mut StdRng, size: usize) -> String {
+    let width = size.max(1) * 2 + 1;
+    let middle = width / 2;
+
+    (0..width)
+        .map(|y| {
+            (0..width)
+                .map(|x| match (x, y) {
+                    _ if x == middle && y == middle => 'S',
+                    _ if x == middle || y == middle => '.',
+                    _ if x == 0 || y == 0 || x == width - 1 || y == width - 1 => '.',
+                    _ if rng.gen_bool(0.1) => '#',
+                    _ => '.',
+                })
+                .collect::<String>()
+        })
+        .join("\n")
+}
+
+/// `size` bricks floating at distinct heights above a ten by ten area
+pub fn day22(rng: &mut StdRng, size: usize) -> String {
+    let mut z = 1;
+
+    (0..size)
+        .map(|_| {
+            z += rng.gen_range(0..3);
+            let (x, y) = (rng.gen_range(0..10), rng.gen_range(0..10));
+            let length = rng.gen_range(0..4);
+
+            let (end_x, end_y, end_z) = match rng.gen_range(0..3) {
+                0 => ((x + length).min(9), y, z),
+                1 => (x, (y + length).min(9), z),
+                _ => (x, y, z + length),
+            };
+
+            let brick = format!("{x},{y},{z}~{end_x},{end_y},{end_z}");
+            z = end_z + 1;
+            brick
+        })
+        .join("\n")
+}
+
+/// `size` hailstones that a single thrown rock hits at distinct times
+pub fn day24(rng: &mut StdRng, size: usize) -> String {
+    let rock: [i64; 3] =
+        std::array::from_fn(|_| rng.gen_range(200_000_000_000_000..400_000_000_000_000));
+    let rock_velocity: [i64; 3] = std::array::from_fn(|_| rng.gen_range(-300..=300));
+
+    let times = distinct(rng, 1..1_000_000, size);
+
+    times
+        .into_iter()
+        .map(|t| {
+            let t = t as i64 * 1_000_000;
+            let velocity: [i64; 3] = std::array::from_fn(|_| rng.gen_range(-300..=300));
+            let position: [i64; 3] =
+                std::array::from_fn(|i| rock[i] + (rock_velocity[i] - velocity[i]) * t);
+
+            format!(
+                "{}, {}, {} @ {}, {}, {}",
+                position[0], position[1], position[2], velocity[0], velocity[1], velocity[2]
+            )
+        })
+        .join("\n")
+}
+
+/// A hiking trail maze of one-wide corridors `size` cells across, with a few loops
+pub fn day23(rng: &mut StdRng, size: usize) -> String {
+    let cells = size.max(2);
+    let width = cells * 2 + 1;
+    let mut grid = vec![vec!['#'; width]; width];
+
+    let mut stack = vec![(1, 1)];
+    grid[1][1] = '.';
+
+    while let Some(&(x, y)) = stack.last() {
+        let unvisited = [(2, 0), (0, 2), (-2, 0), (0, -2)]
+            .into_iter()
+            .map(|(dx, dy)| ((x as i64 + dx) as usize, (y as i64 + dy) as usize))
+            .filter(|(nx, ny)| *nx < width - 1 && *ny < width - 1 && grid[*ny][*nx] == '#')
+            .collect_vec();
+
+        if unvisited.is_empty() {
+            stack.pop();
+        } else {
+            let (nx, ny) = unvisited[rng.gen_range(0..unvisited.len())];
+            grid[(y + ny) / 2][(x + nx) / 2] = '.';
+            grid[ny][nx] = '.';
+            stack.push((nx, ny));
+        }
+    }
+
+    for _ in 0..cells {
+        let x = rng.gen_range(1..width - 1);
+        let y = rng.gen_range(1..width - 1);
+        if (x + y) % 2 == 1 {
+            grid[y][x] = '.';
+        }
+    }
+
+    grid[0][1] = '.';
+    grid[width - 1][width - 2] = '.';
+
+    grid.iter()
+        .map(|row| row.iter().collect::<String>())
+        .join("\n")
+}
+
+/// Two clusters of `size` components each, joined by exactly three wires
+pub fn day25(rng: &mut StdRng, size: usize) -> String {
+    let size = size.max(4);
+    let mut names = HashSet::new();
+    while names.len() < size * 2 {
+        names.insert(letters(rng, 3..=3));
+    }
+    let mut names = names.into_iter().collect_vec();
+    names.sort();
+    names.shuffle(rng);
+
+    let mut edges: BTreeSet<(usize, usize)> = BTreeSet::new();
+    let mut connect = |a: usize, b: usize| {
+        if a != b {
+            edges.insert((a.min(b), a.max(b)));
+        }
+    };
+
+    for offset in [0, size] {
+        // Linking every component to the next two keeps four wires between any two halves
+        // of a cluster, random extra wires make it less regular
+        for i in 0..size {
+            connect(offset + i, offset + (i + 1) % size);
+            connect(offset + i, offset + (i + 2) % size);
+        }
+        for _ in 0..size {
+            connect(
+                offset + rng.gen_range(0..size),
+                offset + rng.gen_range(0..size),
+            );
+        }
+    }
+
+    let mut left = (0..size).collect_vec();
+    let mut right = (size..size * 2).collect_vec();
+    left.shuffle(rng);
+    right.shuffle(rng);
+    for i in 0..3 {
+        connect(left[i], right[i]);
+    }
+
+    let mut wiring: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (a, b) in edges {
+        let (from, to) = if rng.gen_bool(0.5) { (a, b) } else { (b, a) };
+        wiring.entry(&names[from]).or_default().push(&names[to]);
+    }
+
+    wiring
+        .iter()
+        .map(|(from, to)| format!("{from}: {}", to.join(" ")))
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn generate(day: usize, seed: u64, size: usize) -> String {
+        generator(day).unwrap()(&mut StdRng::seed_from_u64(seed), size)
+    }
+
+    #[test]
+    fn generators_are_seeded() {
+        for day in 1..=25 {
+            assert_eq!(generate(day, 1, 10), generate(day, 1, 10), "day {day}");
+        }
+        assert!(generator(0).is_none() && generator(26).is_none());
+    }
+
+    /// A smoke test: only checks that every day gets through its generated inputs without
+    /// panicking, `generated_inputs_match_known_answers` checks the answers themselves
+    #[test]
+    fn generated_inputs_solve_without_panicking() {
+        use crate::*;
+
+        for seed in 0..10 {
+            day1::part2(&generate(1, seed, 50)).unwrap();
+            day2::part2(&generate(2, seed, 50));
+            day3::part2(&generate(3, seed, 20));
+            day4::part2(&generate(4, seed, 50)).unwrap();
+            day5::part2(&generate(5, seed, 50)).unwrap();
+            day6::part1(&generate(6, seed, 5));
+            day7::part2(&generate(7, seed, 50));
+            day8::part2(&generate(8, seed, 10)).unwrap();
+            day9::part2(&generate(9, seed, 50));
+            day10::part2(&generate(10, seed, 10));
+            day11::part2(&generate(11, seed, 30), 1_000_000);
+            day12::part2(&generate(12, seed, 20));
+            day13::part2(&generate(13, seed, 20));
+            day14::part2(&generate(14, seed, 20));
+            day15::part2(&generate(15, seed, 50));
+            day16::part2(&generate(16, seed, 20));
+            day17::part2(&generate(17, seed, 20));
+            day18::part2(&generate(18, seed, 10));
+            day19::part1(&generate(19, seed, 20));
+            day19::part2(&generate(19, seed, 20));
+            day20::part2(&generate(20, seed, 6));
+            // Both parts of day 21 walk the garden step by step, so a short walk keeps it quick
+            day21::part2(&generate(21, seed, 10), 50);
+            day22::part2(&generate(22, seed, 50));
+            day23::part2(&generate(23, seed, 5));
+            // Part 2 of day 24 hands the hailstones to z3, which its own tests already cover
+            day24::part1(&generate(24, seed, 10), 0, i64::MAX);
+            day25::part1(&generate(25, seed, 20));
+        }
+    }
+
+    /// Checks the days that have a slower reference to compare against. Day 25 isn't one of
+    /// them: it guesses the wires to cut from the busiest components, which can miss on
+    /// graphs as small as the generated ones
+    #[test]
+    fn generated_inputs_match_known_answers() {
+        use crate::*;
+
+        let example = "...#......\n.......#..\n#.........\n..........\n......#...\n\
+                       .#........\n.........#\n..........\n.......#..\n#...#.....";
+        assert_eq!(expanded_distances(example), 374);
+
+        for seed in 0..10 {
+            let input = generate(5, seed, 20);
+            assert_eq!(
+                day5::part2(&input),
+                Ok(day5::part2_range_split(&input)),
+                "day 5, seed {seed}"
+            );
+
+            let input = generate(6, seed, 5);
+            let (times, distances) = day6::parse(&input).unwrap();
+            let brute_force: usize = times
+                .iter()
+                .zip(&distances)
+                .map(|(&time, &distance)| day6::winning_races_brute_force(time, distance, &mut ()))
+                .product();
+            assert_eq!(day6::part1(&input), brute_force, "day 6, seed {seed}");
+
+            let input = generate(11, seed, 30);
+            assert_eq!(
+                day11::part1(&input),
+                expanded_distances(&input),
+                "day 11, seed {seed}"
+            );
+        }
+    }
+
+    /// Day 11 the slow way: doubles every empty row and column of the image, then sums the
+    /// distances between every pair of galaxies
+    fn expanded_distances(input: &str) -> usize {
+        let rows = input
+            .lines()
+            .flat_map(|line| match line.contains('#') {
+                true => vec![line.as_bytes()],
+                false => vec![line.as_bytes(); 2],
+            })
+            .collect_vec();
+        let empty_columns = (0..rows[0].len())
+            .filter(|x| rows.iter().all(|row| row[*x] == b'.'))
+            .collect_vec();
+
+        let galaxies = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                let empty_columns = &empty_columns;
+                row.iter()
+                    .positions(|c| *c == b'#')
+                    .map(move |x| (x + empty_columns.iter().filter(|e| **e < x).count(), y))
+            })
+            .collect_vec();
+
+        galaxies
+            .iter()
+            .tuple_combinations()
+            .map(|((ax, ay), (bx, by))| ax.abs_diff(*bx) + ay.abs_diff(*by))
+            .sum()
+    }
+
+    #[test]
+    fn day18_plan_encodes_a_closed_loop() {
+        for seed in 0..20 {
+            let plan = generate(18, seed, 8);
+            let (mut x, mut y) = (0, 0);
+            for line in plan.lines() {
+                let (direction, length, _) = line.split(' ').collect_tuple().unwrap();
+                let length: i64 = length.parse().unwrap();
+                match direction {
+                    "R" => x += length,
+                    "L" => x -= length,
+                    "D" => y += length,
+                    _ => y -= length,
+                }
+            }
+            assert_eq!((x, y), (0, 0));
+        }
+    }
+
+    /// Reads each counter's period back out of the wiring: bit `i` is set when the `i`th flip-flop
+    /// of the chain feeds the counter's conjunction
+    fn day20_periods(input: &str) -> Vec<u64> {
+        let modules: HashMap<&str, Vec<&str>> = input
+            .lines()
+            .map(|line| {
+                let (name, destinations) = line.split_once(" -> ").unwrap();
+                (name, destinations.split(", ").collect_vec())
+            })
+            .collect();
+        let is_conjunction = |name: &&str| modules.contains_key(format!("&{name}").as_str());
+
+        modules["broadcaster"]
+            .iter()
+            .map(|first| {
+                let mut period = 0;
+                let mut flip_flop = Some(*first);
+                for bit in 0.. {
+                    let Some(name) = flip_flop else { break };
+                    let destinations = &modules[format!("%{name}").as_str()];
+                    if destinations.iter().any(is_conjunction) {
+                        period |= 1 << bit;
+                    }
+                    flip_flop = destinations.iter().find(|d| !is_conjunction(d)).copied();
+                }
+                period
+            })
+            .collect()
+    }
+
+    #[test]
+    fn day20_counters_fire_together() {
+        for seed in 0..5 {
+            let input = generate(20, seed, 4);
+            let periods = day20_periods(&input);
+            assert_eq!(periods.len(), 4);
+            assert_eq!(
+                crate::day20::part2(&input) as u64,
+                reikna::factor::lcm_all(&periods),
+                "seed {seed}"
+            );
+        }
+    }
+}
@@ -1,13 +1,134 @@
 use std::{env, fs, path::Path};
 
+pub mod cancel;
+pub mod day1;
+pub mod day10;
+pub mod day11;
+pub mod day12;
+pub mod day13;
+pub mod day14;
+pub mod day15;
+pub mod day16;
+pub mod day17;
+pub mod day18;
+pub mod day19;
+pub mod day2;
+pub mod day20;
+pub mod day21;
+pub mod day22;
+pub mod day23;
+pub mod day24;
 pub mod day25;
+pub mod day3;
+pub mod day4;
+pub mod day5;
+pub mod day6;
+pub mod day7;
+pub mod day8;
+pub mod day9;
+pub mod differential;
+pub mod generators;
+pub mod parallel;
+pub mod parse;
+pub mod progress;
+pub mod render;
+pub mod runner;
+pub mod trace;
 
 fn main() {
-    let _args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().collect();
+
+    // Tracing stays off unless asked for, the guard writes the span timings when main returns
+    let events = args.iter().any(|a| a == "--trace") || env::var_os(trace::FILTER_ENV).is_some();
+    let flame = runner::flag(&args, "--flame").map(Path::new);
+    let _guard = if events || flame.is_some() {
+        match trace::init(events, flame) {
+            Ok(guard) => Some(guard),
+            Err(e) => {
+                eprintln!("{e}");
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    let budget = match runner::budget(runner::flag(&args, "--timeout")) {
+        Ok(budget) => budget,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    if args.iter().any(|a| a == "--tui") {
+        if let Err(e) = runner::tui::run(Path::new("target/answers.tsv"), budget) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if args.iter().any(|a| a == "--all") {
+        if let Err(e) = runner::solve_all(budget) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(path) = runner::flag(&args, "--calibrate") {
+        match day1::calibrate_file(path, &day1::Scanner::english()) {
+            Ok(calibration) => {
+                for diagnostic in &calibration.diagnostics {
+                    eprintln!("Line {}: {}", diagnostic.line, diagnostic.message);
+                }
+                println!("Calibrated {} lines: {}", calibration.lines, calibration.sum);
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+        return;
+    }
+
+    if let Some(query) = runner::flag(&args, "--query") {
+        let input = fs::read_to_string("src/day2/input.txt").expect("Wrong file location");
+        match day2::query(&input, query) {
+            Ok(ids) => println!("{} games match: {ids:?}", ids.len()),
+            Err(e) => eprintln!("{e}"),
+        }
+        return;
+    }
+
+    if let Some(format) = runner::flag(&args, "--standings") {
+        let input = fs::read_to_string("src/day7/input.txt").expect("Wrong file location");
+        let rules = match runner::flag(&args, "--part") {
+            Some("1") => Ok(day7::Rules::camel_cards()),
+            Some("2") | None => Ok(day7::Rules::camel_cards_with_jokers()),
+            Some(other) => Err(format!("Day 7 has no part {other}, use 1 or 2")),
+        };
+        let exported = rules.and_then(|rules| {
+            let format = day7::Format::parse(format)?;
+            let standings = day7::standings(&input, &rules)?;
+            day7::export(&standings, format, &mut std::io::stdout().lock())
+                .map_err(|e| format!("Couldn't write the standings: {e}"))
+        });
+        if let Err(e) = exported {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    if let Some(day) = runner::flag(&args, "--day") {
+        let part = runner::flag(&args, "--part");
+        let progress = runner::flag(&args, "--progress").unwrap_or("bar");
+        if let Err(e) = runner::solve(day, part, progress, budget) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
     let default_filename = Path::new("src/day25/input.txt");
     // let filename = args.get(1).unwrap_or(&default_filename);
 
     let input = fs::read_to_string(default_filename).expect("Wrong file location");
 
     day25::process(input);
-}
\ No newline at end of file
+}
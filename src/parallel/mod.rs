@@ -0,0 +1,46 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Maps every item on all cores with the `parallel` feature, or one after the other without it.
+/// The results keep the order of the items either way
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    return items.par_iter().map(f).collect();
+
+    #[cfg(not(feature = "parallel"))]
+    return items.iter().map(f).collect();
+}
+
+/// How many threads [`map`] spreads the items over
+pub fn threads() -> usize {
+    #[cfg(feature = "parallel")]
+    return rayon::current_num_threads();
+
+    #[cfg(not(feature = "parallel"))]
+    return 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_keep_their_order() {
+        let items = (0..1000).collect::<Vec<u64>>();
+
+        assert_eq!(map(&items, |i| i * i)[..4], [0, 1, 4, 9]);
+        assert_eq!(map(&items, |i| i * 2).iter().sum::<u64>(), 999_000);
+        assert!(map(&[] as &[u64], |i| *i).is_empty());
+    }
+
+    #[test]
+    fn serial_maps_use_one_thread() {
+        assert!(threads() >= 1);
+        assert!(cfg!(feature = "parallel") || threads() == 1);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{fmt::Display, str::FromStr};
+
+/// Why a puzzle input was rejected, and on which line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Counted from 1, the line after the last one when the input ends too early
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+/// What was wrong with a line, so tests can tell rejections apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A separator, field or section that has to be there, e.g. "` @ `"
+    Missing(&'static str),
+    /// A number that doesn't parse, doesn't fit its type or is out of range
+    InvalidNumber(String),
+    /// A character that has no meaning in the puzzle
+    UnexpectedChar(char),
+    /// A word that has no meaning in the puzzle, e.g. a color or a name never defined
+    Unknown(String),
+    /// Something that may only be given once, given again
+    Duplicate(String),
+    /// A well formed line that still makes no sense, e.g. a row of another width than the others
+    Invalid(String),
+}
+
+impl ParseErrorKind {
+    pub fn at(self, line: usize) -> ParseError {
+        ParseError { line, kind: self }
+    }
+}
+
+impl ParseError {
+    /// The same error in a text that starts `lines` lines further down
+    pub fn offset(self, lines: usize) -> ParseError {
+        ParseError {
+            line: self.line + lines,
+            ..self
+        }
+    }
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::Missing(what) => write!(f, "Missing {what}"),
+            ParseErrorKind::InvalidNumber(number) => write!(f, "Invalid number `{number}`"),
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "Unexpected char `{c}`"),
+            ParseErrorKind::Unknown(word) => write!(f, "Unknown `{word}`"),
+            ParseErrorKind::Duplicate(what) => write!(f, "`{what}` given twice"),
+            ParseErrorKind::Invalid(why) => write!(f, "{why}"),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.kind)
+    }
+}
+
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> String {
+        error.to_string()
+    }
+}
+
+/// Parses `input` as a number, rejecting it whole when it isn't one
+pub fn number<T: FromStr>(input: &str) -> Result<T, ParseErrorKind> {
+    input
+        .parse()
+        .map_err(|_| ParseErrorKind::InvalidNumber(input.to_string()))
+}
+
+/// The line that `part`, a slice of `input`, starts on
+pub fn line_of(input: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - input.as_ptr() as usize;
+    input[..offset].matches('\n').count() + 1
+}
+
+/// Parses every line of `input` with `parse`, errors name the line that failed
+pub fn lines<'a, T>(
+    input: &'a str,
+    parse: impl Fn(&'a str) -> Result<T, ParseErrorKind>,
+) -> Result<Vec<T>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse(line).map_err(|kind| kind.at(i + 1)))
+        .collect()
+}
+
+/// Parses `input` as a non-empty rectangle of the `allowed` chars, `what` names it when missing
+pub fn grid(input: &str, what: &'static str, allowed: &str) -> Result<Vec<Vec<char>>, ParseError> {
+    let grid: Vec<Vec<char>> = input.lines().map(|line| line.chars().collect()).collect();
+
+    if grid.is_empty() || grid[0].is_empty() {
+        return Err(ParseErrorKind::Missing(what).at(1));
+    }
+    for (y, row) in grid.iter().enumerate() {
+        if row.len() != grid[0].len() {
+            return Err(ParseErrorKind::Invalid(format!(
+                "Row is {} long, the first is {}",
+                row.len(),
+                grid[0].len()
+            ))
+            .at(y + 1));
+        }
+        if let Some(c) = row.iter().find(|c| !allowed.contains(**c)) {
+            return Err(ParseErrorKind::UnexpectedChar(*c).at(y + 1));
+        }
+    }
+
+    return Ok(grid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_name_the_failing_line() {
+        let result = lines("1\n2\nx\n4", number::<u8>);
+        assert_eq!(
+            result,
+            Err(ParseErrorKind::InvalidNumber("x".to_string()).at(3))
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Line 3: Invalid number `x`"
+        );
+        assert_eq!(lines("1\n2", number::<u8>), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn sections_know_their_line() {
+        let input = "a\nb\n\nc\n";
+        let (first, second) = input.split_once("\n\n").unwrap();
+        assert_eq!(line_of(input, first), 1);
+        assert_eq!(line_of(input, second), 4);
+        assert_eq!(line_of(input, &input[input.len()..]), 5);
+        assert_eq!(ParseErrorKind::Missing("`:`").at(2).offset(3).line, 5);
+    }
+
+    #[test]
+    fn numbers_reject_overflows() {
+        assert_eq!(
+            number::<u8>("256"),
+            Err(ParseErrorKind::InvalidNumber("256".to_string()))
+        );
+        assert_eq!(number::<i64>("-12"), Ok(-12));
+    }
+
+    #[test]
+    fn grids_are_rectangles() {
+        assert_eq!(
+            grid("ab\nba", "a grid", "ab"),
+            Ok(vec![vec!['a', 'b'], vec!['b', 'a']])
+        );
+        assert_eq!(
+            grid("\n", "a grid", "ab"),
+            Err(ParseErrorKind::Missing("a grid").at(1))
+        );
+        assert_eq!(
+            grid("ab\nbc", "a grid", "ab"),
+            Err(ParseErrorKind::UnexpectedChar('c').at(2))
+        );
+        assert!(matches!(
+            grid("ab\nb", "a grid", "ab"),
+            Err(ParseError {
+                line: 2,
+                kind: ParseErrorKind::Invalid(_)
+            })
+        ));
+    }
+}
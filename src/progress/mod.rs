@@ -0,0 +1,230 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Receives how far a long running solver got
+pub trait Progress {
+    /// Starts a task of `total` steps, `None` when the number of steps isn't known up front
+    fn start(&mut self, task: &str, total: Option<u64>);
+    fn advance(&mut self, steps: u64);
+    fn finish(&mut self);
+}
+
+/// Reporting nothing, for tests and benchmarks
+impl Progress for () {
+    fn start(&mut self, _task: &str, _total: Option<u64>) {}
+    fn advance(&mut self, _steps: u64) {}
+    fn finish(&mut self) {}
+}
+
+/// A progress bar on the terminal, or a spinner when the total isn't known
+#[derive(Debug, Default)]
+pub struct Bar {
+    bar: Option<ProgressBar>,
+}
+
+impl Progress for Bar {
+    fn start(&mut self, task: &str, total: Option<u64>) {
+        let (bar, template) = match total {
+            Some(total) => (
+                ProgressBar::new(total),
+                "{msg} {wide_bar} {pos}/{len} [{elapsed_precise}]",
+            ),
+            None => (
+                ProgressBar::new_spinner(),
+                "{spinner} {msg} {pos} [{elapsed_precise}]",
+            ),
+        };
+        bar.set_style(ProgressStyle::with_template(template).unwrap());
+        bar.set_message(task.to_string());
+
+        self.bar = Some(bar);
+    }
+
+    fn advance(&mut self, steps: u64) {
+        if let Some(bar) = &self.bar {
+            bar.inc(steps);
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish();
+        }
+    }
+}
+
+/// A JSON object per line for every event, advances are written at most once per interval
+pub struct JsonEvents<W: Write> {
+    out: W,
+    task: String,
+    position: u64,
+    every: Duration,
+    last: Instant,
+}
+
+impl<W: Write> JsonEvents<W> {
+    pub fn new(out: W) -> JsonEvents<W> {
+        JsonEvents {
+            out,
+            task: String::new(),
+            position: 0,
+            every: Duration::from_millis(100),
+            last: Instant::now(),
+        }
+    }
+
+    pub fn with_interval(self, every: Duration) -> JsonEvents<W> {
+        JsonEvents { every, ..self }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn emit(&mut self, event: &str, field: &str, value: String) {
+        let task = self.task.replace('\\', "\\\\").replace('"', "\\\"");
+
+        // Failing to report progress shouldn't fail the solver
+        let _ = writeln!(
+            self.out,
+            r#"{{"event":"{event}","task":"{task}","{field}":{value}}}"#
+        );
+        self.last = Instant::now();
+    }
+}
+
+impl<W: Write> Progress for JsonEvents<W> {
+    fn start(&mut self, task: &str, total: Option<u64>) {
+        self.task = task.to_string();
+        self.position = 0;
+
+        let total = total.map_or("null".to_string(), |t| t.to_string());
+        self.emit("start", "total", total);
+    }
+
+    fn advance(&mut self, steps: u64) {
+        self.position += steps;
+
+        if self.last.elapsed() >= self.every {
+            self.emit("advance", "position", self.position.to_string());
+        }
+    }
+
+    fn finish(&mut self) {
+        self.emit("finish", "position", self.position.to_string());
+    }
+}
+
+/// Where a task got to, as seen through [`Shared`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    pub task: String,
+    pub position: u64,
+    pub total: Option<u64>,
+    pub finished: bool,
+}
+
+/// Progress that can be read from another thread, like the TUI drawing a running solver
+#[derive(Debug, Clone, Default)]
+pub struct Shared(Arc<Mutex<Status>>);
+
+impl Shared {
+    pub fn status(&self) -> Status {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Progress for Shared {
+    fn start(&mut self, task: &str, total: Option<u64>) {
+        *self.0.lock().unwrap() = Status {
+            task: task.to_string(),
+            position: 0,
+            total,
+            finished: false,
+        };
+    }
+
+    fn advance(&mut self, steps: u64) {
+        self.0.lock().unwrap().position += steps;
+    }
+
+    fn finish(&mut self) {
+        self.0.lock().unwrap().finished = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_to(progress: &mut dyn Progress, total: u64) {
+        progress.start("Counting \"up\"", Some(total));
+        for _ in 0..total {
+            progress.advance(1);
+        }
+        progress.finish();
+    }
+
+    #[test]
+    fn json_events_are_one_per_line() {
+        let mut events = JsonEvents::new(Vec::new()).with_interval(Duration::ZERO);
+        count_to(&mut events, 2);
+
+        let out = String::from_utf8(events.into_inner()).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                r#"{"event":"start","task":"Counting \"up\"","total":2}"#,
+                r#"{"event":"advance","task":"Counting \"up\"","position":1}"#,
+                r#"{"event":"advance","task":"Counting \"up\"","position":2}"#,
+                r#"{"event":"finish","task":"Counting \"up\"","position":2}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn json_advances_are_throttled() {
+        let mut events = JsonEvents::new(Vec::new()).with_interval(Duration::from_secs(60));
+        count_to(&mut events, 1000);
+        events.start("Unknown", None);
+
+        let out = String::from_utf8(events.into_inner()).unwrap();
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.contains(r#""position":1000"#));
+        assert_eq!(
+            out.lines().last(),
+            Some(r#"{"event":"start","task":"Unknown","total":null}"#)
+        );
+    }
+
+    #[test]
+    fn shared_progress_is_seen_through_clones() {
+        let shared = Shared::default();
+        count_to(&mut shared.clone(), 5);
+
+        assert_eq!(
+            shared.status(),
+            Status {
+                task: "Counting \"up\"".to_string(),
+                position: 5,
+                total: Some(5),
+                finished: true,
+            }
+        );
+    }
+
+    #[test]
+    fn bars_only_draw_once_started() {
+        let mut bar = Bar::default();
+        bar.advance(1);
+        bar.finish();
+
+        count_to(&mut bar, 3);
+        assert!(bar.bar.is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use super::{Image, Palette};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Receives the frames of a simulation, the frame is only drawn when it is kept
+pub trait Recorder {
+    fn capture(&mut self, frame: impl FnOnce(&Palette) -> Image);
+}
+
+/// Recording nothing, for when a simulation just needs an answer
+impl Recorder for () {
+    fn capture(&mut self, _frame: impl FnOnce(&Palette) -> Image) {}
+}
+
+/// The frames of a simulation, exported as numbered PNGs or an animated PNG
+#[derive(Debug, Clone)]
+pub struct Animation {
+    palette: Palette,
+    scale: usize,
+    delay: Duration,
+    frames: Vec<Image>,
+}
+
+impl Recorder for Animation {
+    fn capture(&mut self, frame: impl FnOnce(&Palette) -> Image) {
+        let image = frame(&self.palette);
+        self.frames.push(image.scaled(self.scale));
+    }
+}
+
+impl Animation {
+    pub fn new(palette: Palette) -> Animation {
+        Animation {
+            palette,
+            scale: 1,
+            delay: Duration::from_millis(100),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Blows every captured frame up by `factor`, see [`Image::scaled`]
+    pub fn with_scale(self, factor: usize) -> Animation {
+        Animation {
+            scale: factor,
+            ..self
+        }
+    }
+
+    /// How long every frame is shown
+    pub fn with_delay(self, delay: Duration) -> Animation {
+        Animation { delay, ..self }
+    }
+
+    pub fn frames(&self) -> &[Image] {
+        &self.frames
+    }
+
+    /// Writes every frame to `frame_0000.png`, `frame_0001.png`, ... in `dir`
+    pub fn save_frames(&self, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, String> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {dir:?}: {e}"))?;
+
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let path = dir.join(format!("frame_{i:04}.png"));
+                frame.save(&path)?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// An animated PNG that loops forever, every frame has to be the same size
+    pub fn to_apng(&self) -> Result<Vec<u8>, String> {
+        let first = self.frames.first().ok_or("Animation has no frames")?;
+        if let Some(frame) = self
+            .frames
+            .iter()
+            .find(|f| (f.width, f.height) != (first.width, first.height))
+        {
+            return Err(format!(
+                "Frames should all be {}x{}, found one of {}x{}",
+                first.width, first.height, frame.width, frame.height
+            ));
+        }
+
+        let mut bytes = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut bytes, first.width as u32, first.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let delay = self.delay.as_millis().min(u16::MAX as u128) as u16;
+        encoder
+            .set_animated(self.frames.len() as u32, 0)
+            .and_then(|_| encoder.set_frame_delay(delay, 1000))
+            .and_then(|_| encoder.write_header())
+            .and_then(|mut writer| {
+                for frame in &self.frames {
+                    writer.write_image_data(&frame.pixels.concat())?;
+                }
+                writer.finish()
+            })
+            .map_err(|e| format!("Failed to encode APNG: {e}"))?;
+
+        return Ok(bytes);
+    }
+
+    /// Writes the animated PNG to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let bytes = self.to_apng()?;
+
+        std::fs::write(path, bytes).map_err(|e| format!("Failed to write {path:?}: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blink(animation: &mut impl Recorder, frames: usize) {
+        for i in 0..frames {
+            animation.capture(|palette| {
+                let color = match i % 2 {
+                    0 => palette.path,
+                    _ => palette.background,
+                };
+                Image::new(2, 1, color)
+            });
+        }
+    }
+
+    #[test]
+    fn frames_are_captured_scaled() {
+        let mut animation = Animation::new(Palette::default()).with_scale(3);
+        blink(&mut animation, 3);
+
+        assert_eq!(animation.frames().len(), 3);
+        assert_eq!(animation.frames()[0].width(), 6);
+        assert_eq!(
+            animation.frames()[1].get(5, 2),
+            Some(Palette::default().background)
+        );
+    }
+
+    #[test]
+    fn nothing_is_drawn_without_a_recorder() {
+        let mut recorder = ();
+        recorder.capture(|_| panic!("The frame shouldn't be drawn"));
+    }
+
+    #[test]
+    fn apng_has_every_frame() {
+        let mut animation = Animation::new(Palette::default());
+        blink(&mut animation, 4);
+
+        let apng = animation.to_apng().unwrap();
+        let decoder = png::Decoder::new(apng.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().animation_control().unwrap().num_frames, 4);
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let mut decoded = 0;
+        while reader.next_frame(&mut buffer).is_ok() {
+            decoded += 1;
+        }
+        assert_eq!(decoded, 4);
+    }
+
+    #[test]
+    fn apng_rejects_mismatched_frames() {
+        let mut animation = Animation::new(Palette::default());
+        assert!(animation.to_apng().is_err());
+
+        animation.capture(|p| Image::new(2, 2, p.background));
+        animation.capture(|p| Image::new(3, 2, p.background));
+        assert!(animation.to_apng().is_err());
+    }
+
+    #[test]
+    fn frames_are_numbered() {
+        let dir = std::env::temp_dir().join("aoc-2023-animation-frames");
+        let mut animation = Animation::new(Palette::light());
+        blink(&mut animation, 2);
+
+        let paths = animation.save_frames(&dir).unwrap();
+
+        assert_eq!(
+            paths,
+            [dir.join("frame_0000.png"), dir.join("frame_0001.png")]
+        );
+        assert!(paths.iter().all(|p| p.exists()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+use itertools::Itertools;
+use std::path::Path;
+
+mod animation;
+pub use animation::{Animation, Recorder};
+
+/// A color as red, green and blue components
+pub type Rgb = [u8; 3];
+
+/// The colors of the layers a grid is drawn in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub background: Rgb,
+    /// Walls, rocks and other cells that never change
+    pub foreground: Rgb,
+    pub path: Rgb,
+    pub highlight: Rgb,
+    /// The colors of the smallest and largest value of a heatmap
+    pub heat: (Rgb, Rgb),
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: [16, 16, 24],
+            foreground: [96, 96, 112],
+            path: [255, 196, 0],
+            highlight: [0, 200, 120],
+            heat: ([24, 32, 96], [240, 64, 32]),
+        }
+    }
+}
+
+impl Palette {
+    pub fn light() -> Palette {
+        Palette {
+            background: [255, 255, 255],
+            foreground: [48, 48, 48],
+            path: [220, 40, 40],
+            highlight: [40, 120, 220],
+            heat: ([255, 240, 200], [160, 0, 40]),
+        }
+    }
+
+    /// Interpolates between the heat colors, `t` runs from 0 to 1
+    pub fn heat(&self, t: f64) -> Rgb {
+        let t = t.clamp(0., 1.);
+        let (low, high) = self.heat;
+
+        [0, 1, 2].map(|i| (low[i] as f64 + (high[i] as f64 - low[i] as f64) * t).round() as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ppm,
+    Png,
+    Svg,
+}
+
+impl Format {
+    /// Picks the format matching the extension of a file name
+    pub fn from_path(path: &Path) -> Result<Format, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ppm") => Ok(Format::Ppm),
+            Some("png") => Ok(Format::Png),
+            Some("svg") => Ok(Format::Svg),
+            other => Err(format!("Unsupported image extension {other:?}")),
+        }
+    }
+}
+
+/// A grid of colored cells, one pixel per cell until it is scaled
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, color: Rgb) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![color; width * height],
+        }
+    }
+
+    /// Colors every cell of a rectangular grid
+    pub fn from_grid<T>(grid: &[Vec<T>], color: impl Fn(&T) -> Rgb) -> Image {
+        let width = grid.first().map_or(0, Vec::len);
+        let pixels = grid
+            .iter()
+            .flat_map(|row| row.iter().map(&color))
+            .collect_vec();
+
+        Image {
+            width,
+            height: grid.len(),
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// Colors a single cell, cells outside of the image are ignored
+    pub fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    /// Paints a layer of cells on top of the image, like a path or highlighted cells
+    pub fn overlay(&mut self, cells: impl IntoIterator<Item = (usize, usize)>, color: Rgb) {
+        for (x, y) in cells {
+            self.set(x, y, color);
+        }
+    }
+
+    /// Colors cells by their value, from the smallest to the largest value on the map
+    pub fn heatmap(
+        &mut self,
+        values: impl IntoIterator<Item = ((usize, usize), u64)>,
+        palette: &Palette,
+    ) {
+        let values = values.into_iter().collect_vec();
+        let Some((min, max)) = values.iter().map(|(_, v)| *v).minmax().into_option() else {
+            return;
+        };
+
+        for ((x, y), value) in values {
+            let t = if max > min {
+                (value - min) as f64 / (max - min) as f64
+            } else {
+                0.
+            };
+            self.set(x, y, palette.heat(t));
+        }
+    }
+
+    /// Blows every cell up to a square of `factor` by `factor` pixels
+    pub fn scaled(&self, factor: usize) -> Image {
+        let mut image = Image::new(self.width * factor, self.height * factor, [0, 0, 0]);
+
+        for (y, x) in (0..image.height).cartesian_product(0..image.width) {
+            image.pixels[y * image.width + x] = self.pixels[(y / factor) * self.width + x / factor];
+        }
+
+        return image;
+    }
+
+    /// A binary PPM (P6) image
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.pixels.iter().flatten());
+
+        return bytes;
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| {
+                writer.write_image_data(&self.pixels.concat())?;
+                writer.finish()
+            })
+            .map_err(|e| format!("Failed to encode PNG: {e}"))?;
+
+        return Ok(bytes);
+    }
+
+    /// An SVG with a unit square per cell, horizontal runs of one color are merged
+    pub fn to_svg(&self) -> String {
+        let rects = self
+            .pixels
+            .chunks(self.width.max(1))
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .group_by(|(_, color)| **color)
+                    .into_iter()
+                    .map(|([r, g, b], run)| {
+                        let run = run.collect_vec();
+                        format!(
+                            r##"<rect x="{}" y="{y}" width="{}" height="1" fill="#{r:02x}{g:02x}{b:02x}"/>"##,
+                            run[0].0,
+                            run.len()
+                        )
+                    })
+                    .collect_vec()
+            })
+            .join("\n");
+
+        // Cells are drawn 8 pixels wide unless the viewer scales the image
+        let (w, h) = (self.width, self.height);
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}" width="{}" height="{}" shape-rendering="crispEdges">
+{rects}
+</svg>
+"#,
+            w * 8,
+            h * 8,
+        )
+    }
+
+    pub fn encode(&self, format: Format) -> Result<Vec<u8>, String> {
+        match format {
+            Format::Ppm => Ok(self.to_ppm()),
+            Format::Png => self.to_png(),
+            Format::Svg => Ok(self.to_svg().into_bytes()),
+        }
+    }
+
+    /// Writes the image in the format matching the file extension
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let bytes = self.encode(Format::from_path(path)?)?;
+
+        std::fs::write(path, bytes).map_err(|e| format!("Failed to write {path:?}: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard() -> Image {
+        let grid = vec![vec![true, false, false], vec![false, true, true]];
+        Image::from_grid(&grid, |c| if *c { [255, 0, 0] } else { [0, 0, 255] })
+    }
+
+    #[test]
+    fn overlays_paint_over_the_grid() {
+        let mut image = checkerboard();
+        image.overlay([(1, 0), (2, 1), (5, 5)], [0, 255, 0]);
+
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(image.get(0, 0), Some([255, 0, 0]));
+        assert_eq!(image.get(1, 0), Some([0, 255, 0]));
+        assert_eq!(image.get(2, 1), Some([0, 255, 0]));
+        assert_eq!(image.get(5, 5), None);
+    }
+
+    #[test]
+    fn heatmap_spans_the_palette() {
+        let palette = Palette::default();
+        let mut image = Image::new(3, 1, palette.background);
+        image.heatmap([((0, 0), 10), ((1, 0), 20), ((2, 0), 30)], &palette);
+
+        assert_eq!(image.get(0, 0), Some(palette.heat.0));
+        assert_eq!(image.get(1, 0), Some(palette.heat(0.5)));
+        assert_eq!(image.get(2, 0), Some(palette.heat.1));
+    }
+
+    #[test]
+    fn scaling_repeats_cells() {
+        let image = checkerboard().scaled(2);
+
+        assert_eq!((image.width(), image.height()), (6, 4));
+        assert_eq!(image.get(1, 1), Some([255, 0, 0]));
+        assert_eq!(image.get(2, 1), Some([0, 0, 255]));
+        assert_eq!(image.get(5, 3), Some([255, 0, 0]));
+    }
+
+    #[test]
+    fn ppm_has_header_and_pixels() {
+        let ppm = checkerboard().to_ppm();
+
+        assert!(ppm.starts_with(b"P6\n3 2\n255\n"));
+        assert_eq!(ppm.len(), "P6\n3 2\n255\n".len() + 3 * 2 * 3);
+    }
+
+    #[test]
+    fn png_decodes_to_the_same_pixels() {
+        let image = checkerboard();
+        let png = image.to_png().unwrap();
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(&buffer[..info.buffer_size()], image.pixels.concat());
+    }
+
+    #[test]
+    fn svg_merges_runs_of_a_color() {
+        let svg = checkerboard().to_svg();
+
+        assert!(svg.contains(r#"viewBox="0 0 3 2""#));
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert!(svg.contains(r##"<rect x="1" y="0" width="2" height="1" fill="#0000ff"/>"##));
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(Format::from_path(Path::new("a/b.png")), Ok(Format::Png));
+        assert_eq!(Format::from_path(Path::new("b.svg")), Ok(Format::Svg));
+        assert_eq!(Format::from_path(Path::new("b.ppm")), Ok(Format::Ppm));
+        assert!(Format::from_path(Path::new("b.gif")).is_err());
+    }
+}
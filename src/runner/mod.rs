@@ -0,0 +1,583 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use tracing::info_span;
+
+use crate::{
+    cancel::{TimedOut, Token},
+    day1, day10, day11, day12, day13, day14, day15, day16, day17, day18, day19, day2, day20, day21,
+    day22, day23, day24, day25, day3, day4, day5, day6, day7, day8, day9, parallel,
+    progress::{Bar, JsonEvents, Progress},
+};
+
+pub mod tui;
+
+/// Solves one part of a day, returning the answer as text, or why there is none
+pub type Solver = fn(&str, &mut dyn Progress, &Token) -> Result<String, Failure>;
+
+/// A text rendering of a day's puzzle, like the pipe maze of day 10
+pub struct View {
+    pub name: &'static str,
+    pub draw: fn(&str) -> String,
+}
+
+pub struct Day {
+    pub day: usize,
+    pub parts: [Option<Solver>; 2],
+    pub views: Vec<View>,
+}
+
+impl Day {
+    fn new(day: usize, part1: Option<Solver>, part2: Option<Solver>) -> Day {
+        Day {
+            day,
+            parts: [part1, part2],
+            views: vec![],
+        }
+    }
+
+    fn with_view(mut self, name: &'static str, draw: fn(&str) -> String) -> Day {
+        self.views.push(View { name, draw });
+        self
+    }
+
+    pub fn input_path(&self) -> PathBuf {
+        PathBuf::from(format!("src/day{}/input.txt", self.day))
+    }
+
+    pub fn input(&self) -> Result<String, String> {
+        let path = self.input_path();
+        std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {path:?}: {e}"))
+    }
+}
+
+/// Every day of the calendar, with the parameters `process` uses for the real input.
+/// Every part reports its progress and checks its token between steps of its main loop
+pub fn days() -> Vec<Day> {
+    vec![
+        Day::new(
+            1,
+            Some(|i, p, c| Ok(day1::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day1::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            2,
+            Some(|i, p, c| Ok(day2::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day2::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            3,
+            Some(|i, p, c| Ok(day3::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day3::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            4,
+            Some(|i, p, c| Ok(day4::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day4::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            5,
+            Some(|i, p, c| Ok(day5::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| {
+                let answer = day5::part2_with_progress(i, p, c)?.map_err(Failure::Unsolved)?;
+                Ok(answer.to_string())
+            }),
+        ),
+        Day::new(
+            6,
+            Some(|i, p, c| Ok(day6::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day6::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            7,
+            Some(|i, p, c| Ok(day7::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day7::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            8,
+            Some(|i, p, c| Ok(day8::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day8::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            9,
+            Some(|i, p, c| Ok(day9::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day9::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            10,
+            Some(|i, p, c| Ok(day10::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day10::part2_with_progress(i, p, c)?.to_string())),
+        )
+        .with_view("loop", day10::ascii),
+        Day::new(
+            11,
+            Some(|i, p, c| Ok(day11::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day11::part2_with_progress(i, 1_000_000, p, c)?.to_string())),
+        ),
+        Day::new(
+            12,
+            Some(|i, p, c| Ok(day12::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day12::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            13,
+            Some(|i, p, c| Ok(day13::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day13::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            14,
+            Some(|i, p, c| Ok(day14::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day14::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            15,
+            Some(|i, p, c| Ok(day15::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day15::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            16,
+            Some(|i, p, c| Ok(day16::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day16::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            17,
+            Some(|i, p, c| Ok(day17::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day17::part2_with_progress(i, p, c)?.to_string())),
+        )
+        .with_view("path", day17::ascii),
+        Day::new(
+            18,
+            Some(|i, p, c| Ok(day18::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day18::part2_with_progress(i, p, c)?.to_string())),
+        )
+        .with_view("lagoon", day18::ascii),
+        Day::new(
+            19,
+            Some(|i, p, c| Ok(day19::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day19::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            20,
+            Some(|i, p, c| Ok(day20::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day20::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            21,
+            Some(|i, p, c| Ok(day21::part1_with_progress(i, 64, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day21::part2_with_progress(i, 26501365, p, c)?.to_string())),
+        )
+        .with_view("distances", day21::ascii),
+        Day::new(
+            22,
+            Some(|i, p, c| Ok(day22::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day22::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            23,
+            Some(|i, p, c| Ok(day23::part1_with_progress(i, p, c)?.to_string())),
+            Some(|i, p, c| Ok(day23::part2_with_progress(i, p, c)?.to_string())),
+        )
+        .with_view("hike", day23::ascii),
+        Day::new(
+            24,
+            Some(|i, p, c| {
+                let answer = day24::part1_with_progress(i, 200000000000000, 400000000000000, p, c)?;
+                Ok(answer.to_string())
+            }),
+            Some(|i, p, c| Ok(day24::part2_with_progress(i, p, c)?.to_string())),
+        ),
+        Day::new(
+            25,
+            Some(|i, p, c| Ok(day25::part1_with_progress(i, p, c)?.to_string())),
+            None,
+        ),
+    ]
+}
+
+/// Why a part has no answer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    Panicked(String),
+    TimedOut(TimedOut),
+    /// The puzzle has no answer for this input, like day 8 ghosts that never meet or a day 5
+    /// almanac whose maps don't connect
+    Unsolved(String),
+}
+
+impl From<TimedOut> for Failure {
+    fn from(timed_out: TimedOut) -> Failure {
+        Failure::TimedOut(timed_out)
+    }
+}
+
+impl From<day1::Error> for Failure {
+    fn from(error: day1::Error) -> Failure {
+        match error {
+            day1::Error::TimedOut(timed_out) => Failure::TimedOut(timed_out),
+            error => Failure::Unsolved(error.to_string()),
+        }
+    }
+}
+
+impl From<day4::Error> for Failure {
+    fn from(error: day4::Error) -> Failure {
+        match error {
+            day4::Error::TimedOut(timed_out) => Failure::TimedOut(timed_out),
+            error => Failure::Unsolved(error.to_string()),
+        }
+    }
+}
+
+impl From<day8::Error> for Failure {
+    fn from(error: day8::Error) -> Failure {
+        match error {
+            day8::Error::TimedOut(timed_out) => Failure::TimedOut(timed_out),
+            error => Failure::Unsolved(error.to_string()),
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Panicked(message) => write!(f, "Panicked: {message}"),
+            Failure::TimedOut(timed_out) => write!(f, "{timed_out}"),
+            Failure::Unsolved(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// The answer to a part, or why there is none, and how long it took
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub answer: Result<String, Failure>,
+    pub elapsed: Duration,
+}
+
+impl Run {
+    pub fn solve(solver: Solver, input: &str, progress: &mut dyn Progress, cancel: &Token) -> Run {
+        let now = Instant::now();
+        let answer = panic::catch_unwind(AssertUnwindSafe(|| solver(input, progress, cancel)))
+            .map_err(|e| {
+                Failure::Panicked(
+                    e.downcast_ref::<String>()
+                        .cloned()
+                        .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                        .unwrap_or_default(),
+                )
+            })
+            .and_then(|answer| answer);
+
+        Run {
+            answer,
+            elapsed: now.elapsed(),
+        }
+    }
+}
+
+/// The last run of every part, by day and part
+pub type Answers = BTreeMap<(usize, usize), Run>;
+
+/// Reads the answers saved by [`save_answers`], a missing file has no answers
+pub fn load_answers(path: &Path) -> Answers {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Answers::new();
+    };
+
+    text.lines()
+        .filter_map(|line| {
+            let (day, part, micros, answer) = line.splitn(4, '\t').collect_tuple()?;
+            let run = Run {
+                answer: Ok(answer.to_string()),
+                elapsed: Duration::from_micros(micros.parse().ok()?),
+            };
+            Some(((day.parse().ok()?, part.parse().ok()?), run))
+        })
+        .collect()
+}
+
+/// Saves the answers as `day\tpart\tmicroseconds\tanswer` lines, failures aren't kept
+pub fn save_answers(path: &Path, answers: &Answers) -> Result<(), String> {
+    let text = answers
+        .iter()
+        .filter_map(|((day, part), run)| {
+            let answer = run.answer.as_ref().ok()?;
+            Some(format!(
+                "{day}\t{part}\t{}\t{answer}\n",
+                run.elapsed.as_micros()
+            ))
+        })
+        .join("");
+
+    std::fs::write(path, text).map_err(|e| format!("Failed to write {path:?}: {e}"))
+}
+
+/// The value following `name` on the command line, like `--day 23`
+pub fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|a| a == name)?;
+    args.get(i + 1).map(String::as_str)
+}
+
+/// A time budget in seconds, like `--timeout 2.5`
+pub fn budget(timeout: Option<&str>) -> Result<Option<Duration>, String> {
+    timeout
+        .map(|t| {
+            t.parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or(format!("Invalid timeout `{t}`, expected seconds"))
+        })
+        .transpose()
+}
+
+/// A fresh token for every part, so each one gets the whole budget
+fn token(budget: Option<Duration>) -> Token {
+    match budget {
+        Some(budget) => Token::new().with_budget(budget),
+        None => Token::new(),
+    }
+}
+
+/// Solves a day on its input, or only one part of it, reporting progress as a `bar`,
+/// as `json` events on stderr or not at all with `none`. Parts stop once `budget` runs out
+pub fn solve(
+    day: &str,
+    part: Option<&str>,
+    progress: &str,
+    budget: Option<Duration>,
+) -> Result<(), String> {
+    let number: usize = day
+        .parse()
+        .map_err(|e| format!("Invalid day `{day}`: {e}"))?;
+    let part = part
+        .map(|p| {
+            p.parse::<usize>()
+                .map_err(|e| format!("Invalid part `{p}`: {e}"))
+        })
+        .transpose()?;
+
+    let mut progress: Box<dyn Progress> = match progress {
+        "bar" => Box::new(Bar::default()),
+        "json" => Box::new(JsonEvents::new(std::io::stderr())),
+        "none" => Box::new(()),
+        other => return Err(format!("Unknown progress `{other}`, use bar, json or none")),
+    };
+
+    let days = days();
+    let day = days
+        .iter()
+        .find(|d| d.day == number)
+        .ok_or(format!("There is no day {number}"))?;
+    let solvers = (1..=2)
+        .filter(|p| part.is_none() || part == Some(*p))
+        .filter_map(|p| Some((p, day.parts[p - 1]?)))
+        .collect_vec();
+    if solvers.is_empty() {
+        return Err(format!("Day {number} has no part {}", part.unwrap_or(0)));
+    }
+
+    let input = day.input()?;
+    for (part, solver) in solvers {
+        let run = info_span!("solve", day = number, part)
+            .in_scope(|| Run::solve(solver, &input, progress.as_mut(), &token(budget)));
+        report(number, part, &run);
+    }
+
+    Ok(())
+}
+
+fn report(day: usize, part: usize, run: &Run) {
+    match &run.answer {
+        Ok(answer) => println!("Day {day} part {part}: {answer} ({:.2?})", run.elapsed),
+        Err(e) => println!("Day {day} part {part}: {e}"),
+    }
+}
+
+/// Solves both parts of a day one after the other, failing when its input is missing
+fn solve_day(day: &Day, budget: Option<Duration>) -> Result<Vec<(usize, Run)>, String> {
+    let input = day.input()?;
+
+    let runs = (1..=2)
+        .filter_map(|part| Some((part, day.parts[part - 1]?)))
+        .map(|(part, solver)| {
+            let run = info_span!("solve", day = day.day, part)
+                .in_scope(|| Run::solve(solver, &input, &mut (), &token(budget)));
+            (part, run)
+        })
+        .collect();
+
+    return Ok(runs);
+}
+
+/// Solves every day on its input, the days run at the same time with the `parallel` feature
+pub fn solve_all(budget: Option<Duration>) -> Result<(), String> {
+    let days = days();
+    let now = Instant::now();
+    let results = parallel::map(&days, |day| solve_day(day, budget));
+    let elapsed = now.elapsed();
+
+    let mut failed = 0;
+    for (day, result) in days.iter().zip(results) {
+        match result {
+            Ok(runs) => {
+                for (part, run) in runs {
+                    failed += run.answer.is_err() as usize;
+                    report(day.day, part, &run);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("Day {}: {e}", day.day);
+            }
+        }
+    }
+    println!(
+        "Solved every day in {elapsed:.2?} on {} threads",
+        parallel::threads()
+    );
+
+    if failed > 0 {
+        return Err(format!("{failed} days or parts failed"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_day_is_registered() {
+        let days = days();
+
+        assert_eq!(
+            days.iter().map(|d| d.day).collect_vec(),
+            (1..=25).collect_vec()
+        );
+        assert_eq!(days.iter().flat_map(|d| d.parts).flatten().count(), 49);
+        assert_eq!(days[9].input_path(), PathBuf::from("src/day10/input.txt"));
+    }
+
+    #[test]
+    fn flags_take_the_next_argument() {
+        let args = ["aoc", "--day", "23", "--part"].map(String::from);
+
+        assert_eq!(flag(&args, "--day"), Some("23"));
+        assert_eq!(flag(&args, "--part"), None);
+        assert_eq!(flag(&args, "--progress"), None);
+    }
+
+    #[test]
+    fn solve_rejects_unknown_days_and_parts() {
+        assert!(solve("26", None, "none", None).is_err());
+        assert!(solve("x", None, "none", None).is_err());
+        assert!(solve("25", Some("2"), "none", None).is_err());
+        assert!(solve("25", Some("1"), "dots", None).is_err());
+    }
+
+    #[test]
+    fn days_solve_both_parts() {
+        let runs = solve_day(&days()[0], None).unwrap();
+
+        assert_eq!(runs.iter().map(|(part, _)| *part).collect_vec(), [1, 2]);
+        assert!(runs.iter().all(|(_, run)| run.answer.is_ok()));
+        assert!(solve_day(&Day::new(26, None, None), None).is_err());
+    }
+
+    #[test]
+    fn runs_catch_panics() {
+        let solver: Solver = |i, _, _| Ok(day1::part1(i)?.to_string());
+        let run = Run::solve(solver, "a1b2c3", &mut (), &Token::new());
+        assert_eq!(run.answer, Ok("13".to_string()));
+
+        let run = Run::solve(|_, _, _| panic!("No answer"), "", &mut (), &Token::new());
+        assert_eq!(run.answer, Err(Failure::Panicked("No answer".to_string())));
+    }
+
+    #[test]
+    fn runs_stop_when_the_budget_runs_out() {
+        let cancel = Token::new().with_budget(Duration::ZERO);
+        let run = Run::solve(days()[13].parts[1].unwrap(), "O.\n.#", &mut (), &cancel);
+
+        let Err(Failure::TimedOut(timed_out)) = run.answer else {
+            panic!("Expected a time out, got {:?}", run.answer);
+        };
+        assert_eq!(timed_out.partial, "0 spin cycles without a repeat");
+    }
+
+    #[test]
+    fn runs_report_puzzles_without_an_answer() {
+        // The first ghost ends on odd steps, the second on even ones
+        let input = "L\n\n11A = (11Z, XXX)\n11Z = (11A, XXX)\n22A = (22B, XXX)\n22B = (22Z, XXX)\n\
+                     22Z = (22B, XXX)\nXXX = (XXX, XXX)";
+        let run = Run::solve(days()[7].parts[1].unwrap(), input, &mut (), &Token::new());
+
+        assert_eq!(
+            run.answer,
+            Err(Failure::Unsolved(
+                "The ghosts never stand on end nodes together".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn every_part_checks_the_budget() {
+        let cancel = Token::new().with_budget(Duration::ZERO);
+
+        for day in days() {
+            let input = day.input().unwrap();
+            for (part, solver) in day.parts.iter().enumerate() {
+                let Some(solver) = solver else { continue };
+                let run = Run::solve(*solver, &input, &mut (), &cancel);
+                assert!(
+                    matches!(run.answer, Err(Failure::TimedOut(_))),
+                    "day {} part {}",
+                    day.day,
+                    part + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn budgets_are_in_seconds() {
+        assert_eq!(budget(None), Ok(None));
+        assert_eq!(budget(Some("2.5")), Ok(Some(Duration::from_millis(2500))));
+        assert!(budget(Some("-1")).is_err());
+        assert!(budget(Some("soon")).is_err());
+    }
+
+    #[test]
+    fn answers_round_trip() {
+        let path = std::env::temp_dir().join("aoc-2023-answers.tsv");
+        let answers = Answers::from([
+            (
+                (1, 1),
+                Run {
+                    answer: Ok("54630".to_string()),
+                    elapsed: Duration::from_micros(120),
+                },
+            ),
+            (
+                (1, 2),
+                Run {
+                    answer: Err(Failure::Panicked("Oops".to_string())),
+                    elapsed: Duration::from_micros(7),
+                },
+            ),
+        ]);
+
+        save_answers(&path, &answers).unwrap();
+        let loaded = load_answers(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Answers::from([((1, 1), answers[&(1, 1)].clone())]));
+        assert!(load_answers(&path).is_empty());
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    panic,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use console::{Key, Term};
+use itertools::Itertools;
+use tracing::info_span;
+
+use super::{days, load_answers, save_answers, token, Answers, Day, Failure, Run};
+use crate::progress::{Shared, Status};
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+enum Screen {
+    Days,
+    /// A scrollable text rendering, `top` and `left` are the first line and column shown
+    View {
+        day: usize,
+        view: usize,
+        lines: Vec<String>,
+        top: usize,
+        left: usize,
+    },
+}
+
+/// What the terminal loop has to do after a key press
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Nothing,
+    Quit,
+    Run { day: usize, part: usize },
+    Show { day: usize, view: usize },
+}
+
+/// The state of the TUI, kept apart from the terminal so it can be tested
+pub struct App {
+    days: Vec<Day>,
+    answers: Answers,
+    selected: usize,
+    screen: Screen,
+    /// The day and part being solved, since when and how far it got
+    running: Option<(usize, usize, Instant, Shared)>,
+    /// How many frames were drawn while solving, turning the spinner
+    frames: usize,
+}
+
+impl App {
+    pub fn new(days: Vec<Day>, answers: Answers) -> App {
+        App {
+            days,
+            answers,
+            selected: 0,
+            screen: Screen::Days,
+            running: None,
+            frames: 0,
+        }
+    }
+
+    fn day(&self, day: usize) -> Option<&Day> {
+        self.days.iter().find(|d| d.day == day)
+    }
+
+    pub fn handle(&mut self, key: Key) -> Command {
+        let day = &self.days[self.selected];
+
+        match &mut self.screen {
+            Screen::Days => match key {
+                Key::ArrowUp | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+                Key::ArrowDown | Key::Char('j') => {
+                    self.selected = (self.selected + 1).min(self.days.len() - 1)
+                }
+                Key::Char(c @ ('1' | '2')) => {
+                    let part = c.to_digit(10).unwrap() as usize;
+                    if day.parts[part - 1].is_some() {
+                        return Command::Run { day: day.day, part };
+                    }
+                }
+                Key::Char('v') if !day.views.is_empty() => {
+                    return Command::Show {
+                        day: day.day,
+                        view: 0,
+                    }
+                }
+                Key::Char('q') | Key::Escape => return Command::Quit,
+                _ => (),
+            },
+            Screen::View {
+                day,
+                view,
+                lines,
+                top,
+                left,
+            } => match key {
+                Key::ArrowUp | Key::Char('k') => *top = top.saturating_sub(1),
+                Key::ArrowDown | Key::Char('j') => *top = (*top + 1).min(lines.len()),
+                Key::PageUp => *top = top.saturating_sub(20),
+                Key::PageDown => *top = (*top + 20).min(lines.len()),
+                Key::Home => *top = 0,
+                Key::ArrowLeft | Key::Char('h') => *left = left.saturating_sub(8),
+                Key::ArrowRight | Key::Char('l') => *left += 8,
+                // Cycles through the views of the day
+                Key::Char('v') => {
+                    let views = self
+                        .days
+                        .iter()
+                        .find(|d| d.day == *day)
+                        .unwrap()
+                        .views
+                        .len();
+                    return Command::Show {
+                        day: *day,
+                        view: (*view + 1) % views,
+                    };
+                }
+                Key::Char('q') | Key::Escape => self.screen = Screen::Days,
+                _ => (),
+            },
+        }
+
+        return Command::Nothing;
+    }
+
+    /// Marks a part as running, the solver reports its progress to the returned handle
+    pub fn start(&mut self, day: usize, part: usize) -> Shared {
+        let progress = Shared::default();
+        self.running = Some((day, part, Instant::now(), progress.clone()));
+        self.frames = 0;
+
+        return progress;
+    }
+
+    pub fn tick(&mut self) {
+        self.frames += 1;
+    }
+
+    pub fn finish(&mut self, day: usize, part: usize, run: Run) {
+        self.running = None;
+        self.answers.insert((day, part), run);
+    }
+
+    pub fn show(&mut self, day: usize, view: usize, text: &str) {
+        self.screen = Screen::View {
+            day,
+            view,
+            lines: text.lines().map(str::to_string).collect(),
+            top: 0,
+            left: 0,
+        };
+    }
+
+    fn part_cell(&self, day: &Day, part: usize) -> String {
+        let result = match (day.parts[part - 1], self.answers.get(&(day.day, part))) {
+            (None, _) => "n/a".to_string(),
+            (Some(_), None) => "-".to_string(),
+            (Some(_), Some(run)) => match &run.answer {
+                Ok(answer) => format!("{answer} ({:.2?})", run.elapsed),
+                Err(Failure::Panicked(_)) => "panicked".to_string(),
+                Err(Failure::TimedOut(_)) => format!("timed out ({:.2?})", run.elapsed),
+                Err(Failure::Unsolved(reason)) => reason.clone(),
+            },
+        };
+
+        format!("{part}: {result}")
+    }
+
+    /// What the screen shows, cut to the size of the terminal
+    pub fn lines(&self, width: usize, height: usize) -> Vec<String> {
+        let mut lines = match &self.screen {
+            Screen::Days => {
+                let header = "Advent of Code 2023   j/k select   1/2 run a part   v view   q quit";
+                let rows = height.saturating_sub(2).max(1);
+                let first = (self.selected + 1).saturating_sub(rows);
+
+                let days = self
+                    .days
+                    .iter()
+                    .enumerate()
+                    .skip(first)
+                    .take(rows)
+                    .map(|(i, day)| {
+                        let marker = if i == self.selected { '>' } else { ' ' };
+                        let views = day.views.iter().map(|v| v.name).join(", ");
+                        format!(
+                            "{marker} Day {:>2}   {:<32}{:<32}{views}",
+                            day.day,
+                            self.part_cell(day, 1),
+                            self.part_cell(day, 2)
+                        )
+                    });
+
+                [header.to_string()].into_iter().chain(days).collect_vec()
+            }
+            Screen::View {
+                day,
+                view,
+                lines,
+                top,
+                left,
+            } => {
+                let name = self.day(*day).unwrap().views[*view].name;
+                let header = format!(
+                    "Day {day}: {name}   j/k/h/l scroll   v next view   q back   line {}/{}",
+                    top + 1,
+                    lines.len()
+                );
+
+                let text = lines
+                    .iter()
+                    .skip(*top)
+                    .take(height.saturating_sub(2))
+                    .map(|line| line.chars().skip(*left).collect::<String>());
+
+                [header].into_iter().chain(text).collect_vec()
+            }
+        };
+
+        if let Some((day, part, since, progress)) = &self.running {
+            let elapsed = since.elapsed();
+            let spinner = SPINNER[self.frames % SPINNER.len()];
+            let status = match progress.status() {
+                Status { task, .. } if task.is_empty() => String::new(),
+                Status {
+                    task,
+                    position,
+                    total: Some(total),
+                    ..
+                } => format!("   {task}: {position}/{total}"),
+                Status { task, position, .. } => format!("   {task}: {position}"),
+            };
+
+            lines.push(format!(
+                "{spinner} Running day {day} part {part}... {elapsed:.1?}{status}"
+            ));
+        }
+
+        lines
+            .into_iter()
+            .map(|line| line.chars().take(width).collect())
+            .collect()
+    }
+}
+
+fn draw(term: &Term, app: &App) -> std::io::Result<()> {
+    let (height, width) = term.size();
+
+    term.move_cursor_to(0, 0)?;
+    for line in app.lines(width as usize, height as usize) {
+        term.clear_line()?;
+        term.write_line(&line)?;
+    }
+    term.clear_to_end_of_screen()
+}
+
+/// Solves a part on another thread, redrawing the elapsed time until it is done
+fn solve(
+    term: &Term,
+    app: &mut App,
+    day: usize,
+    part: usize,
+    budget: Option<Duration>,
+) -> Result<(), String> {
+    let solver = app.day(day).unwrap().parts[part - 1].unwrap();
+    let input = app.day(day).unwrap().input()?;
+
+    let mut progress = app.start(day, part);
+    let cancel = token(budget);
+    let span = info_span!("solve", day, part);
+    let handle =
+        thread::spawn(move || span.in_scope(|| Run::solve(solver, &input, &mut progress, &cancel)));
+    while !handle.is_finished() {
+        draw(term, app).map_err(|e| e.to_string())?;
+        thread::sleep(Duration::from_millis(100));
+        app.tick();
+    }
+
+    app.finish(day, part, handle.join().unwrap());
+    Ok(())
+}
+
+fn show(app: &mut App, day: usize, view: usize) -> Result<(), String> {
+    let input = app.day(day).unwrap().input()?;
+    let draw = app.day(day).unwrap().views[view].draw;
+
+    let text = panic::catch_unwind(|| draw(&input))
+        .unwrap_or_else(|_| format!("Drawing day {day} panicked"));
+    app.show(day, view, &text);
+    Ok(())
+}
+
+fn browse(
+    term: &Term,
+    app: &mut App,
+    answers: &Path,
+    budget: Option<Duration>,
+) -> Result<(), String> {
+    loop {
+        draw(term, app).map_err(|e| e.to_string())?;
+
+        match app.handle(term.read_key().map_err(|e| e.to_string())?) {
+            Command::Nothing => (),
+            Command::Quit => return Ok(()),
+            Command::Run { day, part } => {
+                solve(term, app, day, part, budget)?;
+                save_answers(answers, &app.answers)?;
+            }
+            Command::Show { day, view } => show(app, day, view)?,
+        }
+    }
+}
+
+/// Browses the days in the terminal until `q` is pressed, answers are kept in `answers`.
+/// Parts that run longer than `budget` are stopped
+pub fn run(answers: &Path, budget: Option<Duration>) -> Result<(), String> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        return Err("The TUI needs an interactive terminal".to_string());
+    }
+
+    let mut app = App::new(days(), load_answers(answers));
+
+    // Panicking solvers show up next to their day instead of garbling the screen
+    let _restore = Restore {
+        term: &term,
+        hook: Some(panic::take_hook()),
+    };
+    panic::set_hook(Box::new(|_| {}));
+    term.hide_cursor().map_err(|e| e.to_string())?;
+    term.clear_screen().map_err(|e| e.to_string())?;
+
+    return browse(&term, &mut app, answers, budget);
+}
+
+type PanicHook = Box<dyn Fn(&panic::PanicHookInfo) + Sync + Send>;
+
+/// Puts the panic hook and the cursor back however the TUI ends, even on an early error
+struct Restore<'a> {
+    term: &'a Term,
+    hook: Option<PanicHook>,
+}
+
+impl Drop for Restore<'_> {
+    fn drop(&mut self) {
+        if let Some(hook) = self.hook.take() {
+            panic::set_hook(hook);
+        }
+        let _ = self.term.show_cursor();
+        let _ = self.term.clear_screen();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cancel::TimedOut, progress::Progress};
+
+    fn app() -> App {
+        let answers = Answers::from([(
+            (1, 1),
+            Run {
+                answer: Ok("54630".to_string()),
+                elapsed: Duration::from_millis(2),
+            },
+        )]);
+        App::new(days(), answers)
+    }
+
+    #[test]
+    fn days_list_answers() {
+        let app = app();
+        let lines = app.lines(200, 40);
+
+        assert_eq!(lines.len(), 26);
+        assert!(lines[1].starts_with("> Day  1   1: 54630 (2.00ms)"));
+        assert!(lines[1].contains("2: -"));
+        assert!(lines[9].contains("1: -"));
+        assert!(lines[25].contains("2: n/a"));
+        assert!(lines[10].ends_with("loop"));
+    }
+
+    #[test]
+    fn selection_scrolls_the_list() {
+        let mut app = app();
+        for _ in 0..30 {
+            app.handle(Key::ArrowDown);
+        }
+
+        let lines = app.lines(40, 12);
+        assert_eq!(lines.len(), 11);
+        assert!(lines.iter().all(|l| l.chars().count() <= 40));
+        assert!(lines[10].starts_with("> Day 25"));
+        assert_eq!(app.handle(Key::Char('2')), Command::Nothing);
+        assert_eq!(
+            app.handle(Key::Char('1')),
+            Command::Run { day: 25, part: 1 }
+        );
+    }
+
+    #[test]
+    fn views_scroll() {
+        let mut app = app();
+        assert_eq!(app.handle(Key::Char('v')), Command::Nothing);
+
+        for _ in 0..9 {
+            app.handle(Key::Char('j'));
+        }
+        assert_eq!(
+            app.handle(Key::Char('v')),
+            Command::Show { day: 10, view: 0 }
+        );
+
+        app.show(10, 0, "abcdefghijkl\nmnopqrstuvwx\nyz");
+        app.handle(Key::ArrowDown);
+        app.handle(Key::ArrowRight);
+        assert_eq!(app.lines(80, 10)[1..], ["uvwx", ""]);
+        assert_eq!(
+            app.handle(Key::Char('v')),
+            Command::Show { day: 10, view: 0 }
+        );
+
+        app.handle(Key::Escape);
+        assert!(app.lines(80, 40)[10].starts_with("> Day 10"));
+        assert_eq!(app.handle(Key::Escape), Command::Quit);
+    }
+
+    #[test]
+    fn running_parts_show_progress() {
+        let mut app = app();
+        let mut progress = app.start(23, 2);
+        assert!(app.lines(80, 40)[26].starts_with("| Running day 23 part 2... "));
+        app.tick();
+        assert!(app.lines(80, 40)[26].starts_with("/ Running day 23 part 2... "));
+
+        progress.start("Hikes", None);
+        progress.advance(12);
+        assert!(app.lines(80, 40)[26].ends_with("   Hikes: 12"));
+        progress.start("Paths", Some(40));
+        progress.advance(2);
+        assert!(app.lines(80, 40)[26].ends_with("   Paths: 2/40"));
+
+        let run = Run {
+            answer: Err(Failure::Panicked("Oops".to_string())),
+            elapsed: Duration::from_secs(1),
+        };
+        app.finish(23, 2, run);
+        let lines = app.lines(200, 40);
+        assert_eq!(lines.len(), 26);
+        assert!(lines[23].contains("2: panicked"));
+
+        let run = Run {
+            answer: Err(Failure::TimedOut(TimedOut {
+                after: Duration::from_secs(5),
+                partial: "12 spin cycles without a repeat".to_string(),
+            })),
+            elapsed: Duration::from_secs(5),
+        };
+        app.finish(14, 2, run);
+        assert!(app.lines(200, 40)[14].contains("2: timed out (5.00s)"));
+
+        let run = Run {
+            answer: Err(Failure::Unsolved(
+                "The ghosts never stand on end nodes together".to_string(),
+            )),
+            elapsed: Duration::from_millis(3),
+        };
+        app.finish(8, 2, run);
+        assert!(app.lines(200, 40)[8].contains("2: The ghosts never stand on end nodes together"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use tracing_flame::{FlameLayer, FlushGuard};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// The environment variable with the spans and events to keep, like `advent_of_code_2023=trace`
+pub const FILTER_ENV: &str = "AOC_TRACE";
+
+/// What is kept when `AOC_TRACE` isn't set: the spans and debug events of every day
+const DEFAULT_FILTER: &str = concat!(env!("CARGO_CRATE_NAME"), "=debug");
+
+/// Writes the span timings until it is dropped, keep it around until the solvers are done
+pub struct Guard {
+    _flame: Option<FlushGuard<BufWriter<File>>>,
+}
+
+fn filter(directives: Option<String>) -> Result<EnvFilter, String> {
+    let directives = directives.unwrap_or(DEFAULT_FILTER.to_string());
+    EnvFilter::try_new(&directives).map_err(|e| format!("Invalid trace filter `{directives}`: {e}"))
+}
+
+/// Logs the solvers' events to stderr when `events` is set, and writes the time spent in every
+/// span to `flame` as folded stacks, which `inferno-flamegraph` draws as a flamegraph
+pub fn init(events: bool, flame: Option<&Path>) -> Result<Guard, String> {
+    let filter = filter(std::env::var(FILTER_ENV).ok())?;
+
+    let (flame, guard) = match flame {
+        Some(path) => {
+            let (layer, guard) = FlameLayer::with_file(path)
+                .map_err(|e| format!("Failed to create {path:?}: {e}"))?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let events = events.then(|| fmt::layer().with_writer(std::io::stderr));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(events)
+        .with(flame)
+        .try_init()
+        .map_err(|e| format!("Failed to start tracing: {e}"))?;
+
+    return Ok(Guard { _flame: guard });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day14;
+
+    #[test]
+    fn filters_default_to_every_day() {
+        assert_eq!(
+            filter(None).unwrap().to_string(),
+            "advent_of_code_2023=debug"
+        );
+        assert!(filter(Some("advent_of_code_2023::day14=trace".to_string())).is_ok());
+        assert!(filter(Some("day14=[".to_string())).is_err());
+    }
+
+    #[test]
+    fn spans_are_written_as_folded_stacks() {
+        let path = std::env::temp_dir().join("aoc-2023-trace.folded");
+        let (layer, guard) = FlameLayer::with_file(&path).unwrap();
+        let subscriber = tracing_subscriber::registry()
+            .with(filter(None).unwrap())
+            .with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            day14::part1("O.\n.#");
+        });
+        guard.flush().unwrap();
+        drop(guard);
+
+        let folded = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let part1 = folded.lines().find(|l| l.contains("day14::part1")).unwrap();
+        let (_stack, nanos) = part1.rsplit_once(' ').unwrap();
+        assert!(nanos.parse::<u64>().is_ok());
+    }
+}